@@ -0,0 +1,147 @@
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web::dev::HttpResponseBuilder;
+use actix_web::http::{header, HeaderName, StatusCode};
+use chrono::{DateTime, Utc};
+
+use crate::config::CacheConfig;
+
+/// IMF-fixdate of RFC 7231, the format of `Last-Modified` and `If-Modified-Since`
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// The `Cache-Control` of the read routes, the defaults when the app has none.
+pub fn config(req: &HttpRequest) -> CacheConfig {
+    req.app_data::<CacheConfig>().cloned().unwrap_or_default()
+}
+
+/// What a client needs to revalidate an answer it cached: a strong `ETag`, the `Last-Modified`
+/// when the route knows it and the `Cache-Control` of the route. Sent with 200 and 304 alike.
+pub struct Validators {
+    etag: String,
+    last_modified: Option<DateTime<Utc>>,
+    cache_control: String,
+    vary: Option<HeaderName>,
+}
+
+impl Validators {
+    /// `etag` is the quoted tag, like `"3"`
+    pub fn new(etag: String, cache_control: String) -> Self {
+        Self { etag, last_modified: None, cache_control, vary: None }
+    }
+
+    pub fn last_modified(mut self, last_modified: DateTime<Utc>) -> Self {
+        self.last_modified = Some(last_modified);
+        self
+    }
+
+    /// a request header the answer depends on besides the URL
+    pub fn vary(mut self, header: HeaderName) -> Self {
+        self.vary = Some(header);
+        self
+    }
+
+    /// 304 when the client has the current answer, see `is_fresh`
+    pub fn not_modified(&self, req: &HttpRequest) -> Option<HttpResponse> {
+        if self.is_fresh(req) {
+            Some(self.build(StatusCode::NOT_MODIFIED).finish())
+        } else {
+            None
+        }
+    }
+
+    /// 200 with the validators, the body is up to the route
+    pub fn ok(&self) -> HttpResponseBuilder {
+        self.build(StatusCode::OK)
+    }
+
+    fn build(&self, status: StatusCode) -> HttpResponseBuilder {
+        let mut response = HttpResponse::build(status);
+        response.header(header::ETAG, self.etag.as_str());
+        if let Some(last_modified) = self.last_modified {
+            response.header(header::LAST_MODIFIED, last_modified.format(HTTP_DATE).to_string());
+        }
+        if !self.cache_control.is_empty() {
+            response.header(header::CACHE_CONTROL, self.cache_control.as_str());
+        }
+        if let Some(vary) = &self.vary {
+            response.header(header::VARY, vary.as_str());
+        }
+        response
+    }
+
+    /// RFC 7232: a tag in `If-None-Match` matches, compared weakly as GET does, or without that header
+    /// the last modification is not after `If-Modified-Since`. Dates that cannot be read never match.
+    fn is_fresh(&self, req: &HttpRequest) -> bool {
+        let headers = req.headers();
+        if headers.contains_key(header::IF_NONE_MATCH) {
+            return headers.get_all(header::IF_NONE_MATCH)
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
+        }
+        let since = headers.get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+        match (self.last_modified, since) {
+            // the header has whole seconds
+            (Some(last_modified), Some(since)) => last_modified.timestamp() <= since.timestamp(),
+            _ => false
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod caching_tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::TestRequest;
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::caching::Validators;
+
+    #[test]
+    fn answers_not_modified_for_matching_tag() {
+        let validators = Validators::new("\"3\"".to_string(), "no-cache".to_string());
+        let fresh = |value: &str| validators.not_modified(&TestRequest::default()
+            .header(header::IF_NONE_MATCH, value).to_http_request()).is_some();
+        assert!(fresh("\"3\""));
+        assert!(fresh("W/\"3\""));
+        assert!(fresh("\"1\", \"3\""));
+        assert!(fresh("*"));
+        assert!(!fresh("\"2\""));
+        assert!(!fresh("3"));
+        assert!(validators.not_modified(&TestRequest::default().to_http_request()).is_none());
+
+        let response = validators.not_modified(&TestRequest::default()
+            .header(header::IF_NONE_MATCH, "\"3\"").to_http_request()).unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), "\"3\"");
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "no-cache");
+    }
+
+    #[test]
+    fn answers_not_modified_since() {
+        let modified = Utc.ymd(2015, 10, 21).and_hms_milli(7, 28, 0, 500);
+        let validators = Validators::new("\"3\"".to_string(), String::new()).last_modified(modified);
+        let fresh = |value: &str| validators.not_modified(&TestRequest::default()
+            .header(header::IF_MODIFIED_SINCE, value).to_http_request()).is_some();
+        assert!(fresh("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert!(fresh("Wed, 21 Oct 2015 08:00:00 GMT"));
+        assert!(!fresh("Wed, 21 Oct 2015 07:27:59 GMT"));
+        assert!(!fresh("yesterday"));
+
+        // If-None-Match wins
+        let req = TestRequest::default()
+            .header(header::IF_NONE_MATCH, "\"2\"")
+            .header(header::IF_MODIFIED_SINCE, "Wed, 21 Oct 2015 08:00:00 GMT").to_http_request();
+        assert!(validators.not_modified(&req).is_none());
+
+        let response = validators.ok().finish();
+        assert_eq!(response.headers().get(header::LAST_MODIFIED).unwrap(), "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(response.headers().get(header::CACHE_CONTROL), None);
+
+        let later = Validators::new("\"4\"".to_string(), String::new()).last_modified(modified + Duration::seconds(1));
+        assert!(later.not_modified(&TestRequest::default()
+            .header(header::IF_MODIFIED_SINCE, "Wed, 21 Oct 2015 07:28:00 GMT").to_http_request()).is_none());
+    }
+}
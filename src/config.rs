@@ -0,0 +1,721 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use actix_web::http::{HeaderValue, Uri};
+use serde::Deserialize;
+use simplelog::LevelFilter;
+
+const DEFAULT_CONFIG_FILE: &str = "zellinotes.toml";
+const ENV_PREFIX: &str = "ZELLINOTES_";
+const ENV_CONFIG_FILE: &str = "ZELLINOTES_CONFIG";
+const ARG_CONFIG_FILE: &str = "--config";
+
+/// Every key that can be overridden by an environment variable or a CLI flag.
+/// `database.uri` is read from `ZELLINOTES_DATABASE_URI` and `--database-uri`.
+const KEYS: [&str; 36] = [
+    "database.backend",
+    "database.sqlite_path",
+    "database.uri",
+    "database.name",
+    "database.recipe_collection",
+    "database.app_name",
+    "database.migrate_on_start",
+    "server.bind",
+    "server.payload_limit",
+    "server.json_limit",
+    "tls.enabled",
+    "tls.cert",
+    "tls.key",
+    "cors.allowed_origins",
+    "cors.max_age",
+    "log.level",
+    "log.file",
+    "validation.max_title_length",
+    "validation.max_description_length",
+    "validation.max_tags",
+    "validation.max_tag_length",
+    "validation.max_ingredients",
+    "validation.max_instructions",
+    "validation.max_instruction_length",
+    "validation.max_cooking_time",
+    "validation.max_servings",
+    "validation.max_batch_size",
+    "validation.max_gallery_images",
+    "validation.max_caption_length",
+    "images.backend",
+    "images.path",
+    "images.bucket",
+    "cache.recipe",
+    "cache.recipes",
+    "cache.image",
+    "cache.gallery_image",
+];
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub cors: CorsConfig,
+    pub log: LogConfig,
+    pub validation: ValidationConfig,
+    pub images: ImagesConfig,
+    pub cache: CacheConfig,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub backend: StorageBackend,
+    pub sqlite_path: PathBuf,
+    pub uri: String,
+    pub name: String,
+    pub recipe_collection: String,
+    pub app_name: String,
+    /// applies pending schema migrations before serving, otherwise they are left to `migrate`
+    pub migrate_on_start: bool,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Mongodb,
+    /// embedded database in `sqlite_path`, for small installs
+    Sqlite,
+    /// not persisted, for development and tests
+    Memory,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: Vec<String>,
+    pub payload_limit: usize,
+    pub json_limit: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// empty means every origin is allowed
+    pub allowed_origins: Vec<String>,
+    pub max_age: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: String,
+    pub file: PathBuf,
+}
+
+/// Limits every recipe sent by a client has to keep, lengths count characters.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    pub max_title_length: usize,
+    pub max_description_length: usize,
+    pub max_tags: usize,
+    pub max_tag_length: usize,
+    pub max_ingredients: usize,
+    pub max_instructions: usize,
+    pub max_instruction_length: usize,
+    /// minutes
+    pub max_cooking_time: u32,
+    pub max_servings: u32,
+    /// recipes in one `POST /recipes`
+    pub max_batch_size: usize,
+    pub max_gallery_images: usize,
+    pub max_caption_length: usize,
+}
+
+/// Where image bytes are kept, recipes only keep a reference to them.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImagesConfig {
+    /// follows `database.backend` when not set: gridfs, filesystem for sqlite, memory
+    pub backend: Option<ImageBackend>,
+    /// directory of the filesystem backend
+    pub path: PathBuf,
+    /// GridFS bucket in `database.name`
+    pub bucket: String,
+}
+
+/// `Cache-Control` the read routes answer with, an empty value sends none.
+/// Answers carry `ETag`s either way, so clients can revalidate what they cached.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// `GET /recipes/{id}`
+    pub recipe: String,
+    /// `GET /recipes`
+    pub recipes: String,
+    /// `GET /recipes/{id}/image`, the cover changes under the same path
+    pub image: String,
+    /// `GET /recipes/{id}/gallery/{imageId}`, the image of a gallery entry never changes
+    pub gallery_image: String,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageBackend {
+    /// needs the mongodb database backend
+    Gridfs,
+    Filesystem,
+    /// not persisted, for development and tests
+    Memory,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigError {
+    File(String),
+    Parse(String),
+    UnknownKey(String),
+    MissingValue(String),
+    InvalidValue { key: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ConfigError::File(message) => write!(f, "could not read config file: {}", message),
+            ConfigError::Parse(message) => write!(f, "could not parse config file: {}", message),
+            ConfigError::UnknownKey(key) => write!(f, "unknown config option '{}'", key),
+            ConfigError::MissingValue(key) => write!(f, "config option '{}' needs a value", key),
+            ConfigError::InvalidValue { key, message } => write!(f, "invalid value for '{}': {}", key, message),
+        }
+    }
+}
+
+impl ConfigError {
+    pub fn invalid(key: &str, message: impl Into<String>) -> Self {
+        ConfigError::InvalidValue { key: key.to_string(), message: message.into() }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::Mongodb,
+            sqlite_path: PathBuf::from("zellinotes.sqlite"),
+            uri: "mongodb://localhost:26666".to_string(),
+            name: "zellinotes_recipes".to_string(),
+            recipe_collection: "recipes".to_string(),
+            app_name: "Zellinotes recipes".to_string(),
+            migrate_on_start: true,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: vec!["127.0.0.1:8080".to_string()],
+            payload_limit: 5 << 20,
+            json_limit: 5 << 20,
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cert: PathBuf::from("localhost.crt"),
+            key: PathBuf::from("localhost.key"),
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self { allowed_origins: vec![], max_age: 3600 }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { level: "info".to_string(), file: PathBuf::from("zellinotes.log") }
+    }
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            max_title_length: 200,
+            max_description_length: 10_000,
+            max_tags: 50,
+            max_tag_length: 50,
+            max_ingredients: 200,
+            max_instructions: 200,
+            max_instruction_length: 5_000,
+            max_cooking_time: 10_080,
+            max_servings: 1_000,
+            max_batch_size: 1_000,
+            max_gallery_images: 50,
+            max_caption_length: 500,
+        }
+    }
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self { backend: None, path: PathBuf::from("images"), bucket: "images".to_string() }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            recipe: "no-cache".to_string(),
+            recipes: "no-cache".to_string(),
+            image: "no-cache".to_string(),
+            gallery_image: "no-cache".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration of the running process, layered as
+    /// defaults < config file < environment variables < CLI flags.
+    /// `args` are the flags without the program name and command.
+    pub fn load(args: &[String]) -> Result<Self, ConfigError> {
+        Config::load_from(args, |key| std::env::var(key).ok())
+    }
+
+    pub fn load_from<F: Fn(&str) -> Option<String>>(args: &[String], env: F) -> Result<Self, ConfigError> {
+        let flags = parse_flags(args)?;
+
+        let explicit_file = flags.iter()
+            .find(|(key, _)| key == ARG_CONFIG_FILE)
+            .map(|(_, value)| PathBuf::from(value))
+            .or_else(|| env(ENV_CONFIG_FILE).map(PathBuf::from));
+
+        let mut config = match explicit_file {
+            Some(path) => Config::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Config::default(),
+        };
+
+        for key in KEYS.iter() {
+            if let Some(value) = env(&env_name(key)) {
+                config.set(key, &value)?;
+            }
+        }
+
+        for (flag, value) in flags.iter().filter(|(flag, _)| flag != ARG_CONFIG_FILE) {
+            let key = KEYS.iter()
+                .find(|key| flag_name(key) == *flag)
+                .ok_or_else(|| ConfigError::UnknownKey(flag.to_string()))?;
+            config.set(key, value)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::File(format!("{}: {}", path.display(), err)))?;
+        Config::from_str(&content)
+    }
+
+    /// overrides a single option, `key` is the dotted name used in the config file
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "database.backend" => self.database.backend = parse_value(key, value)?,
+            "database.sqlite_path" => self.database.sqlite_path = PathBuf::from(value),
+            "database.uri" => self.database.uri = value.to_string(),
+            "database.name" => self.database.name = value.to_string(),
+            "database.recipe_collection" => self.database.recipe_collection = value.to_string(),
+            "database.app_name" => self.database.app_name = value.to_string(),
+            "database.migrate_on_start" => self.database.migrate_on_start = parse_value(key, value)?,
+            "server.bind" => self.server.bind = split_list(value),
+            "server.payload_limit" => self.server.payload_limit = parse_value(key, value)?,
+            "server.json_limit" => self.server.json_limit = parse_value(key, value)?,
+            "tls.enabled" => self.tls.enabled = parse_value(key, value)?,
+            "tls.cert" => self.tls.cert = PathBuf::from(value),
+            "tls.key" => self.tls.key = PathBuf::from(value),
+            "cors.allowed_origins" => self.cors.allowed_origins = split_list(value),
+            "cors.max_age" => self.cors.max_age = parse_value(key, value)?,
+            "log.level" => self.log.level = value.to_string(),
+            "log.file" => self.log.file = PathBuf::from(value),
+            "validation.max_title_length" => self.validation.max_title_length = parse_value(key, value)?,
+            "validation.max_description_length" => self.validation.max_description_length = parse_value(key, value)?,
+            "validation.max_tags" => self.validation.max_tags = parse_value(key, value)?,
+            "validation.max_tag_length" => self.validation.max_tag_length = parse_value(key, value)?,
+            "validation.max_ingredients" => self.validation.max_ingredients = parse_value(key, value)?,
+            "validation.max_instructions" => self.validation.max_instructions = parse_value(key, value)?,
+            "validation.max_instruction_length" => self.validation.max_instruction_length = parse_value(key, value)?,
+            "validation.max_cooking_time" => self.validation.max_cooking_time = parse_value(key, value)?,
+            "validation.max_servings" => self.validation.max_servings = parse_value(key, value)?,
+            "validation.max_batch_size" => self.validation.max_batch_size = parse_value(key, value)?,
+            "validation.max_gallery_images" => self.validation.max_gallery_images = parse_value(key, value)?,
+            "validation.max_caption_length" => self.validation.max_caption_length = parse_value(key, value)?,
+            "images.backend" => self.images.backend = Some(parse_value(key, value)?),
+            "images.path" => self.images.path = PathBuf::from(value),
+            "images.bucket" => self.images.bucket = value.to_string(),
+            "cache.recipe" => self.cache.recipe = value.to_string(),
+            "cache.recipes" => self.cache.recipes = value.to_string(),
+            "cache.image" => self.cache.image = value.to_string(),
+            "cache.gallery_image" => self.cache.gallery_image = value.to_string(),
+            _ => return Err(ConfigError::UnknownKey(key.to_string()))
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.database.backend == StorageBackend::Mongodb {
+            if !self.database.uri.starts_with("mongodb://") && !self.database.uri.starts_with("mongodb+srv://") {
+                return Err(ConfigError::invalid("database.uri", "must start with mongodb:// or mongodb+srv://"));
+            }
+            validate_name("database.name", &self.database.name)?;
+            validate_name("database.recipe_collection", &self.database.recipe_collection)?;
+        }
+        if self.database.backend == StorageBackend::Sqlite {
+            let parent = self.database.sqlite_path.parent().filter(|parent| !parent.as_os_str().is_empty());
+            if self.database.sqlite_path.is_dir() || parent.is_some_and(|parent| !parent.is_dir()) {
+                return Err(ConfigError::invalid("database.sqlite_path", format!("cannot create a database file at '{}'", self.database.sqlite_path.display())));
+            }
+        }
+
+        if self.server.bind.is_empty() {
+            return Err(ConfigError::invalid("server.bind", "at least one address is needed"));
+        }
+        for addr in &self.server.bind {
+            addr.to_socket_addrs()
+                .map_err(|err| ConfigError::invalid("server.bind", format!("'{}' is no valid address: {}", addr, err)))?;
+        }
+        if self.server.payload_limit == 0 {
+            return Err(ConfigError::invalid("server.payload_limit", "must be greater than 0"));
+        }
+        if self.server.json_limit == 0 {
+            return Err(ConfigError::invalid("server.json_limit", "must be greater than 0"));
+        }
+
+        if self.tls.enabled {
+            if !self.tls.cert.is_file() {
+                return Err(ConfigError::invalid("tls.cert", format!("file '{}' does not exist", self.tls.cert.display())));
+            }
+            if !self.tls.key.is_file() {
+                return Err(ConfigError::invalid("tls.key", format!("file '{}' does not exist", self.tls.key.display())));
+            }
+        }
+
+        for origin in &self.cors.allowed_origins {
+            let valid = Uri::try_from(origin.as_str())
+                .map(|uri| uri.scheme().is_some() && uri.host().is_some())
+                .unwrap_or(false);
+            if !valid {
+                return Err(ConfigError::invalid("cors.allowed_origins", format!("'{}' is no origin like https://example.org", origin)));
+            }
+        }
+
+        let validation = &self.validation;
+        let limits = [
+            ("validation.max_title_length", validation.max_title_length),
+            ("validation.max_tag_length", validation.max_tag_length),
+            ("validation.max_servings", validation.max_servings as usize),
+            ("validation.max_batch_size", validation.max_batch_size),
+        ];
+        if let Some((key, _)) = limits.iter().find(|(_, limit)| *limit == 0) {
+            return Err(ConfigError::invalid(key, "must be greater than 0, no recipe could be stored"));
+        }
+
+        match self.image_backend() {
+            ImageBackend::Gridfs if self.database.backend != StorageBackend::Mongodb => {
+                return Err(ConfigError::invalid("images.backend", "gridfs needs the mongodb database backend"));
+            }
+            ImageBackend::Gridfs => validate_name("images.bucket", &self.images.bucket)?,
+            ImageBackend::Filesystem if self.images.path.is_file() => {
+                return Err(ConfigError::invalid("images.path", format!("'{}' is a file, not a directory", self.images.path.display())));
+            }
+            _ => {}
+        }
+
+        let cache = [
+            ("cache.recipe", &self.cache.recipe),
+            ("cache.recipes", &self.cache.recipes),
+            ("cache.image", &self.cache.image),
+            ("cache.gallery_image", &self.cache.gallery_image),
+        ];
+        for (key, value) in cache.iter() {
+            if HeaderValue::from_str(value).is_err() {
+                return Err(ConfigError::invalid(key, format!("'{}' cannot be sent as Cache-Control", value.escape_debug())));
+            }
+        }
+
+        self.log_level()?;
+        Ok(())
+    }
+
+    pub fn image_backend(&self) -> ImageBackend {
+        self.images.backend.unwrap_or(match self.database.backend {
+            StorageBackend::Mongodb => ImageBackend::Gridfs,
+            StorageBackend::Sqlite => ImageBackend::Filesystem,
+            StorageBackend::Memory => ImageBackend::Memory,
+        })
+    }
+
+    pub fn log_level(&self) -> Result<LevelFilter, ConfigError> {
+        LevelFilter::from_str(&self.log.level)
+            .map_err(|_| ConfigError::invalid("log.level", format!("'{}' is none of off, error, warn, info, debug, trace", self.log.level)))
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mongodb" => Ok(StorageBackend::Mongodb),
+            "sqlite" => Ok(StorageBackend::Sqlite),
+            "memory" => Ok(StorageBackend::Memory),
+            _ => Err("expected one of mongodb, sqlite, memory".to_string())
+        }
+    }
+}
+
+impl FromStr for ImageBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "gridfs" => Ok(ImageBackend::Gridfs),
+            "filesystem" => Ok(ImageBackend::Filesystem),
+            "memory" => Ok(ImageBackend::Memory),
+            _ => Err("expected one of gridfs, filesystem, memory".to_string())
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        toml::from_str(content).map_err(|err| ConfigError::Parse(err.to_string()))
+    }
+}
+
+fn env_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase())
+}
+
+fn flag_name(key: &str) -> String {
+    format!("--{}", key.replace(['.', '_'], "-"))
+}
+
+/// accepts `--key value` and `--key=value`
+fn parse_flags(args: &[String]) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut flags = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            return Err(ConfigError::UnknownKey(arg.to_string()));
+        }
+        match arg.find('=') {
+            Some(index) => flags.push((arg[..index].to_string(), arg[index + 1..].to_string())),
+            None => {
+                let value = args.next().ok_or_else(|| ConfigError::MissingValue(arg.to_string()))?;
+                flags.push((arg.to_string(), value.to_string()));
+            }
+        }
+    }
+    Ok(flags)
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError> where T::Err: fmt::Display {
+    value.trim().parse::<T>()
+        .map_err(|err| ConfigError::invalid(key, format!("'{}': {}", value, err)))
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn validate_name(key: &str, name: &str) -> Result<(), ConfigError> {
+    if name.is_empty() {
+        return Err(ConfigError::invalid(key, "must not be empty"));
+    }
+    if name.contains(['$', '/', '\\', ' ', '\0']) {
+        return Err(ConfigError::invalid(key, format!("'{}' contains characters MongoDB does not allow", name)));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod config_tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use crate::config::{Config, ConfigError, ImageBackend, StorageBackend};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn no_tls() -> Vec<String> {
+        args(&["--tls-enabled", "false"])
+    }
+
+    #[test]
+    fn defaults_match_previous_constants() {
+        let config = Config::default();
+        assert_eq!(config.database.uri, "mongodb://localhost:26666");
+        assert_eq!(config.database.name, "zellinotes_recipes");
+        assert_eq!(config.database.recipe_collection, "recipes");
+        assert_eq!(config.server.bind, vec!["127.0.0.1:8080"]);
+        assert_eq!(config.server.payload_limit, 5 << 20);
+        assert_eq!(config.server.json_limit, 5 << 20);
+    }
+
+    #[test]
+    fn parse_partial_toml_keeps_defaults() {
+        let config = Config::from_str(r#"
+            [database]
+            name = "other"
+
+            [server]
+            bind = ["0.0.0.0:9000", "[::1]:9000"]
+        "#).unwrap();
+        assert_eq!(config.database.name, "other");
+        assert_eq!(config.database.uri, "mongodb://localhost:26666");
+        assert_eq!(config.server.bind, vec!["0.0.0.0:9000", "[::1]:9000"]);
+        assert_eq!(config.server.json_limit, 5 << 20);
+    }
+
+    #[test]
+    fn parse_toml_with_unknown_key_fails() {
+        let result = Config::from_str("[database]\nurl = \"mongodb://x\"");
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn env_overrides_defaults_and_flags_override_env() {
+        let env: HashMap<&str, &str> = vec![
+            ("ZELLINOTES_DATABASE_NAME", "from_env"),
+            ("ZELLINOTES_DATABASE_RECIPE_COLLECTION", "env_recipes"),
+            ("ZELLINOTES_SERVER_BIND", "127.0.0.1:1, 127.0.0.1:2"),
+        ].into_iter().collect();
+        let mut arguments = no_tls();
+        arguments.extend(args(&["--database-name", "from_flag", "--log-level=debug"]));
+
+        let config = Config::load_from(&arguments, |key| env.get(key).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.database.name, "from_flag");
+        assert_eq!(config.database.recipe_collection, "env_recipes");
+        assert_eq!(config.server.bind, vec!["127.0.0.1:1", "127.0.0.1:2"]);
+        assert_eq!(config.log.level, "debug");
+    }
+
+    #[test]
+    fn unknown_flag_and_missing_value_fail() {
+        let result = Config::load_from(&args(&["--datbase-uri", "x"]), |_| None);
+        assert_eq!(result, Err(ConfigError::UnknownKey("--datbase-uri".to_string())));
+
+        let result = Config::load_from(&args(&["--database-uri"]), |_| None);
+        assert_eq!(result, Err(ConfigError::MissingValue("--database-uri".to_string())));
+    }
+
+    #[test]
+    fn missing_config_file_fails() {
+        let result = Config::load_from(&args(&["--config", "/does/not/exist.toml"]), |_| None);
+        assert!(matches!(result, Err(ConfigError::File(_))));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let invalid = vec![
+            ("--database-uri", "postgres://localhost"),
+            ("--database-name", ""),
+            ("--database-recipe-collection", "re$cipes"),
+            ("--server-bind", "not an address"),
+            ("--server-payload-limit", "0"),
+            ("--server-json-limit", "-1"),
+            ("--cors-allowed-origins", "example.org"),
+            ("--log-level", "loud"),
+            ("--tls-enabled", "maybe"),
+            ("--database-backend", "postgres"),
+            ("--validation-max-servings", "0"),
+            ("--validation-max-tags", "many"),
+            ("--database-migrate-on-start", "later"),
+            ("--images-backend", "s3"),
+            ("--images-bucket", "my images"),
+            ("--cache-recipe", "no-cache\r\nSet-Cookie: a=b"),
+        ];
+        for (flag, value) in invalid {
+            let mut arguments = no_tls();
+            arguments.extend(args(&[flag, value]));
+            let result = Config::load_from(&arguments, |_| None);
+            assert!(matches!(result, Err(ConfigError::InvalidValue { .. })), "{} {} -> {:?}", flag, value, result);
+        }
+    }
+
+    #[test]
+    fn tls_files_must_exist_when_enabled() {
+        let result = Config::load_from(&args(&["--tls-cert", "/does/not/exist.crt"]), |_| None);
+        match result {
+            Err(ConfigError::InvalidValue { key, .. }) => assert_eq!(key, "tls.cert"),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn memory_backend_ignores_mongo_settings() {
+        let mut arguments = no_tls();
+        arguments.extend(args(&["--database-backend", "memory", "--database-uri", "unused"]));
+        let config = Config::load_from(&arguments, |_| None).unwrap();
+        assert_eq!(config.database.backend, StorageBackend::Memory);
+    }
+
+    #[test]
+    fn sqlite_backend_needs_existing_directory() {
+        let mut arguments = no_tls();
+        arguments.extend(args(&["--database-backend", "sqlite", "--database-sqlite-path", "/does/not/exist/recipes.sqlite"]));
+        let result = Config::load_from(&arguments, |_| None);
+        assert!(matches!(result, Err(ConfigError::InvalidValue { .. })));
+
+        let mut arguments = no_tls();
+        arguments.extend(args(&["--database-backend=sqlite", "--database-sqlite-path=recipes.sqlite"]));
+        let config = Config::load_from(&arguments, |_| None).unwrap();
+        assert_eq!(config.database.backend, StorageBackend::Sqlite);
+    }
+
+    #[test]
+    fn image_backend_follows_database_backend() {
+        let mut arguments = no_tls();
+        arguments.extend(args(&["--database-backend", "sqlite", "--database-sqlite-path", "recipes.sqlite"]));
+        let config = Config::load_from(&arguments, |_| None).unwrap();
+        assert_eq!(config.image_backend(), ImageBackend::Filesystem);
+        assert_eq!(Config::default().image_backend(), ImageBackend::Gridfs);
+
+        arguments.extend(args(&["--images-backend", "gridfs"]));
+        let result = Config::load_from(&arguments, |_| None);
+        assert!(matches!(result, Err(ConfigError::InvalidValue { key, .. }) if key == "images.backend"));
+    }
+
+    #[test]
+    fn cors_origins_are_accepted() {
+        let mut arguments = no_tls();
+        arguments.extend(args(&["--cors-allowed-origins", "https://zellinotes.de,http://localhost:4200"]));
+        let config = Config::load_from(&arguments, |_| None).unwrap();
+        assert_eq!(config.cors.allowed_origins, vec!["https://zellinotes.de", "http://localhost:4200"]);
+    }
+}
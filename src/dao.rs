@@ -2,50 +2,67 @@ use std::convert::TryFrom;
 
 use bson::Document;
 use bson::document::ValueAccessError;
+use async_trait::async_trait;
 use bson::oid::ObjectId;
+use chrono::{TimeZone, Utc};
 use futures_util::StreamExt;
-use mongodb::{bson::{Bson, doc}, Client, options::FindOptions};
+use mongodb::{bson::{Bson, doc}, Client, Collection, options::FindOptions};
 use mongodb::Database;
 use mongodb::error::Error;
-use mongodb::options::{ClientOptions, FindOneOptions, UpdateModifications};
+use mongodb::options::{ClientOptions, FindOneAndUpdateOptions, FindOneOptions, ReturnDocument, UpdateModifications, UpdateOptions};
 
 use crate::{LogExtensionErr, LogExtensionOk};
+use crate::config::DatabaseConfig;
+use crate::filter::RecipeFilter;
+use crate::model::gallery::{self, GalleryImage};
 use crate::model::recipe::{Recipe, RecipeFormatError};
-use crate::pagination::Pagination;
+use crate::model::unit::Unit;
+use crate::pagination::{Cursor, Listing, Sort, SortField, SortKey, SortValue};
+use crate::store::{DaoError, ImageBase64String, now, RecipeStore, RecipeStream, RecipeSummary, Resource, revise, UnreadableRecipe};
 
-const RECIPE_COLLECTION: &str = "recipes";
-const URL: &str = "mongodb://localhost:26666";
-const APP_NAME: &str = "Zellinotes recipes";
-const DATABASE: &str = "zellinotes_recipes";
-
-type ImageBase64String = String;
+/// custom units, `{_id: id, definition: unit, added: date}`
+pub const UNIT_COLLECTION: &str = "units";
 
 #[derive(Clone)]
 pub struct Dao {
-    pub database: Database
-}
-
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum DaoError {
-    DatabaseError(String),
-    DocumentNotFound,
-    RecipeFormatError(String),
+    pub database: Database,
+    pub recipe_collection: String,
 }
 
 impl Dao {
-    pub async fn new() -> Option<Self> {
-        match get_db_handler().await
+    pub async fn new(config: &DatabaseConfig) -> Option<Self> {
+        get_db_handler(config).await
             .log_if_ok(|_| info!("Created database handler"))
             .log_if_err(|err| error!("Could not create database handler. Err={}", err))
-            .ok() {
-            Some(database) => Some(Self { database }),
-            None => None
-        }
+            .ok()
+            .map(|database| Self { database, recipe_collection: config.recipe_collection.clone() })
+    }
+
+    fn recipes(&self) -> Collection {
+        self.database.collection(&self.recipe_collection)
     }
 
-    /// ignores id
-    pub async fn insert_recipe(&self, recipe: Recipe) -> Result<Bson, DaoError> {
-        match self.database.collection(RECIPE_COLLECTION).insert_one(recipe.clone().into(), None).await {
+    fn units(&self) -> Collection {
+        self.database.collection(UNIT_COLLECTION)
+    }
+
+    fn recipe_without_image_find_options() -> Option<FindOneOptions> {
+        let mut options = FindOneOptions::default();
+        options.projection = Some(db_projection_only_image());
+        Some(options)
+    }
+
+    fn recipe_only_image_find_options() -> Option<FindOneOptions> {
+        let mut options = FindOneOptions::default();
+        options.projection = Some(Recipe::default_projection_no_image());
+        Some(options)
+    }
+}
+
+#[async_trait]
+impl RecipeStore for Dao {
+    async fn insert_recipe(&self, recipe: Recipe) -> Result<Bson, DaoError> {
+        match self.recipes().insert_one(recipe.clone().into(), None).await {
             Ok(result) => {
                 info!("Added recipe in db. id={:?}", result.inserted_id);
                 Ok(result.inserted_id)
@@ -57,26 +74,31 @@ impl Dao {
         }
     }
 
-    pub async fn update_recipe_ignore_image(&self, id: ObjectId, recipe: Recipe) -> Result<(), DaoError> {
-        let query = object_id_into_doc(id.clone());
+    async fn update_recipe_ignore_image(&self, id: ObjectId, recipe: Recipe, expected_version: u32) -> Result<Recipe, DaoError> {
+        let mut query = object_id_into_doc(id.clone());
+        query.insert("version", expected_version);
 
-        let mut recipe = Document::from(recipe);
-        recipe.remove("image");
+        let mut document = Document::from(revise(recipe, id.clone(), expected_version));
+        document.remove("image");
+        document.remove("created");
+        document.remove("gallery");
         let update = UpdateModifications::Document(
-            doc! { "$set" : recipe}
+            doc! { "$set" : document}
         );
+        let mut options = FindOneAndUpdateOptions::default();
+        options.projection = Some(Recipe::default_projection_no_image());
+        options.return_document = Some(ReturnDocument::After);
 
-        match self.database.collection(RECIPE_COLLECTION)
-            .update_one(query, update, None).await {
-            Ok(result) => match result.modified_count {
-                0 => {
-                    info!("Not Updated recipe, doc not found with id={:#?}", &id);
-                    Err(DaoError::DocumentNotFound)
-                }
-                _ => {
-                    info!("Updated recipe in db with id={:#?}", &id);
-                    Ok(())
-                }
+        match self.recipes()
+            .find_one_and_update(query, update, options).await {
+            Ok(Some(document)) => {
+                info!("Updated recipe in db with id={:#?}", &id);
+                Ok(Recipe::try_from(document)?)
+            }
+            Ok(None) => {
+                let current = self.get_one_recipe_without_image(id.clone()).await?;
+                info!("Not Updated recipe, version {} expected but found {} id={:#?}", expected_version, current.version, &id);
+                Err(DaoError::VersionConflict(Box::new(current)))
             }
             Err(err) => {
                 error!("Could not update recipe with id={:#?}, Err={:#?}", &id, err);
@@ -85,8 +107,8 @@ impl Dao {
         }
     }
 
-    pub async fn add_many_recipes(&self, recipes: Vec<Recipe>) -> Result<Bson, DaoError> {
-        match self.database.collection(RECIPE_COLLECTION).insert_many(
+    async fn add_many_recipes(&self, recipes: Vec<Recipe>) -> Result<Bson, DaoError> {
+        match self.recipes().insert_many(
             recipes.clone().into_iter().map(|r| r.into()).collect::<Vec<Document>>(), None).await {
             Ok(result) => {
                 info!("Added multiple recipes in db. ids={:#?}", result.inserted_ids);
@@ -99,16 +121,15 @@ impl Dao {
         }
     }
 
-    pub async fn get_one_recipe_without_image(&self, id: ObjectId) -> Result<Recipe, DaoError> {
+    async fn get_one_recipe_without_image(&self, id: ObjectId) -> Result<Recipe, DaoError> {
         let filter = object_id_into_doc(id.clone());
 
         let options = Dao::recipe_only_image_find_options();
 
-        let result = self.database
-            .collection(RECIPE_COLLECTION)
+        let result = self.recipes()
             .find_one(filter, options).await
-            .map_err(|err| DaoError::from(err))?
-            .map(|doc| Recipe::try_from(doc));
+            .map_err(DaoError::from)?
+            .map(Recipe::try_from);
 
         match result {
             Some(Ok(recipe)) => {
@@ -121,21 +142,20 @@ impl Dao {
             }
             None => {
                 error!("get recipe without image, recipe Not found: id={:#?}", id);
-                Err(DaoError::DocumentNotFound)
+                Err(DaoError::DocumentNotFound(Resource::Recipe))
             }
         }
     }
 
-    pub async fn get_one_recipe_image(&self, id: ObjectId) -> Result<ImageBase64String, DaoError> {
+    async fn get_one_recipe_image(&self, id: ObjectId) -> Result<ImageBase64String, DaoError> {
         let filter = object_id_into_doc(id.clone());
 
         let options = Dao::recipe_without_image_find_options();
 
-        let image: Option<Document> = self.database
-            .collection(RECIPE_COLLECTION)
+        let image: Option<Document> = self.recipes()
             .find_one(filter, options)
             .await
-            .map_err(|err| DaoError::from(err))?;
+            .map_err(DaoError::from)?;
 
         match image {
             Some(image) => {
@@ -146,67 +166,71 @@ impl Dao {
                     }
                     Err(_) => {
                         error!("Image not found, or not string id={:#?}", id.clone());
-                        Err(DaoError::DocumentNotFound)
+                        Err(DaoError::DocumentNotFound(Resource::Image))
                     }
                 }
             }
             None => {
                 error!("Image not found id={:#?}", id);
-                Err(DaoError::DocumentNotFound)
+                Err(DaoError::DocumentNotFound(Resource::Recipe))
             }
         }
     }
 
-    pub async fn update_one_recipe_image(&self, id: ObjectId, image: Option<String>) -> Result<(), DaoError> {
-        let query = object_id_into_doc(id.clone());
+    async fn update_gallery(&self, id: ObjectId, gallery: Vec<GalleryImage>, expected_version: u32) -> Result<Recipe, DaoError> {
+        let mut query = object_id_into_doc(id.clone());
+        query.insert("version", expected_version);
 
-        let update = match image {
-            Some(image) => UpdateModifications::Document(
-                doc! { "$set" : { "image" : image} }
-            ),
-            None => UpdateModifications::Document(
-                doc! { "$set" : { "image" : Bson::Null} }
-            )
-        };
+        let image = gallery::cover(&gallery).map_or(Bson::Null, |cover| Bson::String(cover.image.clone()));
+        let update = UpdateModifications::Document(doc! { "$set": {
+            "gallery": gallery,
+            "image": image,
+            "version": expected_version.saturating_add(1),
+            "last_modified": now(),
+        } });
+        let mut options = FindOneAndUpdateOptions::default();
+        options.projection = Some(Recipe::default_projection_no_image());
+        options.return_document = Some(ReturnDocument::After);
 
-        match self.database.collection(RECIPE_COLLECTION)
-            .update_one(query, update, None).await {
-            Ok(result) => match result.modified_count {
-                0 => {
-                    info!("Not Updated image, doc not found with id={:#?}", &id);
-                    Err(DaoError::DocumentNotFound)
-                }
-                _ => {
-                    info!("Updated recipe image in db with id={:#?}", &id);
-                    Ok(())
-                }
+        match self.recipes().find_one_and_update(query, update, options).await {
+            Ok(Some(document)) => {
+                info!("Updated recipe gallery in db with id={:#?}", &id);
+                Ok(Recipe::try_from(document)?)
+            }
+            Ok(None) => {
+                let current = self.get_one_recipe_without_image(id.clone()).await?;
+                info!("Not Updated gallery, version {} expected but found {} id={:#?}", expected_version, current.version, &id);
+                Err(DaoError::VersionConflict(Box::new(current)))
             }
             Err(err) => {
-                error!("Could not update recipe image with id={:#?}, Err={:#?}", &id, err);
+                error!("Could not update recipe gallery with id={:#?}, Err={:#?}", &id, err);
                 Err(DaoError::from(err))
             }
         }
     }
 
-
-    fn recipe_without_image_find_options() -> Option<FindOneOptions> {
-        let mut options = FindOneOptions::default();
-        options.projection = Some(db_projection_only_image());
-        let options = Some(options);
-        options
+    async fn recipes_with_inline_image(&self) -> Result<Vec<ObjectId>, DaoError> {
+        let inline = doc! { "image": { "$type": "string", "$not": { "$regex": "^sha256:" } } };
+        let mut options = FindOptions::default();
+        options.projection = Some(doc! { "_id": 1 });
+        let mut cursor = self.recipes().find(inline, options).await?;
+        let mut ids = vec![];
+        while let Some(document) = cursor.next().await {
+            ids.push(document?.get_object_id("_id")?.clone());
+        }
+        Ok(ids)
     }
 
-    fn recipe_only_image_find_options() -> Option<FindOneOptions> {
-        let mut options = FindOneOptions::default();
-        options.projection = Some(Recipe::default_projection_no_image());
-        let options = Some(options);
-        options
+    async fn replace_inline_image(&self, id: ObjectId, inline: &str, reference: &str) -> Result<bool, DaoError> {
+        let result = self.recipes().update_one(doc! { "_id": id, "image": inline },
+                                               doc! { "$set": { "image": reference } }, None).await?;
+        Ok(result.matched_count == 1)
     }
 
-    pub async fn delete_one_recipe(&self, id: ObjectId) -> Result<(), DaoError> {
+    async fn delete_one_recipe(&self, id: ObjectId) -> Result<(), DaoError> {
         let query = object_id_into_doc(id.clone());
 
-        match self.database.collection(RECIPE_COLLECTION).delete_one(query, None).await {
+        match self.recipes().delete_one(query, None).await {
             Ok(delete_result) => match delete_result.deleted_count {
                 1 => {
                     info!("Deleted one recipe from db. id={:#?}", &id);
@@ -214,7 +238,7 @@ impl Dao {
                 }
                 _ => {
                     error!("Deleted no recipe from db. id={:#?}", &id);
-                    Err(DaoError::DocumentNotFound)
+                    Err(DaoError::DocumentNotFound(Resource::Recipe))
                 }
             }
             Err(err) => {
@@ -224,11 +248,79 @@ impl Dao {
         }
     }
 
-    pub async fn get_many_recipes(&self, pagination: Option<Pagination>) -> Result<Vec<Recipe>, DaoError> {
-        get_many_recipes(&self.database, pagination).await
-            .log_if_ok(|recipes| info!("Get many recipes from db. ids={:#?}", recipes))
+    async fn stream_many_recipes(&self, listing: &Listing, filter: &RecipeFilter) -> Result<RecipeStream, DaoError> {
+        stream_many_recipes(&self.recipes(), listing, filter).await
+            .log_if_ok(|_| info!("Streaming many recipes from db. listing={:?}, filter={:?}", listing, filter))
             .log_if_err(|err| error!("{:#?}", err))
     }
+
+    async fn summarize_recipes(&self, filter: &RecipeFilter) -> Result<RecipeSummary, DaoError> {
+        let pipeline = vec![
+            doc! { "$match": filter.to_document() },
+            doc! { "$group": {
+                "_id": Bson::Null,
+                "count": { "$sum": 1 },
+                "versions": { "$sum": "$version" },
+                "last_modified": { "$max": "$last_modified" },
+            }},
+        ];
+        let mut groups = self.recipes().aggregate(pipeline, None).await
+            .map_err(DaoError::from)
+            .log_if_err(|err| error!("Error summarizing recipes in db. filter={:?}, err={:#?}", filter, err))?;
+        let group = match groups.next().await {
+            Some(group) => group.map_err(DaoError::from)?,
+            None => return Ok(RecipeSummary::default())
+        };
+        // $sum answers an int32 as long as it fits
+        let number = |key: &str| group.get_i64(key).or_else(|_| group.get_i32(key).map(i64::from)).unwrap_or_default();
+        Ok(RecipeSummary {
+            count: number("count") as usize,
+            versions: number("versions") as u64,
+            last_modified: group.get_datetime("last_modified").ok().copied(),
+        })
+    }
+
+    async fn custom_units(&self) -> Result<Vec<Unit>, DaoError> {
+        let mut options = FindOptions::default();
+        options.sort = Some(doc! { "added": 1, "_id": 1 });
+        let mut documents = self.units().find(None, options).await
+            .map_err(DaoError::from)
+            .log_if_err(|err| error!("Error reading units from db. err={:#?}", err))?;
+        let mut units = vec![];
+        while let Some(document) = documents.next().await {
+            let definition = document.map_err(DaoError::from)?.get_document("definition")?.clone();
+            units.push(bson::from_document(definition)
+                .map_err(|err| DaoError::RecipeFormatError(format!("unit, {}", err)))?);
+        }
+        Ok(units)
+    }
+
+    async fn put_unit(&self, unit: Unit) -> Result<bool, DaoError> {
+        let definition = bson::to_document(&unit)
+            .map_err(|err| DaoError::RecipeFormatError(err.to_string()))?;
+        let mut options = UpdateOptions::default();
+        options.upsert = Some(true);
+        let result = self.units().update_one(doc! { "_id": unit.id.id() },
+                                             doc! { "$set": { "definition": definition }, "$setOnInsert": { "added": Utc::now() } },
+                                             options).await
+            .map_err(DaoError::from)
+            .log_if_err(|err| error!("Error storing unit in db. id={}, err={:#?}", unit.id, err))?;
+        info!("Stored unit in db. id={}", unit.id);
+        Ok(result.upserted_id.is_some())
+    }
+
+    async fn delete_unit(&self, id: &str) -> Result<(), DaoError> {
+        let result = self.units().delete_one(doc! { "_id": id }, None).await
+            .map_err(DaoError::from)
+            .log_if_err(|err| error!("Error deleting unit from db. id={}, err={:#?}", id, err))?;
+        match result.deleted_count {
+            1 => {
+                info!("Deleted unit from db. id={}", id);
+                Ok(())
+            }
+            _ => Err(DaoError::DocumentNotFound(Resource::Unit))
+        }
+    }
 }
 
 
@@ -250,6 +342,15 @@ impl From<RecipeFormatError> for DaoError {
     }
 }
 
+/// the id as hex string for reports, any other `_id` as it is stored
+pub fn document_id(document: &Document) -> String {
+    match document.get("_id") {
+        Some(Bson::ObjectId(id)) => id.to_hex(),
+        Some(id) => id.to_string(),
+        None => "unknown".to_string()
+    }
+}
+
 fn object_id_into_doc(id: ObjectId) -> Document {
     doc! {"_id": Bson::ObjectId(id)}
 }
@@ -258,58 +359,99 @@ fn db_projection_only_image() -> Document {
     doc! {"image": 1, "_id": 0}
 }
 
-async fn get_db_handler() -> Result<Database, Error> {
-    let mut client_options = ClientOptions::parse(URL).await?;
-    client_options.app_name = Some(APP_NAME.to_string());
+pub async fn get_db_handler(config: &DatabaseConfig) -> Result<Database, Error> {
+    let mut client_options = ClientOptions::parse(&config.uri).await?;
+    client_options.app_name = Some(config.app_name.clone());
     let client = Client::with_options(client_options)?;
-    return Ok(client.database(DATABASE));
+    return Ok(client.database(&config.name));
 }
 
 
-pub async fn get_many_recipes(db: &Database, pagination: Option<Pagination>) -> Result<Vec<Recipe>, DaoError> {
+/// filter, order, skip and limit are sent to the server, the image is never part of a listing
+pub async fn stream_many_recipes(collection: &Collection, listing: &Listing, filter: &RecipeFilter) -> Result<RecipeStream, DaoError> {
     let mut find_options = FindOptions::default();
-    let mut skip = 0;
-    let mut take = usize::MAX;
-    if pagination.is_some() {
-        skip = (pagination.unwrap().page.unwrap() - 1) * pagination.unwrap().items.unwrap();
-        take = pagination.unwrap().items.unwrap();
-        find_options.sort = Some(doc! { "created": Bson::Int32(pagination.unwrap().sorting.unwrap() as i32) });
-        find_options.projection = Some(Recipe::default_projection_no_image());
-    }
-
-    match db.collection(RECIPE_COLLECTION).find(None, find_options).await {
-        Ok(cursor) => {
-            let recipes = cursor
-                .skip(skip)
-                .take(take)
-                .collect::<Vec<Result<Document, Error>>>()
-                .await
-                .into_iter()
-                .collect::<Result<Vec<Document>, Error>>()
-                .map_err(|err| {
-                    DaoError::DatabaseError(format!("{:#?}", err))
-                })?;
-
-            let recipes = recipes
-                .into_iter()
-                .map(|recipe| Recipe::try_from(recipe))
-                .collect::<Result<Vec<Recipe>, RecipeFormatError>>()
-                .map_err(|err| {
-                    DaoError::DatabaseError(format!("{:#?}", err))
-                })?;
-
-            Ok(recipes)
-        }
-        Err(err) => Err(DaoError::DatabaseError(format!("{:#?}", err)))
+    find_options.projection = Some(Recipe::default_projection_no_image());
+    find_options.skip = Some(listing.skip as i64);
+    find_options.limit = listing.limit.map(|limit| limit as i64);
+    find_options.sort = listing.sort.as_ref().map(sort_document);
+
+    let mut query = filter.to_document();
+    if let (Some(sort), Some(after)) = (&listing.sort, &listing.after) {
+        query = doc! { "$and": [query, after_document(sort, after)] };
+    }
+
+    let cursor = collection.find(query, find_options).await
+        .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?;
+
+    Ok(cursor
+        .map(|document| {
+            let document = document.map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?;
+            let id = document_id(&document);
+            Recipe::try_from(document).map_err(|err| DaoError::UnreadableRecipe(UnreadableRecipe { id, error: err.error }))
+        })
+        .boxed())
+}
+
+fn field_name(field: SortField) -> &'static str {
+    match field {
+        SortField::Title => "title",
+        SortField::Created => "created",
+        SortField::LastModified => "last_modified",
+        SortField::CookingTime => "cookingTimeInMinutes",
+        SortField::DefaultServings => "defaultServings",
+    }
+}
+
+fn sort_value(field: SortField, value: &SortValue) -> Bson {
+    match (field, value) {
+        (SortField::Created, SortValue::Integer(millis)) | (SortField::LastModified, SortValue::Integer(millis)) =>
+            Bson::DateTime(Utc.timestamp_millis(*millis)),
+        (_, SortValue::Integer(value)) => Bson::Int64(*value),
+        (_, SortValue::Text(value)) => Bson::String(value.clone()),
+    }
+}
+
+/// ties are ordered by _id
+fn sort_document(sort: &Sort) -> Document {
+    let mut document = Document::new();
+    for key in &sort.keys {
+        document.insert(field_name(key.field), if key.descending { -1 } else { 1 });
     }
+    document.insert("_id", 1);
+    document
+}
+
+/// recipes behind the cursor: (a > x) or (a = x and b < y) or ... or (a = x and b = y and _id > id)
+fn after_document(sort: &Sort, after: &Cursor) -> Document {
+    let equal = |keys: &[SortKey]| {
+        let mut document = Document::new();
+        for (key, value) in keys.iter().zip(&after.values) {
+            document.insert(field_name(key.field), sort_value(key.field, value));
+        }
+        document
+    };
+
+    let mut alternatives: Vec<Bson> = vec![];
+    for (i, key) in sort.keys.iter().enumerate() {
+        let mut alternative = equal(&sort.keys[..i]);
+        let operator = if key.descending { "$lt" } else { "$gt" };
+        let mut comparison = Document::new();
+        comparison.insert(operator, sort_value(key.field, &after.values[i]));
+        alternative.insert(field_name(key.field), comparison);
+        alternatives.push(Bson::Document(alternative));
+    }
+    let mut alternative = equal(&sort.keys);
+    alternative.insert("_id", doc! { "$gt": after.id.clone() });
+    alternatives.push(Bson::Document(alternative));
+    doc! { "$or": alternatives }
 }
 
 
 #[cfg(test)]
 pub mod dao_tests {
-    use bson::Bson;
+    use bson::{Bson, Document};
     use bson::oid::ObjectId;
-    use chrono::{Duration, Timelike};
+    use chrono::{Duration, TimeZone, Timelike};
     use chrono::Utc;
     use log::LevelFilter;
     use mongodb::{Client, Database};
@@ -318,14 +460,19 @@ pub mod dao_tests {
     use serial_test::serial;
     use simplelog::{Config, TerminalMode, TermLogger};
 
-    use crate::dao::{Dao, DaoError};
+    use crate::dao::Dao;
+    use crate::image_store::gridfs::GridFsImageStore;
+    use crate::image_store::image_store_tests;
+    use crate::migration;
     use crate::model::difficulty::Difficulty;
     use crate::model::recipe::Recipe;
-    use crate::pagination::Pagination;
+    use crate::pagination::{Listing, Pagination};
+    use crate::store::{DaoError, RecipeStore, Resource, store_tests};
 
     const TEST_URL: &str = "mongodb://localhost:26666";
     const TEST_APP_NAME: &str = "Zellinotes development recipes";
     const TEST_DATABASE: &str = "test_zellinotes_development_recipes";
+    const TEST_COLLECTION: &str = "recipes";
 
     pub fn create_one_recipe_without_image() -> Recipe {
         Recipe {
@@ -342,6 +489,7 @@ pub mod dao_tests {
             image_base64: None,
             instructions: vec![],
             default_servings: 1,
+            gallery: vec![],
         }
     }
 
@@ -352,7 +500,7 @@ pub mod dao_tests {
     }
 
     pub fn create_many_recipes_without_images(amount: i32) -> Vec<Recipe> {
-        (0..amount).into_iter().map(|i| {
+        (0..amount).map(|i| {
             let mut x = create_one_recipe_without_image();
             x.title = i.to_string();
             x.created = Utc::now().with_nanosecond(0).unwrap() + Duration::days(1);
@@ -370,15 +518,15 @@ pub mod dao_tests {
 
     pub async fn before() -> Dao {
         init_test_logger();
-        let dao = Dao { database: init_test_database().await.unwrap() };
+        let dao = Dao { database: init_test_database().await.unwrap(), recipe_collection: TEST_COLLECTION.to_string() };
         cleanup_after(dao).await;
-        Dao { database: init_test_database().await.unwrap() }
+        Dao { database: init_test_database().await.unwrap(), recipe_collection: TEST_COLLECTION.to_string() }
     }
 
     fn init_test_logger() {
         let _ = TermLogger::init(LevelFilter::Info,
                                  Config::default(),
-                                 TerminalMode::Mixed).unwrap_or_else(|_| ());
+                                 TerminalMode::Mixed);
     }
 
     pub async fn cleanup_after(dao: Dao) {
@@ -422,7 +570,7 @@ pub mod dao_tests {
         recipe.title = "new".to_string();
         recipe.image_base64 = Some("new_image".to_string());
 
-        let result = dao.update_recipe_ignore_image(recipe_id.clone(), recipe.clone()).await;
+        let result = dao.update_recipe_ignore_image(recipe_id.clone(), recipe.clone(), recipe.version).await;
         assert!(result.is_ok());
 
         let result = dao.get_one_recipe_without_image(recipe_id.clone()).await;
@@ -432,8 +580,8 @@ pub mod dao_tests {
         let result = dao.get_one_recipe_image(recipe_id).await;
         assert_eq!(result.unwrap().as_str(), "image");
 
-        let result = dao.update_recipe_ignore_image(ObjectId::new(), recipe.clone()).await;
-        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound);
+        let result = dao.update_recipe_ignore_image(ObjectId::new(), recipe.clone(), recipe.version).await;
+        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
 
         cleanup_after(dao).await;
     }
@@ -441,24 +589,9 @@ pub mod dao_tests {
 
     #[actix_rt::test]
     #[serial]
-    async fn update_one_recipe_image_test() {
+    async fn update_gallery_sets_image() {
         let dao = before().await;
-        let recipe = create_one_recipe_with_image();
-
-        let result = dao.insert_recipe(recipe.clone()).await.unwrap();
-        let recipe_id = result.as_object_id().unwrap().to_owned();
-        let result = dao.update_one_recipe_image(recipe_id.clone(), Some("new_image".to_string())).await;
-        assert!(result.is_ok());
-
-        let result = dao.get_one_recipe_image(recipe_id.clone()).await;
-        assert_eq!(result.unwrap(), "new_image".to_string());
-
-        let result = dao.update_one_recipe_image(recipe_id.clone(),None).await;
-        assert!(result.is_ok());
-
-        let result = dao.get_one_recipe_image(recipe_id.clone()).await;
-        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound);
-
+        store_tests::update_gallery_sets_image(&dao).await;
         cleanup_after(dao).await;
     }
 
@@ -477,7 +610,7 @@ pub mod dao_tests {
         assert!(result.is_ok());
 
         let result = dao.get_one_recipe_image(recipe_id.clone()).await;
-        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound);
+        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
 
         cleanup_after(dao).await;
     }
@@ -517,7 +650,7 @@ pub mod dao_tests {
         assert_eq!(recipe_found.unwrap().image_base64, None);
 
         let doc_with_wrong_id_not_found = dao.get_one_recipe_without_image(ObjectId::new()).await;
-        assert_eq!(doc_with_wrong_id_not_found.err().unwrap(), DaoError::DocumentNotFound);
+        assert_eq!(doc_with_wrong_id_not_found.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
 
         cleanup_after(dao).await;
     }
@@ -538,7 +671,7 @@ pub mod dao_tests {
         assert_eq!(image.unwrap(), "image");
 
         let doc_with_wrong_id_not_found = dao.get_one_recipe_without_image(ObjectId::new()).await;
-        assert_eq!(doc_with_wrong_id_not_found.err().unwrap(), DaoError::DocumentNotFound);
+        assert_eq!(doc_with_wrong_id_not_found.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
 
         cleanup_after(dao).await;
     }
@@ -555,7 +688,7 @@ pub mod dao_tests {
         assert!(result.is_ok());
 
         let result = dao.delete_one_recipe(ObjectId::new()).await;
-        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound);
+        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
 
         cleanup_after(dao).await;
     }
@@ -571,7 +704,7 @@ pub mod dao_tests {
         assert!(result.clone().is_ok());
         assert_eq!(result.clone().unwrap().as_array().unwrap().len(), recipes.clone().len());
 
-        let read_recipes = dao.get_many_recipes(None).await.unwrap();
+        let read_recipes = dao.get_many_recipes(&Listing::default()).await.unwrap();
         assert_eq!(amount_of_recipes, read_recipes.len());
 
         cleanup_after(dao).await;
@@ -613,23 +746,195 @@ pub mod dao_tests {
         Ok(())
     }
 
+    #[actix_rt::test]
+    #[serial]
+    async fn insert_and_get_recipe() {
+        let dao = before().await;
+        store_tests::insert_and_get_recipe(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn update_recipe_ignores_image() {
+        let dao = before().await;
+        store_tests::update_recipe_ignores_image(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn delete_recipe() {
+        let dao = before().await;
+        store_tests::delete_recipe(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn add_and_get_many_recipes() {
+        let dao = before().await;
+        store_tests::add_and_get_many_recipes(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn get_paged_recipes() {
+        let dao = before().await;
+        store_tests::get_paged_recipes(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn keeps_ingredients_tags_and_instructions_in_order() {
+        let dao = before().await;
+        store_tests::keeps_recipe_content(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn get_filtered_recipes() {
+        let dao = before().await;
+        store_tests::filter_recipes(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn sort_and_follow_cursor() {
+        let dao = before().await;
+        store_tests::sort_and_follow_cursor(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn update_checks_version() {
+        let dao = before().await;
+        store_tests::update_checks_version(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn update_keeps_created() {
+        let dao = before().await;
+        store_tests::update_keeps_created(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn put_and_delete_units() {
+        let dao = before().await;
+        store_tests::put_and_delete_units(&dao).await;
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn gridfs_images() {
+        let dao = before().await;
+        let images = GridFsImageStore::new(dao.database.clone(), "images").await;
+        image_store_tests::put_and_get(&images).await;
+        assert_eq!(dao.database.collection("images.chunks").count_documents(None, None).await.unwrap(), 3);
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn migrate_recipes() {
+        let dao = before().await;
+        let created = Utc.timestamp_millis(1_600_000_000_000);
+        let later = Utc.timestamp_millis(1_600_000_100_000);
+        let mut wire_name = Document::from(create_one_recipe_without_image());
+        wire_name.remove("last_modified");
+        wire_name.insert("lastModified", "2020-09-13T12:28:20+00:00");
+        let mut both = Document::from(create_one_recipe_without_image());
+        both.insert("last_modified", created);
+        both.insert("lastModified", later);
+        both.insert("version", 2_i64);
+        let mut missing = Document::from(create_one_recipe_without_image());
+        missing.remove("last_modified");
+        missing.remove("tags");
+        missing.insert("created", created);
+        let mut broken = Document::from(create_one_recipe_without_image());
+        broken.insert("cookingTimeInMinutes", "a while");
+        let broken_id = broken.get_object_id("_id").unwrap().to_hex();
+        dao.recipes().insert_many(vec![wire_name, both, missing, broken], None).await.unwrap();
+
+        assert_eq!(migration::schema_version(&dao).await.unwrap(), 0);
+        let reports = migration::migrate(&dao).await.unwrap();
+        assert_eq!(reports.iter().map(|report| report.converted).collect::<Vec<_>>(), vec![3, 1, 1]);
+        assert_eq!(reports[1].failed.iter().map(|failed| failed.id.as_str()).collect::<Vec<_>>(), vec![broken_id.as_str()]);
+        assert_eq!(migration::schema_version(&dao).await.unwrap(), migration::latest_version());
+        assert_eq!(migration::migrate(&dao).await.unwrap(), vec![]);
+
+        let report = dao.scan_recipes().await.unwrap();
+        assert_eq!(report.scanned, 4);
+        assert_eq!(report.unreadable.iter().map(|unreadable| unreadable.id.as_str()).collect::<Vec<_>>(), vec![broken_id.as_str()]);
+        assert_eq!(report.unreadable[0].error, "Error getting cooking timefrom document");
+        dao.delete_one_recipe(ObjectId::with_string(&broken_id).unwrap()).await.unwrap();
+        let recipes = dao.get_many_recipes(&Listing::default()).await.unwrap();
+        assert_eq!(recipes.iter().map(|recipe| recipe.last_modified).collect::<Vec<_>>(), vec![later, later, created]);
+        assert_eq!(recipes[1].version, 2);
+
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn migrate_keeps_quantities() {
+        let dao = before().await;
+        let mut quantities = Document::from(create_one_recipe_without_image());
+        quantities.insert("ingredients", vec![
+            Bson::Document(doc! { "id": "0", "amount": "½", "title": "Zitrone", "measurementUnit": "Piece" }),
+            Bson::Document(doc! { "id": "1", "amount": { "min": 2, "max": 3 }, "title": "Eier", "measurementUnit": "Piece" }),
+            Bson::Document(doc! { "id": "2", "amount": "to taste", "title": "Salz", "measurementUnit": "Prise" }),
+            Bson::Document(doc! { "id": "3", "amount": Bson::Null, "title": "Öl", "measurementUnit": "Essloeffel" }),
+        ]);
+        dao.recipes().insert_one(quantities, None).await.unwrap();
+
+        let reports = migration::migrate(&dao).await.unwrap();
+        assert!(reports.iter().all(|report| report.failed.is_empty()));
+        assert_eq!(dao.scan_recipes().await.unwrap().unreadable, vec![]);
+
+        cleanup_after(dao).await;
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn stamp_new_collection() {
+        let dao = before().await;
+        assert!(migration::stamp_new_collection(&dao).await.unwrap());
+        assert_eq!(migration::schema_version(&dao).await.unwrap(), migration::latest_version());
+        assert!(!migration::stamp_new_collection(&dao).await.unwrap());
+        assert_eq!(migration::migrate(&dao).await.unwrap(), vec![]);
+
+        cleanup_after(dao).await;
+    }
+
 
     async fn get_paged_recipes_test(dao: &Dao, mut recipes_to_insert: Vec<Recipe>, page: usize, items: usize, sorting: i32) {
         let result = dao.add_many_recipes(recipes_to_insert.clone()).await;
         assert!(result.clone().is_ok());
         assert_eq!(result.clone().unwrap().as_array().unwrap().len(), recipes_to_insert.clone().len());
 
-        let read_recipes = dao.get_many_recipes(Some(Pagination {
+        let read_recipes = dao.get_many_recipes(&Pagination {
             page: Some(page),
             items: Some(items),
             sorting: Some(sorting),
-        })).await.unwrap();
+            ..Default::default()
+        }.listing().unwrap()).await.unwrap();
         let read_recipes: Vec<Recipe> = read_recipes.into_iter().map(|mut r| {
             r._id = ObjectId::with_bytes([0; 12]);
             r
         }).collect();
 
-        recipes_to_insert.sort_by(|l, r| l.created.cmp(&r.created));
+        recipes_to_insert.sort_by_key(|recipe| recipe.created);
 
         let recipes_to_insert: Vec<Recipe> = recipes_to_insert
             .into_iter()
@@ -0,0 +1,305 @@
+use std::fmt;
+
+use actix_web::{HttpResponse, ResponseError};
+use actix_web::error::{JsonPayloadError, PayloadError, QueryPayloadError};
+use actix_web::http::{header, StatusCode};
+use serde::Serialize;
+
+use crate::model::recipe::{Recipe, RecipeFormatError};
+use crate::pagination::ParameterError;
+use crate::store::{DaoError, Resource};
+use crate::validation::Violation;
+
+pub const PROBLEM_MEDIA_TYPE: &str = "application/problem+json";
+
+/// Every error the API answers with. Rendered as RFC 7807 problem details,
+/// the `RequestId` middleware adds the id of the request.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ApiError {
+    status: StatusCode,
+    problem: &'static str,
+    title: &'static str,
+    pub detail: String,
+    pub field: Option<String>,
+    /// boxed, only few problems carry more than the detail
+    members: Option<Box<Members>>,
+    request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Members {
+    Violations(Vec<Violation>),
+    /// the stored recipe a stale update conflicts with
+    Current(Recipe),
+}
+
+#[derive(Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    problem_type: String,
+    title: &'a str,
+    status: u16,
+    detail: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    violations: &'a [Violation],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<&'a Recipe>,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'a str>,
+}
+
+impl ApiError {
+    /// `problem` names the kind of error in the problem type, `title` is the same for every occurrence.
+    pub fn new(status: StatusCode, problem: &'static str, title: &'static str, detail: impl Into<String>) -> Self {
+        ApiError { status, problem, title, detail: detail.into(), field: None, members: None, request_id: None }
+    }
+
+    /// path of the offending query parameter or JSON field, like `ingredients[0].amount`
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    pub fn invalid_id(id: &str) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid-id", "Invalid recipe id",
+                      format!("'{}' is no recipe id, expected 24 hexadecimal characters", id))
+            .with_field("id")
+    }
+
+    /// one problem listing every violated rule, `field` is only set when there is just one
+    pub fn invalid_recipe(violations: Vec<Violation>) -> Self {
+        ApiError::invalid("invalid-recipe", "Invalid recipe", "recipe", violations)
+    }
+
+    pub fn invalid_unit(violations: Vec<Violation>) -> Self {
+        ApiError::invalid("invalid-unit", "Invalid unit", "unit", violations)
+    }
+
+    pub fn unit_not_found() -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, "unit-not-found", "Unit not found",
+                      "There is no unit with this id")
+    }
+
+    fn invalid(problem: &'static str, title: &'static str, name: &str, violations: Vec<Violation>) -> Self {
+        let detail = match violations.as_slice() {
+            [violation] => violation.message.clone(),
+            _ => format!("The {} violates {} rules, see violations", name, violations.len())
+        };
+        let field = match violations.as_slice() {
+            [violation] => violation.field.clone(),
+            _ => None
+        };
+        let members = Some(Box::new(Members::Violations(violations)));
+        ApiError { field, members, ..ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, problem, title, detail) }
+    }
+
+    pub fn version_conflict(current: Box<Recipe>) -> Self {
+        let detail = format!("The recipe was changed in the meantime, the current version is {}", current.version);
+        ApiError {
+            members: Some(Box::new(Members::Current(*current))),
+            ..ApiError::new(StatusCode::PRECONDITION_FAILED, "version-conflict", "Version conflict", detail)
+        }
+    }
+
+    pub fn recipe_not_found() -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, "recipe-not-found", "Recipe not found",
+                      "There is no recipe with this id")
+    }
+
+    pub fn image_not_found() -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, "image-not-found", "Image not found",
+                      "There is no image stored for this recipe")
+    }
+
+    pub fn internal(detail: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal-error", "Internal server error", detail)
+    }
+
+    fn violations(&self) -> &[Violation] {
+        match self.members.as_deref() {
+            Some(Members::Violations(violations)) => violations,
+            _ => &[]
+        }
+    }
+
+    fn current(&self) -> Option<&Recipe> {
+        match self.members.as_deref() {
+            Some(Members::Current(current)) => Some(current),
+            _ => None
+        }
+    }
+
+    fn payload_too_large() -> Self {
+        ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "payload-too-large", "Payload too large",
+                      "The body exceeds the configured size limit")
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.title, self.detail)?;
+        if let Some(field) = &self.field {
+            write!(f, " (field {})", field)?;
+        }
+        Ok(())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem {
+            problem_type: format!("urn:zellinotes:problem:{}", self.problem),
+            title: self.title,
+            status: self.status.as_u16(),
+            detail: &self.detail,
+            field: self.field.as_deref(),
+            violations: self.violations(),
+            current: self.current(),
+            request_id: self.request_id.as_deref(),
+        };
+        let mut response = HttpResponse::build(self.status);
+        if let Some(current) = self.current() {
+            response.header(header::ETAG, current.etag());
+        }
+        response.content_type(PROBLEM_MEDIA_TYPE)
+            .body(serde_json::to_string(&problem).unwrap_or_default())
+    }
+}
+
+/// Details of database errors are logged, not sent to the client.
+impl From<DaoError> for ApiError {
+    fn from(error: DaoError) -> Self {
+        match error {
+            DaoError::DocumentNotFound(Resource::Recipe) => ApiError::recipe_not_found(),
+            DaoError::DocumentNotFound(Resource::Image) => ApiError::image_not_found(),
+            DaoError::DocumentNotFound(Resource::Unit) => ApiError::unit_not_found(),
+            DaoError::DatabaseError(err) => {
+                error!("Database error={}", err);
+                ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "database-unavailable", "Database unavailable",
+                              "The recipe database could not be reached, try again later")
+            }
+            DaoError::RecipeFormatError(err) => {
+                error!("Stored recipe is invalid, error={}", err);
+                ApiError::internal("A stored recipe could not be read")
+            }
+            DaoError::ImageStoreError(err) => {
+                error!("Image store error={}", err);
+                ApiError::internal("The image could not be read or stored")
+            }
+            DaoError::VersionConflict(current) => ApiError::version_conflict(current),
+            DaoError::UnreadableRecipe(unreadable) => {
+                error!("Stored recipe is invalid, id={}, error={}", unreadable.id, unreadable.error);
+                ApiError::internal("A stored recipe could not be read")
+            }
+        }
+    }
+}
+
+impl From<RecipeFormatError> for ApiError {
+    fn from(error: RecipeFormatError) -> Self {
+        ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid-recipe", "Invalid recipe", error.error)
+    }
+}
+
+impl From<ParameterError> for ApiError {
+    fn from(error: ParameterError) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid-parameter", "Invalid query parameter", error.error)
+            .with_field(error.parameter)
+    }
+}
+
+impl From<QueryPayloadError> for ApiError {
+    fn from(error: QueryPayloadError) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid-parameter", "Invalid query parameter", error.to_string())
+    }
+}
+
+impl From<PayloadError> for ApiError {
+    fn from(error: PayloadError) -> Self {
+        match error {
+            PayloadError::Overflow => ApiError::payload_too_large(),
+            error => ApiError::new(StatusCode::BAD_REQUEST, "invalid-body", "Invalid request body", error.to_string())
+        }
+    }
+}
+
+impl From<JsonPayloadError> for ApiError {
+    fn from(error: JsonPayloadError) -> Self {
+        match error {
+            JsonPayloadError::Overflow => ApiError::payload_too_large(),
+            JsonPayloadError::ContentType => ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported-media-type", "Unsupported media type",
+                "The body has to be sent as application/json"),
+            JsonPayloadError::Deserialize(err) => ApiError::new(
+                StatusCode::BAD_REQUEST, "invalid-json", "Invalid JSON body", err.to_string()),
+            JsonPayloadError::Payload(err) => ApiError::from(err),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod error_tests {
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+    use actix_web::test;
+
+    use crate::error::ApiError;
+    use crate::model::recipe::RecipeFormatError;
+    use crate::pagination::ParameterError;
+    use crate::store::{DaoError, Resource};
+
+    #[actix_rt::test]
+    async fn renders_problem_json() {
+        let error = ApiError::invalid_id("hello").with_request_id("abc");
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/problem+json");
+
+        let body: serde_json::Value = test::read_body_json(test::TestRequest::default()
+            .to_srv_response(response)).await;
+        assert_eq!(body, serde_json::json!({
+            "type": "urn:zellinotes:problem:invalid-id",
+            "title": "Invalid recipe id",
+            "status": 400,
+            "detail": "'hello' is no recipe id, expected 24 hexadecimal characters",
+            "field": "id",
+            "requestId": "abc",
+        }));
+    }
+
+    #[test]
+    fn maps_errors() {
+        let problem = |resource| ApiError::from(DaoError::DocumentNotFound(resource)).problem;
+        assert_eq!(problem(Resource::Recipe), "recipe-not-found");
+        assert_eq!(problem(Resource::Image), "image-not-found");
+        assert_eq!(problem(Resource::Unit), "unit-not-found");
+        assert_eq!(ApiError::from(DaoError::DocumentNotFound(Resource::Image)).status_code(), StatusCode::NOT_FOUND);
+        let error = ApiError::from(DaoError::DatabaseError("connection refused".to_string()));
+        assert_eq!(error.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!error.detail.contains("connection refused"));
+        assert_eq!(ApiError::from(DaoError::RecipeFormatError("x".to_string())).status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        let error = ApiError::from(DaoError::ImageStoreError("No space left on device".to_string()));
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!error.detail.contains("No space"));
+
+        let error = ApiError::from(RecipeFormatError::from("Difficulty 'Extreme' does not match one predefined value"));
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(error.detail.contains("Extreme"));
+
+        let error = ApiError::from(ParameterError::new("tags", "must not be empty"));
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.field.as_deref(), Some("tags"));
+    }
+}
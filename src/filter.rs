@@ -0,0 +1,292 @@
+use std::convert::TryFrom;
+
+use bson::{Bson, doc, Document};
+use chrono::{DateTime, Utc};
+
+use crate::model::difficulty::Difficulty;
+use crate::model::recipe::Recipe;
+use crate::pagination::{Pagination, ParameterError};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum TagMatch {
+    #[default]
+    All,
+    Any,
+}
+
+/// Restricts a recipe listing. Every backend applies it before skip and limit,
+/// an empty filter matches every recipe.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RecipeFilter {
+    /// any of
+    pub difficulties: Vec<Difficulty>,
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    pub min_cooking_time: Option<u32>,
+    pub max_cooking_time: Option<u32>,
+    /// exclusive
+    pub created_after: Option<DateTime<Utc>>,
+    /// inclusive
+    pub modified_since: Option<DateTime<Utc>>,
+    pub has_image: Option<bool>,
+    /// part of an ingredient title, case is ignored
+    pub ingredient: Option<String>,
+}
+
+impl TryFrom<&Pagination> for RecipeFilter {
+    type Error = ParameterError;
+
+    fn try_from(params: &Pagination) -> Result<Self, Self::Error> {
+        let filter = RecipeFilter {
+            difficulties: match &params.difficulty {
+                Some(value) => split_list(value, "difficulty")?.into_iter()
+                    .map(|difficulty| Difficulty::try_from(difficulty.as_str()))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| ParameterError::new("difficulty", "expected a comma separated list of Easy, Medium and Hard"))?,
+                None => vec![]
+            },
+            tags: match &params.tags {
+                Some(value) => split_list(value, "tags")?,
+                None => vec![]
+            },
+            tag_match: match params.tag_match.as_deref() {
+                None | Some("all") => TagMatch::All,
+                Some("any") => TagMatch::Any,
+                Some(_) => return Err(ParameterError::new("tagMatch", "expected all or any"))
+            },
+            min_cooking_time: parse_minutes(&params.min_cooking_time, "minCookingTime")?,
+            max_cooking_time: parse_minutes(&params.max_cooking_time, "maxCookingTime")?,
+            created_after: parse_date(&params.created_after, "createdAfter")?,
+            modified_since: parse_date(&params.modified_since, "modifiedSince")?,
+            has_image: match params.has_image.as_deref() {
+                None => None,
+                Some("true") => Some(true),
+                Some("false") => Some(false),
+                Some(_) => return Err(ParameterError::new("hasImage", "expected true or false"))
+            },
+            ingredient: match params.ingredient.as_deref().map(str::trim) {
+                None => None,
+                Some("") => return Err(ParameterError::new("ingredient", "must not be empty")),
+                Some(ingredient) => Some(ingredient.to_string())
+            },
+        };
+
+        if let (Some(min), Some(max)) = (filter.min_cooking_time, filter.max_cooking_time) {
+            if min > max {
+                return Err(ParameterError::new("minCookingTime", "must not be greater than maxCookingTime"));
+            }
+        }
+        return Ok(filter);
+    }
+}
+
+impl RecipeFilter {
+    pub fn matches(&self, recipe: &Recipe) -> bool {
+        let tag_matches = |tag: &String| recipe.tags.contains(tag);
+        (self.difficulties.is_empty() || self.difficulties.contains(&recipe.difficulty))
+            && (self.tags.is_empty() || match self.tag_match {
+                TagMatch::All => self.tags.iter().all(tag_matches),
+                TagMatch::Any => self.tags.iter().any(tag_matches),
+            })
+            && self.min_cooking_time.is_none_or(|min| recipe.cooking_time_in_minutes >= min)
+            && self.max_cooking_time.is_none_or(|max| recipe.cooking_time_in_minutes <= max)
+            && self.created_after.is_none_or(|date| recipe.created > date)
+            && self.modified_since.is_none_or(|date| recipe.last_modified >= date)
+            && self.has_image.is_none_or(|has_image| recipe.image_base64.is_some() == has_image)
+            && self.ingredient.as_ref().is_none_or(|ingredient| recipe.ingredients.iter()
+                .any(|stored| contains_ignore_case(&stored.title, ingredient)))
+    }
+
+    /// MongoDB query selecting the same recipes as `matches`
+    pub fn to_document(&self) -> Document {
+        let mut query = Document::new();
+        if !self.difficulties.is_empty() {
+            let difficulties: Vec<Bson> = self.difficulties.iter().cloned().map(Bson::from).collect();
+            query.insert("difficulty", doc! { "$in": difficulties });
+        }
+        if !self.tags.is_empty() {
+            let operator = match self.tag_match {
+                TagMatch::All => "$all",
+                TagMatch::Any => "$in",
+            };
+            let mut tags = Document::new();
+            tags.insert(operator, self.tags.clone());
+            query.insert("tags", tags);
+        }
+        let mut cooking_time = Document::new();
+        if let Some(min) = self.min_cooking_time {
+            cooking_time.insert("$gte", min);
+        }
+        if let Some(max) = self.max_cooking_time {
+            cooking_time.insert("$lte", max);
+        }
+        if !cooking_time.is_empty() {
+            query.insert("cookingTimeInMinutes", cooking_time);
+        }
+        if let Some(date) = self.created_after {
+            query.insert("created", doc! { "$gt": date });
+        }
+        if let Some(date) = self.modified_since {
+            query.insert("last_modified", doc! { "$gte": date });
+        }
+        match self.has_image {
+            Some(true) => query.insert("image", doc! { "$ne": Bson::Null }),
+            Some(false) => query.insert("image", Bson::Null),
+            None => None
+        };
+        if let Some(ingredient) = &self.ingredient {
+            query.insert("ingredients.title", doc! { "$regex": escape_regex(ingredient), "$options": "i" });
+        }
+        return query;
+    }
+}
+
+pub fn contains_ignore_case(text: &str, part: &str) -> bool {
+    text.to_lowercase().contains(&part.to_lowercase())
+}
+
+fn split_list(value: &str, parameter: &'static str) -> Result<Vec<String>, ParameterError> {
+    let values: Vec<String> = value.split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .collect();
+    if values.is_empty() {
+        return Err(ParameterError::new(parameter, "must not be empty"));
+    }
+    return Ok(values);
+}
+
+fn parse_minutes(value: &Option<String>, parameter: &'static str) -> Result<Option<u32>, ParameterError> {
+    value.as_ref()
+        .map(|value| value.trim().parse::<u32>()
+            .map_err(|_| ParameterError::new(parameter, "expected a whole number of minutes")))
+        .transpose()
+}
+
+/// A '+' of the offset arrives as space when the client did not encode it.
+fn parse_date(value: &Option<String>, parameter: &'static str) -> Result<Option<DateTime<Utc>>, ParameterError> {
+    value.as_ref()
+        .map(|value| DateTime::parse_from_rfc3339(&value.trim().replace(' ', "+"))
+            .map(|date| date.with_timezone(&Utc))
+            .map_err(|_| ParameterError::new(parameter, "expected an RFC 3339 date like 2020-09-11T12:21:21Z")))
+        .transpose()
+}
+
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    return escaped;
+}
+
+
+#[cfg(test)]
+mod filter_tests {
+    use std::convert::TryFrom;
+
+    use actix_web::web::Query;
+    use bson::{Bson, doc};
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::dao::dao_tests::create_one_recipe_without_image;
+    use crate::filter::{escape_regex, RecipeFilter, TagMatch};
+    use crate::model::difficulty::Difficulty;
+    use crate::model::ingredients::Ingredient;
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::pagination::{Pagination, ParameterError};
+
+    fn parse(query: &str) -> Result<RecipeFilter, ParameterError> {
+        let params = Query::<Pagination>::from_query(query).unwrap();
+        RecipeFilter::try_from(&params.into_inner())
+    }
+
+    fn invalid_parameter(query: &str) -> &'static str {
+        parse(query).err().unwrap().parameter
+    }
+
+    #[test]
+    fn parses_filters() {
+        assert_eq!(parse("").unwrap(), RecipeFilter::default());
+
+        let filter = parse("difficulty=Easy,Hard&tags=vegan,%20schnell&tagMatch=any&minCookingTime=5&maxCookingTime=30\
+            &createdAfter=2020-09-11T12:21:21Z&modifiedSince=2020-09-11T14:21:21+02:00&hasImage=false&ingredient=K%C3%A4se").unwrap();
+        assert_eq!(filter, RecipeFilter {
+            difficulties: vec![Difficulty::Easy, Difficulty::Hard],
+            tags: vec!["vegan".to_string(), "schnell".to_string()],
+            tag_match: TagMatch::Any,
+            min_cooking_time: Some(5),
+            max_cooking_time: Some(30),
+            created_after: Some(Utc.ymd(2020, 9, 11).and_hms(12, 21, 21)),
+            modified_since: Some(Utc.ymd(2020, 9, 11).and_hms(12, 21, 21)),
+            has_image: Some(false),
+            ingredient: Some("Käse".to_string()),
+        });
+    }
+
+    #[test]
+    fn names_the_invalid_parameter() {
+        assert_eq!(invalid_parameter("difficulty=Easy,Extreme"), "difficulty");
+        assert_eq!(invalid_parameter("tags=,"), "tags");
+        assert_eq!(invalid_parameter("tagMatch=some"), "tagMatch");
+        assert_eq!(invalid_parameter("minCookingTime=-1"), "minCookingTime");
+        assert_eq!(invalid_parameter("maxCookingTime=ten"), "maxCookingTime");
+        assert_eq!(invalid_parameter("minCookingTime=20&maxCookingTime=10"), "minCookingTime");
+        assert_eq!(invalid_parameter("createdAfter=yesterday"), "createdAfter");
+        assert_eq!(invalid_parameter("modifiedSince=2020-09-11"), "modifiedSince");
+        assert_eq!(invalid_parameter("hasImage=yes"), "hasImage");
+        assert_eq!(invalid_parameter("ingredient=%20"), "ingredient");
+        assert!(parse("hasImage=yes").err().unwrap().error.contains("'hasImage'"));
+    }
+
+    #[test]
+    fn matches_recipes() {
+        let mut recipe = create_one_recipe_without_image();
+        recipe.difficulty = Difficulty::Medium;
+        recipe.tags = vec!["vegan".to_string(), "schnell".to_string()];
+        recipe.cooking_time_in_minutes = 20;
+        recipe.ingredients = vec![Ingredient::new("0", 1, "Räuchertofu", MeasurementUnit::PIECE)];
+
+        assert!(RecipeFilter::default().matches(&recipe));
+        assert!(parse("difficulty=Easy,Medium").unwrap().matches(&recipe));
+        assert!(!parse("difficulty=Hard").unwrap().matches(&recipe));
+        assert!(parse("tags=vegan,schnell").unwrap().matches(&recipe));
+        assert!(!parse("tags=vegan,party").unwrap().matches(&recipe));
+        assert!(parse("tags=vegan,party&tagMatch=any").unwrap().matches(&recipe));
+        assert!(parse("minCookingTime=20&maxCookingTime=20").unwrap().matches(&recipe));
+        assert!(!parse("maxCookingTime=19").unwrap().matches(&recipe));
+        assert!(parse("ingredient=R%C3%84UCHER").unwrap().matches(&recipe));
+        assert!(!parse("ingredient=tempeh").unwrap().matches(&recipe));
+        assert!(parse("hasImage=false").unwrap().matches(&recipe));
+        assert!(!parse("hasImage=true").unwrap().matches(&recipe));
+
+        let created_after = RecipeFilter { created_after: Some(recipe.created), ..Default::default() };
+        assert!(!created_after.matches(&recipe));
+        let modified_since = RecipeFilter { modified_since: Some(recipe.last_modified), ..Default::default() };
+        assert!(modified_since.matches(&recipe));
+        let modified_since = RecipeFilter { modified_since: Some(recipe.last_modified + Duration::seconds(1)), ..Default::default() };
+        assert!(!modified_since.matches(&recipe));
+    }
+
+    #[test]
+    fn builds_mongo_query() {
+        assert_eq!(RecipeFilter::default().to_document(), doc! {});
+        let query = parse("difficulty=Easy&tags=vegan&tagMatch=any&minCookingTime=5&hasImage=false&ingredient=a.b").unwrap().to_document();
+        assert_eq!(query, doc! {
+            "difficulty": { "$in": ["Easy"] },
+            "tags": { "$in": ["vegan"] },
+            "cookingTimeInMinutes": { "$gte": 5_u32 },
+            "image": Bson::Null,
+            "ingredients.title": { "$regex": "a\\.b", "$options": "i" },
+        });
+    }
+
+    #[test]
+    fn escapes_regex() {
+        assert_eq!(escape_regex("1/2 (frisch)"), "1/2 \\(frisch\\)");
+    }
+}
@@ -0,0 +1,275 @@
+use actix_multipart::Multipart;
+use actix_web::{dev, FromRequest, HttpMessage, HttpRequest};
+use actix_web::error::PayloadError;
+use actix_web::http::StatusCode;
+use actix_web::web::{Bytes, BytesMut};
+use futures_util::future::LocalBoxFuture;
+use futures_util::stream::{Stream, StreamExt};
+use serde::Deserialize;
+
+use crate::error::ApiError;
+use crate::image_store::variants::ImageSize;
+
+/// the form field that holds the image in a multipart upload
+const MULTIPART_FIELD: &str = "image";
+
+/// The image formats recipes may have, recognised by their first bytes and not by what the client claims.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn sniff(bytes: &[u8]) -> Option<ImageFormat> {
+        match bytes {
+            [0xFF, 0xD8, 0xFF, ..] => Some(ImageFormat::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(ImageFormat::Png),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageFormat::Webp),
+            _ => None
+        }
+    }
+
+    pub fn media_type(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<ImageFormat> {
+        match media_type {
+            "image/jpeg" | "image/jpg" => Some(ImageFormat::Jpeg),
+            "image/png" => Some(ImageFormat::Png),
+            "image/webp" => Some(ImageFormat::Webp),
+            _ => None
+        }
+    }
+}
+
+/// Largest accepted image in bytes, registered with `app_data`.
+#[derive(Clone)]
+pub struct ImageConfig {
+    pub limit: usize,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        ImageConfig { limit: 5 << 20 }
+    }
+}
+
+/// `GET /recipes/{id}/image?encoding=base64` answers the base64 text older clients expect,
+/// `size` a smaller variant instead of the image as uploaded
+#[derive(Deserialize, Debug, Default)]
+pub struct ImageQuery {
+    pub encoding: Option<String>,
+    pub size: Option<ImageSize>,
+}
+
+impl ImageQuery {
+    pub fn wants_base64(&self) -> bool {
+        self.encoding.as_deref().is_some_and(|encoding| encoding.eq_ignore_ascii_case("base64"))
+    }
+}
+
+/// An uploaded image whose bytes are a JPEG, PNG or WebP. Accepts the raw bytes with their
+/// media type, `multipart/form-data` with the file in the field `image`, or for older clients
+/// the base64 text, also as data URL, with `text/plain` or without content type.
+#[derive(Debug)]
+pub struct ImageUpload {
+    pub bytes: Vec<u8>,
+    pub format: ImageFormat,
+}
+
+impl ImageUpload {
+    fn new(bytes: Vec<u8>, declared: Option<ImageFormat>) -> Result<Self, ApiError> {
+        let format = ImageFormat::sniff(&bytes)
+            .ok_or_else(|| unsupported_image("the image is no JPEG, PNG or WebP"))?;
+        match declared {
+            Some(declared) if declared != format => Err(unsupported_image(
+                &format!("the image was sent as {} but is {}", declared.media_type(), format.media_type()))),
+            _ => Ok(ImageUpload { bytes, format })
+        }
+    }
+
+    /// base64 text, also as data URL
+    pub fn from_base64(text: &[u8]) -> Result<Self, ApiError> {
+        let text: Vec<u8> = text.iter().copied().filter(|c| !c.is_ascii_whitespace()).collect();
+        // data:image/png;base64,iVBOR...
+        let encoded = match text.iter().position(|&c| c == b',') {
+            Some(index) if text.starts_with(b"data:") => &text[index + 1..],
+            _ => &text[..]
+        };
+        let bytes = base64::decode(encoded)
+            .map_err(|err| invalid_image(&format!("the body is no image and no base64 text, {}", err)))?;
+        ImageUpload::new(bytes, None)
+    }
+}
+
+impl FromRequest for ImageUpload {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, ApiError>>;
+    type Config = ImageConfig;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let limit = req.app_data::<ImageConfig>().map_or(ImageConfig::default().limit, |config| config.limit);
+        let media_type = match req.mime_type() {
+            Ok(mime) => mime.map(|mime| mime.essence_str().to_lowercase()),
+            Err(_) => return Box::pin(async { Err(unsupported_image("the content type cannot be read")) })
+        };
+        let body = dev::Decompress::from_headers(payload.take(), req.headers());
+
+        match media_type.as_deref() {
+            Some("multipart/form-data") => {
+                let multipart = Multipart::new(req.headers(), body);
+                Box::pin(from_multipart(multipart, limit))
+            }
+            Some(media_type) if media_type.starts_with("image/") => {
+                let declared = ImageFormat::from_media_type(media_type);
+                let media_type = media_type.to_string();
+                Box::pin(async move {
+                    let declared = declared.ok_or_else(|| unsupported_image(
+                        &format!("{} is not supported, use image/jpeg, image/png or image/webp", media_type)))?;
+                    let bytes = read_limited(body, limit, ApiError::from).await?;
+                    ImageUpload::new(bytes.to_vec(), Some(declared))
+                })
+            }
+            None | Some("text/plain") => Box::pin(async move {
+                let text = read_limited(body, limit, ApiError::from).await?;
+                ImageUpload::from_base64(&text)
+            }),
+            Some(media_type) => {
+                let detail = format!("{} is not supported, send image bytes, multipart/form-data or base64 text", media_type);
+                Box::pin(async move { Err(unsupported_image(&detail)) })
+            }
+        }
+    }
+}
+
+/// the field `image`, or else the first file of the form
+async fn from_multipart(mut multipart: Multipart, limit: usize) -> Result<ImageUpload, ApiError> {
+    while let Some(field) = multipart.next().await {
+        let field = field.map_err(|err| invalid_image(&format!("the form cannot be read, {}", err)))?;
+        let disposition = field.content_disposition();
+        let is_image = disposition.as_ref().is_some_and(|disposition|
+            disposition.get_name() == Some(MULTIPART_FIELD) || disposition.get_filename().is_some());
+        let declared = ImageFormat::from_media_type(field.content_type().essence_str());
+        let bytes = read_limited(field, limit, |err| invalid_image(&format!("the form cannot be read, {}", err))).await?;
+        if is_image {
+            return ImageUpload::new(bytes.to_vec(), declared);
+        }
+    }
+    Err(invalid_image(&format!("the form has no file in the field '{}'", MULTIPART_FIELD)))
+}
+
+async fn read_limited<S, E, F>(mut body: S, limit: usize, map_err: F) -> Result<BytesMut, ApiError>
+    where S: Stream<Item=Result<Bytes, E>> + Unpin,
+          F: Fn(E) -> ApiError {
+    let mut bytes = BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(&map_err)?;
+        if bytes.len() + chunk.len() > limit {
+            return Err(ApiError::from(PayloadError::Overflow));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+fn unsupported_image(detail: &str) -> ApiError {
+    ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported-image", "Unsupported image", detail.to_string())
+}
+
+pub fn invalid_image(detail: &str) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid-image", "Invalid image", detail.to_string())
+}
+
+
+#[cfg(test)]
+pub mod image_tests {
+    use actix_web::FromRequest;
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+    use actix_web::test::TestRequest;
+
+    use crate::error::ApiError;
+    use crate::image::{ImageConfig, ImageFormat, ImageUpload};
+
+    pub const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 13, b'I', b'H', b'D', b'R'];
+    pub const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0, 16, b'J', b'F', b'I', b'F', 0];
+
+    async fn upload(request: TestRequest) -> Result<ImageUpload, ApiError> {
+        let (req, mut payload) = request.to_http_parts();
+        ImageUpload::from_request(&req, &mut payload).await
+    }
+
+    #[test]
+    fn sniffs_formats() {
+        assert_eq!(ImageFormat::sniff(PNG), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::sniff(JPEG), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::sniff(b"RIFF\x24\0\0\0WEBPVP8 "), Some(ImageFormat::Webp));
+        assert_eq!(ImageFormat::sniff(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(ImageFormat::sniff(b"GIF89a"), None);
+        assert_eq!(ImageFormat::sniff(&[]), None);
+    }
+
+    #[actix_rt::test]
+    async fn accepts_image_bytes() {
+        let image = upload(TestRequest::put().header("content-type", "image/png").set_payload(PNG)).await.unwrap();
+        assert_eq!(image.format, ImageFormat::Png);
+        assert_eq!(image.bytes, PNG);
+
+        let error = upload(TestRequest::put().header("content-type", "image/jpeg").set_payload(PNG)).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(error.detail, "the image was sent as image/jpeg but is image/png");
+
+        let error = upload(TestRequest::put().header("content-type", "image/gif").set_payload("GIF89a")).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let error = upload(TestRequest::put().header("content-type", "image/png")
+            .app_data(ImageConfig { limit: 8 }).set_payload(PNG)).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_rt::test]
+    async fn accepts_base64() {
+        let image = upload(TestRequest::put().set_payload(base64::encode(JPEG))).await.unwrap();
+        assert_eq!(image.format, ImageFormat::Jpeg);
+
+        let data_url = format!("data:image/png;base64,{}\n", base64::encode(PNG));
+        let image = upload(TestRequest::put().header("content-type", "text/plain").set_payload(data_url)).await.unwrap();
+        assert_eq!(image.bytes, PNG);
+
+        let error = upload(TestRequest::put().set_payload("image")).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        let error = upload(TestRequest::put().set_payload(base64::encode("plain text"))).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[actix_rt::test]
+    async fn accepts_multipart() {
+        let mut body = b"--boundary\r\nContent-Disposition: form-data; name=\"caption\"\r\n\r\nPasta\r\n\
+            --boundary\r\nContent-Disposition: form-data; name=\"image\"; filename=\"pasta.png\"\r\n\
+            Content-Type: image/png\r\n\r\n".to_vec();
+        body.extend_from_slice(PNG);
+        body.extend_from_slice(b"\r\n--boundary--\r\n");
+        let request = || TestRequest::put().header("content-type", "multipart/form-data; boundary=boundary");
+
+        let image = upload(request().set_payload(body)).await.unwrap();
+        assert_eq!(image.bytes, PNG);
+
+        let error = upload(request().set_payload(
+            "--boundary\r\nContent-Disposition: form-data; name=\"caption\"\r\n\r\nPasta\r\n--boundary--\r\n")).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.detail, "the form has no file in the field 'image'");
+    }
+
+    #[actix_rt::test]
+    async fn rejects_other_content_types() {
+        let error = upload(TestRequest::put().header("content-type", "application/json").set_payload("{}")).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use actix_web::error::BlockingError;
+use actix_web::web;
+use async_trait::async_trait;
+use bson::oid::ObjectId;
+
+use crate::image_store::{ImageStore, is_hash};
+use crate::store::{DaoError, Resource};
+
+/// Images as files below `root`, in directories named after the first two characters of the hash.
+pub struct FileImageStore {
+    root: PathBuf,
+}
+
+impl FileImageStore {
+    pub fn open(root: &Path) -> Result<Self, DaoError> {
+        fs::create_dir_all(root)?;
+        Ok(Self { root: root.to_path_buf() })
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.root.join(&hash[..2]).join(hash)
+    }
+}
+
+#[async_trait]
+impl ImageStore for FileImageStore {
+    async fn put_hashed(&self, hash: &str, bytes: &[u8]) -> Result<(), DaoError> {
+        if !is_hash(hash) {
+            return Err(DaoError::ImageStoreError(format!("'{}' is no image hash", hash)));
+        }
+        let path = self.path(hash);
+        let temporary = path.with_file_name(format!(".{}.{}", hash, ObjectId::new().to_hex()));
+        let bytes = bytes.to_vec();
+        run(move || {
+            if path.exists() {
+                return Ok(());
+            }
+            fs::create_dir_all(path.parent().unwrap())?;
+            // renamed when complete, a reader never sees half an image
+            fs::write(&temporary, &bytes)?;
+            fs::rename(&temporary, &path).map_err(|err| {
+                let _ = fs::remove_file(&temporary);
+                DaoError::from(err)
+            })
+        }).await
+    }
+
+    async fn get(&self, hash: &str) -> Result<Vec<u8>, DaoError> {
+        if !is_hash(hash) {
+            return Err(DaoError::DocumentNotFound(Resource::Image));
+        }
+        let path = self.path(hash);
+        run(move || match fs::read(&path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => Err(DaoError::DocumentNotFound(Resource::Image)),
+            read => read.map_err(DaoError::from)
+        }).await
+    }
+
+    async fn contains(&self, hash: &str) -> Result<bool, DaoError> {
+        if !is_hash(hash) {
+            return Ok(false);
+        }
+        let path = self.path(hash);
+        run(move || Ok(path.is_file())).await
+    }
+}
+
+/// file operations block, they run on the thread pool of actix like the sqlite store
+async fn run<T, F>(f: F) -> Result<T, DaoError>
+    where F: FnOnce() -> Result<T, DaoError> + Send + 'static,
+          T: Send + 'static {
+    web::block(f).await.map_err(|err| match err {
+        BlockingError::Error(err) => err,
+        BlockingError::Canceled => DaoError::ImageStoreError("image file operation was canceled".to_string())
+    })
+}
+
+impl From<std::io::Error> for DaoError {
+    fn from(error: std::io::Error) -> Self {
+        DaoError::ImageStoreError(format!("{:#?}", error))
+    }
+}
+
+
+#[cfg(test)]
+mod file_image_store_tests {
+    use bson::oid::ObjectId;
+
+    use crate::image_store::{hash, image_store_tests, ImageStore};
+    use crate::image_store::filesystem::FileImageStore;
+
+    #[actix_rt::test]
+    async fn put_and_get() {
+        let root = std::env::temp_dir().join(format!("zellinotes-images-{}", ObjectId::new().to_hex()));
+        let images = FileImageStore::open(&root).unwrap();
+        image_store_tests::put_and_get(&images).await;
+
+        let stored = hash(b"abc");
+        images.put(b"abc").await.unwrap();
+        assert!(root.join(&stored[..2]).join(&stored).is_file());
+        assert!(images.put_hashed("../../escape", b"abc").await.is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+use bson::{Binary, Bson, doc};
+use bson::spec::BinarySubtype;
+use futures_util::StreamExt;
+use mongodb::{Collection, Database};
+use mongodb::options::{FindOptions, UpdateOptions};
+
+use crate::image_store::{ImageStore, is_hash};
+use crate::store::{DaoError, now, Resource};
+
+/// the chunk size other GridFS drivers use
+const CHUNK_SIZE: usize = 255 * 1024;
+
+/// Images in a GridFS bucket, `<bucket>.files` and `<bucket>.chunks`, readable by every GridFS tool.
+/// The file id is the hash. The files document is written after the chunks, so a file only exists complete.
+pub struct GridFsImageStore {
+    database: Database,
+    bucket: String,
+}
+
+impl GridFsImageStore {
+    pub async fn new(database: Database, bucket: &str) -> Self {
+        let store = Self { database, bucket: bucket.to_string() };
+        let index = doc! {
+            "createIndexes": store.chunks().name(),
+            "indexes": [{ "key": { "files_id": 1, "n": 1 }, "name": "files_id_1_n_1", "unique": true }],
+        };
+        if let Err(err) = store.database.run_command(index, None).await {
+            warn!("Could not create the index of the image chunks. Err={:#?}", err);
+        }
+        store
+    }
+
+    fn files(&self) -> Collection {
+        self.database.collection(&format!("{}.files", self.bucket))
+    }
+
+    fn chunks(&self) -> Collection {
+        self.database.collection(&format!("{}.chunks", self.bucket))
+    }
+}
+
+#[async_trait]
+impl ImageStore for GridFsImageStore {
+    async fn put_hashed(&self, hash: &str, bytes: &[u8]) -> Result<(), DaoError> {
+        if !is_hash(hash) {
+            return Err(DaoError::ImageStoreError(format!("'{}' is no image hash", hash)));
+        }
+        if self.files().find_one(doc! { "_id": hash }, None).await?.is_some() {
+            return Ok(());
+        }
+
+        // upserts, two uploads of the same image write the same chunks
+        let mut options = UpdateOptions::default();
+        options.upsert = Some(true);
+        for (n, chunk) in bytes.chunks(CHUNK_SIZE).enumerate() {
+            let data = Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes: chunk.to_vec() });
+            self.chunks().update_one(doc! { "files_id": hash, "n": n as i32 },
+                                     doc! { "$set": { "data": data } },
+                                     options.clone()).await?;
+        }
+        let file = doc! {
+            "length": bytes.len() as i64,
+            "chunkSize": CHUNK_SIZE as i32,
+            "uploadDate": now(),
+            "filename": hash,
+        };
+        self.files().update_one(doc! { "_id": hash }, doc! { "$setOnInsert": file }, options).await?;
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Vec<u8>, DaoError> {
+        let file = self.files().find_one(doc! { "_id": hash }, None).await?
+            .ok_or(DaoError::DocumentNotFound(Resource::Image))?;
+        let length = file.get_i64("length")? as usize;
+
+        let mut options = FindOptions::default();
+        options.sort = Some(doc! { "n": 1 });
+        let mut chunks = self.chunks().find(doc! { "files_id": hash }, options).await?;
+        let mut bytes = Vec::with_capacity(length);
+        while let Some(chunk) = chunks.next().await {
+            bytes.extend_from_slice(chunk?.get_binary_generic("data")?);
+        }
+        if bytes.len() != length {
+            return Err(DaoError::ImageStoreError(format!("image {} has {} of {} bytes", hash, bytes.len(), length)));
+        }
+        Ok(bytes)
+    }
+
+    async fn contains(&self, hash: &str) -> Result<bool, DaoError> {
+        Ok(self.files().find_one(doc! { "_id": hash }, None).await?.is_some())
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::image_store::ImageStore;
+use crate::store::{DaoError, Resource};
+
+/// Images of the memory backend, lost on shutdown.
+#[derive(Default)]
+pub struct MemoryImageStore {
+    images: RwLock<HashMap<String, Vec<u8>>>
+}
+
+impl MemoryImageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ImageStore for MemoryImageStore {
+    async fn put_hashed(&self, hash: &str, bytes: &[u8]) -> Result<(), DaoError> {
+        self.images.write()
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?
+            .entry(hash.to_string())
+            .or_insert_with(|| bytes.to_vec());
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Vec<u8>, DaoError> {
+        self.images.read()
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?
+            .get(hash)
+            .cloned()
+            .ok_or(DaoError::DocumentNotFound(Resource::Image))
+    }
+
+    async fn contains(&self, hash: &str) -> Result<bool, DaoError> {
+        Ok(self.images.read()
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?
+            .contains_key(hash))
+    }
+}
+
+
+#[cfg(test)]
+mod memory_image_store_tests {
+    use crate::image_store::image_store_tests;
+    use crate::image_store::memory::MemoryImageStore;
+
+    #[actix_rt::test]
+    async fn put_and_get() {
+        image_store_tests::put_and_get(&MemoryImageStore::new()).await;
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+
+use crate::store::{DaoError, RecipeStore};
+
+pub mod filesystem;
+pub mod gridfs;
+pub mod memory;
+pub mod variants;
+
+/// prefix of the image a recipe refers to, base64 never contains the colon
+const REFERENCE_PREFIX: &str = "sha256:";
+
+/// Keeps image bytes apart from the recipes, addressed by the SHA-256 of their content.
+/// Storing the same bytes twice keeps one copy.
+#[async_trait]
+pub trait ImageStore: Send + Sync {
+    /// returns the reference a recipe keeps, `sha256:<hex>`
+    async fn put(&self, bytes: &[u8]) -> Result<String, DaoError> {
+        let hash = hash(bytes);
+        self.put_hashed(&hash, bytes).await?;
+        Ok(format!("{}{}", REFERENCE_PREFIX, hash))
+    }
+
+    /// stores `bytes` under `hash`, nothing happens when the hash is stored already
+    async fn put_hashed(&self, hash: &str, bytes: &[u8]) -> Result<(), DaoError>;
+
+    /// DocumentNotFound of the image when no image has the hash
+    async fn get(&self, hash: &str) -> Result<Vec<u8>, DaoError>;
+
+    /// whether an image has the hash, backends answer it without reading the image
+    async fn contains(&self, hash: &str) -> Result<bool, DaoError> {
+        match self.get(hash).await {
+            Ok(_) => Ok(true),
+            Err(DaoError::DocumentNotFound(_)) => Ok(false),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+/// lowercase hex SHA-256 of the content
+pub fn hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// whether `hash` looks like one `hash` returned, anything else must not become part of a path or key
+pub fn is_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+/// the hash of a reference, None for images older versions kept inline as base64
+pub fn referenced_hash(image: &str) -> Option<&str> {
+    image.strip_prefix(REFERENCE_PREFIX).filter(|hash| is_hash(hash))
+}
+
+/// Moves the images older versions kept inline as base64 into the image store and leaves a
+/// reference in the recipe. Their metadata is dropped, variants are created when first requested.
+/// Meant to run in the background, a recipe whose image changed in the meantime is left alone.
+/// Returns how many images were moved.
+pub async fn move_inline_images(recipes: Arc<dyn RecipeStore>, images: Arc<dyn ImageStore>) -> Result<usize, DaoError> {
+    let mut moved = 0;
+    for id in recipes.recipes_with_inline_image().await? {
+        let inline = match recipes.get_one_recipe_image(id.clone()).await {
+            Ok(inline) => inline,
+            Err(DaoError::DocumentNotFound(_)) => continue,
+            Err(err) => return Err(err)
+        };
+        let bytes = match base64::decode(inline.trim()) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!("Could not move image of recipe, it is no base64. id={}, err={}", id, err);
+                continue;
+            }
+        };
+        // kept with their metadata when they cannot be decoded, they are served as text
+        let bytes = match variants::clean(bytes.clone()).await {
+            Ok(cleaned) => cleaned,
+            Err(_) => bytes
+        };
+        let reference = images.put(&bytes).await?;
+        if recipes.replace_inline_image(id.clone(), &inline, &reference).await? {
+            moved += 1;
+        }
+    }
+    info!("Moved {} inline images into the image store", moved);
+    Ok(moved)
+}
+
+
+#[cfg(test)]
+pub mod image_store_tests {
+    use std::sync::Arc;
+
+    use crate::dao::dao_tests::{create_one_recipe_with_image, create_one_recipe_without_image};
+    use crate::image_store::{hash, ImageStore, move_inline_images, referenced_hash};
+    use crate::image_store::memory::MemoryImageStore;
+    use crate::store::{DaoError, RecipeStore, Resource};
+    use crate::store::memory::MemoryStore;
+
+    /// the checks every image store runs
+    pub async fn put_and_get(images: &dyn ImageStore) {
+        let bytes: Vec<u8> = (0..600_000).map(|i| (i % 251) as u8).collect();
+        let reference = images.put(&bytes).await.unwrap();
+        assert_eq!(reference, format!("sha256:{}", hash(&bytes)));
+        assert_eq!(images.put(&bytes).await.unwrap(), reference);
+        assert_eq!(images.get(referenced_hash(&reference).unwrap()).await.unwrap(), bytes);
+
+        let empty = images.put(&[]).await.unwrap();
+        assert_eq!(images.get(referenced_hash(&empty).unwrap()).await.unwrap(), Vec::<u8>::new());
+
+        assert_eq!(images.get(&hash(b"unknown")).await, Err(DaoError::DocumentNotFound(Resource::Image)));
+        assert_eq!(images.contains(referenced_hash(&reference).unwrap()).await, Ok(true));
+        assert_eq!(images.contains(&hash(b"unknown")).await, Ok(false));
+    }
+
+    #[test]
+    fn hashes_content() {
+        assert_eq!(hash(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(referenced_hash(&format!("sha256:{}", hash(b"abc"))), Some(hash(b"abc").as_str()));
+        assert_eq!(referenced_hash("sha256:../../etc/passwd"), None);
+        assert_eq!(referenced_hash("iVBORw0KGgo="), None);
+    }
+
+    #[actix_rt::test]
+    async fn moves_inline_images() {
+        let recipes = Arc::new(MemoryStore::new());
+        let images = Arc::new(MemoryImageStore::new());
+        let mut inline = create_one_recipe_with_image();
+        inline.image_base64 = Some(base64::encode(b"image"));
+        let id = recipes.insert_recipe(inline).await.unwrap().as_object_id().unwrap().clone();
+        let mut broken = create_one_recipe_with_image();
+        broken.image_base64 = Some("no base64".to_string());
+        recipes.insert_recipe(broken).await.unwrap();
+        recipes.insert_recipe(create_one_recipe_without_image()).await.unwrap();
+        let before = recipes.get_one_recipe_without_image(id.clone()).await.unwrap();
+
+        assert_eq!(move_inline_images(recipes.clone(), images.clone()).await, Ok(1));
+        let reference = recipes.get_one_recipe_image(id.clone()).await.unwrap();
+        assert_eq!(images.get(referenced_hash(&reference).unwrap()).await.unwrap(), b"image");
+        assert_eq!(recipes.get_one_recipe_without_image(id).await.unwrap().last_modified, before.last_modified);
+        assert_eq!(move_inline_images(recipes, images).await, Ok(0));
+    }
+}
@@ -0,0 +1,248 @@
+use std::io::{self, Cursor};
+
+use ::image::{DynamicImage, ImageDecoder, ImageError, ImageReader, ImageResult};
+use ::image::codecs::jpeg::JpegEncoder;
+use ::image::codecs::png::PngEncoder;
+use ::image::codecs::webp::WebPEncoder;
+use ::image::imageops::FilterType;
+use actix_web::error::BlockingError;
+use actix_web::web;
+use serde::Deserialize;
+
+use crate::image::ImageFormat;
+use crate::image_store::{hash, ImageStore};
+use crate::store::DaoError;
+
+/// quality of re-encoded originals, they are shown full size
+const ORIGINAL_QUALITY: u8 = 90;
+const VARIANT_QUALITY: u8 = 80;
+
+/// The widths the recipe grid and pages show, `GET /recipes/{id}/image?size=thumb`.
+#[derive(Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSize {
+    Thumb,
+    Medium,
+    Large,
+}
+
+impl ImageSize {
+    pub const ALL: [ImageSize; 3] = [ImageSize::Thumb, ImageSize::Medium, ImageSize::Large];
+
+    /// pixels, smaller images keep their width
+    pub fn width(self) -> u32 {
+        match self {
+            ImageSize::Thumb => 200,
+            ImageSize::Medium => 600,
+            ImageSize::Large => 1200,
+        }
+    }
+}
+
+/// An upload with EXIF orientation applied and without EXIF and XMP metadata, which may hold
+/// the GPS position. Images without metadata keep their bytes. Err when the image cannot be decoded.
+pub async fn clean(bytes: Vec<u8>) -> ImageResult<Vec<u8>> {
+    blocking(move || {
+        let format = ImageFormat::sniff(&bytes).ok_or_else(unsupported)?;
+        let (image, had_metadata) = decode(&bytes)?;
+        if had_metadata {
+            encode(&image, format, ORIGINAL_QUALITY)
+        } else {
+            Ok(bytes)
+        }
+    }).await
+}
+
+/// Stores every size of the image stored under `source`. Images that cannot be decoded
+/// get no variants but a marker, they are served at full size without being decoded again.
+pub async fn put_variants(images: &dyn ImageStore, source: &str, bytes: Vec<u8>) -> Result<(), DaoError> {
+    for size in ImageSize::ALL.iter().copied() {
+        let source_bytes = bytes.clone();
+        match blocking(move || resize(&source_bytes, size)).await {
+            Ok(variant) => images.put_hashed(&variant_hash(source, size), &variant).await?,
+            Err(err) => {
+                warn!("Could not create image variant. source={}, size={:?}, err={}", source, size, err);
+                return images.put_hashed(&undecodable_hash(source), &[]).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The variant of the image stored under `source`. Variants missing since the image was stored
+/// by an older version are created, the image itself is returned when it cannot be resized.
+pub async fn get_variant(images: &dyn ImageStore, source: &str, size: ImageSize) -> Result<Vec<u8>, DaoError> {
+    match images.get(&variant_hash(source, size)).await {
+        Err(DaoError::DocumentNotFound(_)) => {}
+        variant => return variant
+    }
+    if images.contains(&undecodable_hash(source)).await? {
+        return images.get(source).await;
+    }
+    let bytes = images.get(source).await?;
+    put_variants(images, source, bytes.clone()).await?;
+    match images.get(&variant_hash(source, size)).await {
+        Err(DaoError::DocumentNotFound(_)) => Ok(bytes),
+        variant => variant
+    }
+}
+
+/// Variants are no content hash, they are found by their source. A new image gets new variants.
+fn variant_hash(source: &str, size: ImageSize) -> String {
+    hash(format!("{}:{}", source, size.width()).as_bytes())
+}
+
+/// Marks an image `put_variants` could not decode, like the variants it is found by its source.
+fn undecodable_hash(source: &str) -> String {
+    hash(format!("{}:undecodable", source).as_bytes())
+}
+
+/// the image turned as its EXIF orientation says, and whether it had metadata
+fn decode(bytes: &[u8]) -> ImageResult<(DynamicImage, bool)> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?.into_decoder()?;
+    let had_metadata = decoder.exif_metadata()?.is_some() || decoder.xmp_metadata()?.is_some();
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok((image, had_metadata))
+}
+
+/// at most `size.width` wide, JPEG or lossless WebP when the image is transparent
+fn resize(bytes: &[u8], size: ImageSize) -> ImageResult<Vec<u8>> {
+    let (mut image, _) = decode(bytes)?;
+    if image.width() > size.width() {
+        image = image.resize(size.width(), u32::MAX, FilterType::Lanczos3);
+    }
+    let format = if image.color().has_alpha() { ImageFormat::Webp } else { ImageFormat::Jpeg };
+    encode(&image, format, VARIANT_QUALITY)
+}
+
+/// the encoders write no metadata
+fn encode(image: &DynamicImage, format: ImageFormat, quality: u8) -> ImageResult<Vec<u8>> {
+    let mut bytes = vec![];
+    match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))?,
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut bytes))?,
+        ImageFormat::Webp => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut bytes))?,
+    }
+    Ok(bytes)
+}
+
+fn unsupported() -> ImageError {
+    ImageError::IoError(io::Error::new(io::ErrorKind::InvalidData, "the image is no JPEG, PNG or WebP"))
+}
+
+/// decoding and resizing take too long for the server threads
+async fn blocking<T: Send + 'static, F: FnOnce() -> ImageResult<T> + Send + 'static>(f: F) -> ImageResult<T> {
+    web::block(f).await.map_err(|err| match err {
+        BlockingError::Error(err) => err,
+        BlockingError::Canceled => ImageError::IoError(io::Error::new(io::ErrorKind::Interrupted, "canceled"))
+    })
+}
+
+
+#[cfg(test)]
+pub mod variants_tests {
+    use std::io::Cursor;
+
+    use ::image::{DynamicImage, ImageFormat as Codec, Rgb, RgbImage, RgbaImage};
+
+    use crate::image::ImageFormat;
+    use crate::image_store::{ImageStore, referenced_hash};
+    use crate::image_store::memory::MemoryImageStore;
+    use crate::image_store::variants::{clean, get_variant, ImageSize, put_variants, resize, undecodable_hash, variant_hash};
+
+    /// a real image of `width` x `height`, `PNG` and `JPEG` of `image_tests` only have the magic bytes
+    pub fn encoded(width: u32, height: u32, codec: Codec) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| Rgb([(x % 256) as u8, (y % 256) as u8, 128]));
+        let mut bytes = vec![];
+        DynamicImage::ImageRgb8(image).write_to(&mut Cursor::new(&mut bytes), codec).unwrap();
+        bytes
+    }
+
+    /// a JPEG of 4 x 2 pixels with an APP1 segment holding orientation 6, turned by 90°, and a GPS entry
+    fn jpeg_with_exif() -> Vec<u8> {
+        let tiff: &[u8] = &[
+            b'M', b'M', 0, 42, 0, 0, 0, 8,
+            0, 2,
+            0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0,
+            0x88, 0x25, 0, 4, 0, 0, 0, 1, 0, 0, 0, 0,
+            0, 0, 0, 0,
+        ];
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend_from_slice(tiff);
+        let length = (exif.len() + 2) as u16;
+
+        let jpeg = encoded(4, 2, Codec::Jpeg);
+        let mut bytes = jpeg[..2].to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xE1]);
+        bytes.extend_from_slice(&length.to_be_bytes());
+        bytes.extend_from_slice(&exif);
+        bytes.extend_from_slice(&jpeg[2..]);
+        bytes
+    }
+
+    fn dimensions(bytes: &[u8]) -> (u32, u32) {
+        let image = ::image::load_from_memory(bytes).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[actix_rt::test]
+    async fn clean_applies_orientation_and_drops_exif() {
+        let cleaned = clean(jpeg_with_exif()).await.unwrap();
+        assert_eq!(ImageFormat::sniff(&cleaned), Some(ImageFormat::Jpeg));
+        assert_eq!(dimensions(&cleaned), (2, 4));
+        assert!(!cleaned.windows(4).any(|window| window == b"Exif"));
+
+        let plain = encoded(4, 2, Codec::Png);
+        assert_eq!(clean(plain.clone()).await.unwrap(), plain);
+        assert!(clean(b"\x89PNG\r\n\x1a\nbroken".to_vec()).await.is_err());
+    }
+
+    #[test]
+    fn resizes_to_width_and_keeps_small_images() {
+        let large = resize(&encoded(1000, 500, Codec::Png), ImageSize::Thumb).unwrap();
+        assert_eq!(ImageFormat::sniff(&large), Some(ImageFormat::Jpeg));
+        assert_eq!(dimensions(&large), (200, 100));
+
+        assert_eq!(dimensions(&resize(&encoded(100, 50, Codec::Jpeg), ImageSize::Thumb).unwrap()), (100, 50));
+
+        let mut transparent = vec![];
+        DynamicImage::ImageRgba8(RgbaImage::new(300, 300)).write_to(&mut Cursor::new(&mut transparent), Codec::Png).unwrap();
+        assert_eq!(ImageFormat::sniff(&resize(&transparent, ImageSize::Thumb).unwrap()), Some(ImageFormat::Webp));
+    }
+
+    #[actix_rt::test]
+    async fn variants_are_created_when_missing() {
+        let images = MemoryImageStore::new();
+        let reference = images.put(&encoded(800, 400, Codec::Jpeg)).await.unwrap();
+        let source = referenced_hash(&reference).unwrap();
+        assert!(images.get(&variant_hash(source, ImageSize::Medium)).await.is_err());
+
+        let medium = get_variant(&images, source, ImageSize::Medium).await.unwrap();
+        assert_eq!(dimensions(&medium), (600, 300));
+        assert_eq!(dimensions(&images.get(&variant_hash(source, ImageSize::Thumb)).await.unwrap()), (200, 100));
+
+        let broken = images.put(b"\xFF\xD8\xFFbroken").await.unwrap();
+        let broken = referenced_hash(&broken).unwrap();
+        put_variants(&images, broken, b"\xFF\xD8\xFFbroken".to_vec()).await.unwrap();
+        assert_eq!(get_variant(&images, broken, ImageSize::Thumb).await.unwrap(), b"\xFF\xD8\xFFbroken");
+    }
+
+    #[actix_rt::test]
+    async fn undecodable_images_are_decoded_once() {
+        let images = MemoryImageStore::new();
+        let broken = images.put(b"\xFF\xD8\xFFbroken").await.unwrap();
+        let broken = referenced_hash(&broken).unwrap();
+        assert!(!images.contains(&undecodable_hash(broken)).await.unwrap());
+
+        assert_eq!(get_variant(&images, broken, ImageSize::Thumb).await.unwrap(), b"\xFF\xD8\xFFbroken");
+        assert!(images.contains(&undecodable_hash(broken)).await.unwrap());
+        assert_eq!(get_variant(&images, broken, ImageSize::Large).await.unwrap(), b"\xFF\xD8\xFFbroken");
+        for size in ImageSize::ALL.iter().copied() {
+            assert!(!images.contains(&variant_hash(broken, size)).await.unwrap());
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use serde::Serialize;
+
+use crate::model::ingredients::Ingredient;
+use crate::model::measurement_unit::MeasurementUnit;
+use crate::model::quantity::{Amount, Quantity};
+use crate::model::unit::{Unit, UnitCatalogue};
+
+/// words that stand for an amount, German and English
+const NUMBER_WORDS: &[(&str, i64, i64)] = &[
+    ("ein", 1, 1), ("eine", 1, 1), ("einen", 1, 1), ("einer", 1, 1), ("a", 1, 1), ("an", 1, 1), ("one", 1, 1),
+    ("zwei", 2, 1), ("two", 2, 1), ("drei", 3, 1), ("three", 3, 1), ("vier", 4, 1), ("four", 4, 1),
+    ("halbe", 1, 2), ("halben", 1, 2), ("halber", 1, 2), ("half", 1, 2),
+];
+
+/// words before an amount that do not change it, like the `ca.` of `ca. 200 g`
+const APPROXIMATELY: &[&str] = &["ca.", "ca", "circa", "etwa", "ungefähr", "about", "approx.", "approximately", "roughly"];
+
+/// words for an amount nobody measures
+const SOME: &[&str] = &["etwas", "some", "wenig"];
+
+const TO_TASTE: &[&str] = &["nach geschmack", "nach belieben", "to taste", "as needed"];
+
+/// words between unit and ingredient, `a pinch of salt`
+const FILLERS: &[&str] = &["of"];
+
+/// list markers in front of a line
+pub const BULLETS: &[char] = &['-', '*', '•', '–', '·'];
+
+/// How sure the parser is of each part, they add up to 1.
+const CONFIDENCE_TITLE: f64 = 0.4;
+const CONFIDENCE_AMOUNT: f64 = 0.3;
+const CONFIDENCE_UNIT: f64 = 0.3;
+/// a line like `2 Eier` names no unit, it counts pieces
+const CONFIDENCE_COUNT: f64 = 0.2;
+/// numbers left in the title were most likely not understood
+const PENALTY_DIGITS: f64 = 0.2;
+
+/// An ingredient line split into its parts. Lines without unit count pieces, `confidence` between
+/// 0 and 1 tells how much of the line was understood.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedIngredient {
+    pub original: String,
+    pub amount: Quantity,
+    pub measurement_unit: MeasurementUnit,
+    pub title: String,
+    /// how the ingredient is prepared, the parts in parentheses and after the first comma
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub confidence: f64,
+}
+
+impl ParsedIngredient {
+    /// The ingredient with this id, the note is kept in parentheses after the title.
+    pub fn ingredient(&self, id: &str) -> Ingredient {
+        let title = match &self.note {
+            Some(note) => format!("{} ({})", self.title, note),
+            None => self.title.clone()
+        };
+        Ingredient::new(id, self.amount.clone(), &title, self.measurement_unit.clone())
+    }
+}
+
+/// Each line of `text` that is not empty, parsed.
+pub fn parse_text(text: &str, units: &UnitCatalogue) -> Vec<ParsedIngredient> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_line(line, units))
+        .collect()
+}
+
+/// Whether `text` has more than `max` lines that are not empty, the rest of the text is not looked at.
+pub fn has_more_lines(text: &str, max: usize) -> bool {
+    text.lines().filter(|line| !line.trim().is_empty()).nth(max).is_some()
+}
+
+/// Splits a line like `200 g Mehl (Type 405), gesiebt` or `a pinch of salt` into amount, unit,
+/// title and note. Units are whatever the catalogue calls them, with plurals like `Zehen` or `cups`.
+pub fn parse_line(line: &str, units: &UnitCatalogue) -> ParsedIngredient {
+    let text = line.trim().trim_start_matches(BULLETS).trim();
+    let (main, mut notes) = split_notes(text);
+
+    let mut tokens = tokens(&main, units);
+    let mut index = 0;
+    while tokens.get(index).is_some_and(|token| APPROXIMATELY.contains(&token.to_lowercase().as_str())) {
+        index += 1;
+    }
+    let (mut amount, length) = leading_amount(&tokens[index..]);
+    let mut explicit_amount = length > 0 && amount != Quantity::Unspecified;
+    index += length;
+
+    let mut unit = None;
+    for length in (1..=2).rev() {
+        if index + length > tokens.len() {
+            continue;
+        }
+        if let Some(found) = find_unit(&tokens[index..index + length].join(" "), units, explicit_amount) {
+            unit = Some(found.id.clone());
+            index += length;
+            break;
+        }
+    }
+    if unit.is_some() && tokens.get(index).is_some_and(|token| FILLERS.contains(&token.as_str())) {
+        index += 1;
+    }
+
+    let mut title = tokens.split_off(index).join(" ");
+    if let Some(without) = without_to_taste(&title) {
+        title = without.to_string();
+        amount = Quantity::ToTaste;
+        explicit_amount = true;
+    }
+    if notes.iter().any(|note| without_to_taste(note) == Some("")) {
+        notes.retain(|note| without_to_taste(note) != Some(""));
+        if amount == Quantity::Unspecified {
+            amount = Quantity::ToTaste;
+            explicit_amount = true;
+        }
+    }
+
+    let mut confidence = 0.0;
+    if !title.is_empty() {
+        confidence += CONFIDENCE_TITLE;
+    }
+    if explicit_amount {
+        confidence += CONFIDENCE_AMOUNT;
+    }
+    if unit.is_some() {
+        confidence += CONFIDENCE_UNIT;
+    } else if explicit_amount {
+        confidence += CONFIDENCE_COUNT;
+    }
+    if title.chars().any(|c| c.is_ascii_digit()) {
+        confidence -= PENALTY_DIGITS;
+    }
+
+    ParsedIngredient {
+        original: line.to_string(),
+        amount,
+        measurement_unit: unit.unwrap_or(MeasurementUnit::PIECE),
+        title,
+        note: if notes.is_empty() { None } else { Some(notes.join(", ")) },
+        confidence: (confidence.clamp(0.0, 1.0) * 100.0).round() / 100.0,
+    }
+}
+
+/// The text without parentheses and everything after the first comma, which are the notes.
+/// A comma between digits is a decimal comma.
+fn split_notes(text: &str) -> (String, Vec<String>) {
+    let mut main = String::new();
+    let mut notes = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('(') {
+        let end = rest[start..].find(')').map_or(rest.len(), |end| start + end);
+        main.push_str(&rest[..start]);
+        notes.push(rest[start + 1..end].trim().to_string());
+        rest = rest.get(end + 1..).unwrap_or_default();
+    }
+    main.push_str(rest);
+
+    let chars: Vec<char> = main.chars().collect();
+    let comma = chars.iter().enumerate().position(|(index, c)| *c == ','
+        && !(index > 0 && chars[index - 1].is_ascii_digit() && chars.get(index + 1).is_some_and(|c| c.is_ascii_digit())));
+    let main = match comma {
+        Some(comma) => {
+            let note: String = chars[comma + 1..].iter().collect();
+            notes.extend(note.split(',').map(|note| note.trim().to_string()));
+            chars[..comma].iter().collect()
+        }
+        None => main,
+    };
+    notes.retain(|note| !note.is_empty());
+    (main.trim().to_string(), notes)
+}
+
+/// the text without a trailing `to taste` or `nach Geschmack`, None when it has none
+fn without_to_taste(text: &str) -> Option<&str> {
+    TO_TASTE.iter().find_map(|phrase| {
+        let start = text.len().checked_sub(phrase.len())?;
+        match text.get(start..) {
+            Some(tail) if tail.eq_ignore_ascii_case(phrase) => Some(text[..start].trim_end()),
+            _ => None
+        }
+    })
+}
+
+/// The words of the line, an amount written together with its unit like `200g` is split.
+fn tokens(text: &str, units: &UnitCatalogue) -> Vec<String> {
+    let mut tokens = vec![];
+    for word in text.split_whitespace() {
+        let split = word.find(|c: char| c.is_alphabetic())
+            .filter(|index| *index > 0 && word[..*index].chars().all(|c| !c.is_alphabetic()))
+            .filter(|index| Amount::parse(&word[..*index]).is_some() && units.find(word[*index..].trim_end_matches('.')).is_some());
+        match split {
+            Some(index) => tokens.extend([word[..index].to_string(), word[index..].to_string()]),
+            None => tokens.push(word.to_string()),
+        }
+    }
+    tokens
+}
+
+/// The amount the tokens start with and how many tokens it takes, `1 1/2` and `2 - 3` take several.
+fn leading_amount(tokens: &[String]) -> (Quantity, usize) {
+    let first = match tokens.first() {
+        Some(first) => first.to_lowercase(),
+        None => return (Quantity::Unspecified, 0)
+    };
+    if let Some((_, numerator, denominator)) = NUMBER_WORDS.iter().find(|(word, _, _)| *word == first) {
+        let amount = Amount::fraction(*numerator, *denominator).map_or(Quantity::Unspecified, Quantity::Exact);
+        return (amount, 1);
+    }
+    if SOME.contains(&first.as_str()) {
+        return (Quantity::Unspecified, 1);
+    }
+    for length in (1..=tokens.len().min(3)).rev() {
+        match Quantity::parse(&tokens[..length].join(" ")) {
+            Some(quantity @ Quantity::Exact(_)) | Some(quantity @ Quantity::Range { .. }) => return (quantity, length),
+            _ => {}
+        }
+    }
+    (Quantity::Unspecified, 0)
+}
+
+/// The unit `word` names, also in plural and without the trailing dot of an abbreviation.
+/// Without an amount before it only words of three letters or more count, `g Mehl` is no unit.
+fn find_unit<'a>(word: &str, units: &'a UnitCatalogue, after_amount: bool) -> Option<&'a Unit> {
+    let bare = word.trim_end_matches('.');
+    let mut candidates = vec![word, bare];
+    candidates.extend(["es", "s", "en", "n"].iter()
+        .filter_map(|ending| bare.strip_suffix(ending))
+        .filter(|stem| stem.chars().count() > 2));
+    candidates.into_iter()
+        .filter(|candidate| after_amount || candidate.chars().count() >= 3)
+        .find_map(|candidate| units.find(candidate))
+}
+
+
+#[cfg(test)]
+mod ingredient_parser_tests {
+    use crate::ingredient_parser::{has_more_lines, parse_line, parse_text, ParsedIngredient};
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::quantity::{Amount, Quantity};
+    use crate::model::unit::UnitCatalogue;
+
+    fn parse(line: &str) -> ParsedIngredient {
+        parse_line(line, &UnitCatalogue::builtin())
+    }
+
+    fn parts(line: &str) -> (String, MeasurementUnit, String, Option<String>) {
+        let parsed = parse(line);
+        (parsed.amount.to_string(), parsed.measurement_unit, parsed.title, parsed.note)
+    }
+
+    fn expected(amount: &str, unit: MeasurementUnit, title: &str, note: Option<&str>) -> (String, MeasurementUnit, String, Option<String>) {
+        (amount.to_string(), unit, title.to_string(), note.map(String::from))
+    }
+
+    #[test]
+    fn parses_german_lines() {
+        assert_eq!(parts("200 g Mehl (Type 405), gesiebt"), expected("200", MeasurementUnit::GRAMM, "Mehl", Some("Type 405, gesiebt")));
+        assert_eq!(parts("2 EL Olivenöl"), expected("2", MeasurementUnit::ESSLOEFFEL, "Olivenöl", None));
+        assert_eq!(parts("1,5 l Milch"), expected("1.5", MeasurementUnit::LITER, "Milch", None));
+        assert_eq!(parts("2-3 Zehen Knoblauch, fein gehackt"), expected("2–3", MeasurementUnit::ZEHE, "Knoblauch", Some("fein gehackt")));
+        assert_eq!(parts("½ Bund Petersilie"), expected("½", MeasurementUnit::BUND, "Petersilie", None));
+        assert_eq!(parts("eine Prise Salz"), expected("1", MeasurementUnit::PRISE, "Salz", None));
+        assert_eq!(parts("3 Eier"), expected("3", MeasurementUnit::PIECE, "Eier", None));
+        assert_eq!(parts("- ca. 500g Kartoffeln"), expected("500", MeasurementUnit::GRAMM, "Kartoffeln", None));
+        assert_eq!(parts("1 Dose Tomaten (400 g)"), expected("1", MeasurementUnit::DOSE, "Tomaten", Some("400 g")));
+        assert_eq!(parts("Pfeffer nach Geschmack"), expected("to taste", MeasurementUnit::PIECE, "Pfeffer", None));
+        assert_eq!(parts("Salz"), expected("", MeasurementUnit::PIECE, "Salz", None));
+    }
+
+    #[test]
+    fn parses_english_lines() {
+        assert_eq!(parts("1 1/2 cups all-purpose flour, sifted"), expected("1½", MeasurementUnit::CUP, "all-purpose flour", Some("sifted")));
+        assert_eq!(parts("a pinch of salt"), expected("1", MeasurementUnit::PRISE, "salt", None));
+        assert_eq!(parts("2 tbsp olive oil"), expected("2", MeasurementUnit::TABLESPOON, "olive oil", None));
+        assert_eq!(parts("8 fl oz milk"), expected("8", MeasurementUnit::FLUID_OUNCE, "milk", None));
+        assert_eq!(parts("3 cloves garlic"), expected("3", MeasurementUnit::ZEHE, "garlic", None));
+        assert_eq!(parts("black pepper, to taste"), expected("to taste", MeasurementUnit::PIECE, "black pepper", None));
+    }
+
+    #[test]
+    fn rates_confidence() {
+        let flour = parse("200 g Mehl");
+        assert_eq!(flour.confidence, 1.0);
+        assert_eq!(flour.original, "200 g Mehl");
+        assert_eq!(flour.amount, Quantity::Exact(Amount::whole(200)));
+        assert_eq!(parse("3 Eier").confidence, 0.9);
+        assert_eq!(parse("Salz").confidence, 0.4);
+        assert_eq!(parse("Mehl 200 g").confidence, 0.2);
+        assert_eq!(parse("200 g").confidence, 0.6);
+        assert!(parse("g Mehl").confidence < 0.5);
+    }
+
+    #[test]
+    fn parses_every_line_of_text() {
+        let parsed = parse_text("200 g Mehl\n\n  \n2 Eier\r\n", &UnitCatalogue::builtin());
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].title, "Eier");
+        assert_eq!(parsed[1].measurement_unit, MeasurementUnit::PIECE);
+        assert_eq!(parse("200 g Mehl (Type 405), gesiebt").ingredient("0").title, "Mehl (Type 405, gesiebt)");
+    }
+
+    #[test]
+    fn counts_lines_that_are_not_empty() {
+        assert!(!has_more_lines("200 g Mehl\n\n  \n2 Eier\r\n", 2));
+        assert!(has_more_lines("200 g Mehl\n2 Eier\nSalz", 2));
+        assert!(!has_more_lines("", 0));
+    }
+}
@@ -0,0 +1,86 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::ingredient_parser::{self, ParsedIngredient};
+use crate::json_body::JsonBody;
+use crate::store::RecipeStore;
+use crate::validation;
+
+/// `POST /ingredients/parse`, one ingredient per line of `text`
+#[derive(Deserialize, Debug)]
+pub struct ParseRequest {
+    pub text: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ParsedIngredients {
+    pub ingredients: Vec<ParsedIngredient>,
+}
+
+pub struct IngredientRoutes {}
+
+impl IngredientRoutes {
+    /// Splits pasted ingredient lines into amount, unit, title and note. Nothing is stored, units
+    /// are looked up in the catalogue. At most `validation.max_ingredients` lines are parsed at once.
+    pub async fn parse_ingredients(req: HttpRequest, database: web::Data<dyn RecipeStore>, body: JsonBody<ParseRequest>) -> Result<HttpResponse, ApiError> {
+        let max_ingredients = validation::limits(&req).max_ingredients;
+        if ingredient_parser::has_more_lines(&body.text, max_ingredients) {
+            return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "too-many-lines", "Too many lines",
+                                     format!("At most {} ingredients can be parsed at once", max_ingredients))
+                .with_field("text"));
+        }
+        let ingredients = ingredient_parser::parse_text(&body.text, &database.unit_catalogue().await?);
+        Ok(HttpResponse::Ok().json(ParsedIngredients { ingredients }))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, test, web};
+    use actix_web::http::StatusCode;
+
+    use crate::config::ValidationConfig;
+    use crate::ingredient_routes::IngredientRoutes;
+    use crate::store::memory::MemoryStore;
+    use crate::store::RecipeStore;
+
+    #[actix_rt::test]
+    async fn test_parse_ingredients() {
+        let store = web::Data::from(Arc::new(MemoryStore::new()) as Arc<dyn RecipeStore>);
+        let mut app = test::init_service(App::new()
+            .app_data(store)
+            .app_data(ValidationConfig { max_ingredients: 2, ..Default::default() })
+            .route("/ingredients/parse", web::post().to(IngredientRoutes::parse_ingredients))).await;
+
+        let req = test::TestRequest::post().uri("/ingredients/parse")
+            .set_json(&serde_json::json!({"text": "200 g Mehl (Type 405), gesiebt\n2-3 Eier"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body, serde_json::json!({"ingredients": [
+            {"original": "200 g Mehl (Type 405), gesiebt", "amount": 200, "measurementUnit": "Gramm",
+             "title": "Mehl", "note": "Type 405, gesiebt", "confidence": 1.0},
+            {"original": "2-3 Eier", "amount": {"min": 2, "max": 3}, "measurementUnit": "Piece",
+             "title": "Eier", "confidence": 0.9},
+        ]}));
+
+        let req = test::TestRequest::post().uri("/ingredients/parse")
+            .set_json(&serde_json::json!({"text": "Salz\nPfeffer\nZucker"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "text");
+
+        let req = test::TestRequest::post().uri("/ingredients/parse")
+            .set_json(&serde_json::json!({"lines": []}))
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,170 @@
+use std::ops::Deref;
+
+use actix_web::{dev, FromRequest, HttpMessage, HttpRequest};
+use actix_web::error::{JsonPayloadError, PayloadError};
+use actix_web::http::{header, StatusCode};
+use actix_web::web::BytesMut;
+use futures_util::future::LocalBoxFuture;
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use serde_json::error::Category;
+
+use crate::error::ApiError;
+
+/// Replaces `web::Json` for request bodies. A body that does not fit the type is rejected
+/// with a problem naming the path of the offending field, like `ingredients[1].amount`.
+pub struct JsonBody<T>(pub T);
+
+impl<T> JsonBody<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for JsonBody<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Largest accepted body in bytes, registered with `app_data`.
+#[derive(Clone)]
+pub struct JsonBodyConfig {
+    pub limit: usize,
+}
+
+impl Default for JsonBodyConfig {
+    fn default() -> Self {
+        JsonBodyConfig { limit: 262_144 }
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for JsonBody<T> {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, ApiError>>;
+    type Config = JsonBodyConfig;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let limit = req.app_data::<JsonBodyConfig>().map_or(JsonBodyConfig::default().limit, |config| config.limit);
+        let is_json = matches!(req.mime_type(), Ok(Some(mime))
+            if mime.subtype() == "json" || mime.suffix().is_some_and(|suffix| suffix == "json"));
+        let length = req.headers().get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<usize>().ok());
+        let mut body = dev::Decompress::from_headers(payload.take(), req.headers());
+
+        Box::pin(async move {
+            if !is_json {
+                return Err(ApiError::from(JsonPayloadError::ContentType));
+            }
+            if length.is_some_and(|length| length > limit) {
+                return Err(ApiError::from(PayloadError::Overflow));
+            }
+
+            let mut bytes = BytesMut::new();
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk?;
+                if bytes.len() + chunk.len() > limit {
+                    return Err(ApiError::from(PayloadError::Overflow));
+                }
+                bytes.extend_from_slice(&chunk);
+            }
+
+            deserialize(&bytes).map(JsonBody)
+        })
+    }
+}
+
+fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ApiError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|err| {
+        let field = err.path().to_string();
+        let error = invalid_body(err.into_inner());
+        match field.as_str() {
+            "." => error,
+            _ => error.with_field(field)
+        }
+    })?;
+    deserializer.end().map_err(invalid_body)?;
+    Ok(value)
+}
+
+fn invalid_body(err: serde_json::Error) -> ApiError {
+    match err.classify() {
+        Category::Data => ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid-recipe",
+                                        "Invalid recipe", strip_position(&err)),
+        _ => ApiError::new(StatusCode::BAD_REQUEST, "invalid-json", "Invalid JSON body", err.to_string()),
+    }
+}
+
+/// serde_json appends the position to data errors, the field path is more helpful there
+fn strip_position(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message
+    }
+}
+
+
+#[cfg(test)]
+mod json_body_tests {
+    use actix_web::FromRequest;
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+    use actix_web::test::TestRequest;
+
+    use crate::json_body::{JsonBody, JsonBodyConfig};
+    use crate::model::recipe::Recipe;
+
+    const RECIPE: &str = r#"{"cookingTimeInMinutes": 12, "created": "2020-09-11T12:21:21+00:00",
+        "lastModified": "2020-09-11T12:21:21+00:00", "version": 1, "difficulty": "Easy",
+        "description": "", "title": "Spaghetti", "tags": [], "image": null, "instructions": [],
+        "defaultServings": 2, "ingredients": [
+            {"id": "0", "amount": 200, "title": "Wheat", "measurementUnit": "Kilogramm"}]}"#;
+
+    async fn extract(request: TestRequest) -> Result<JsonBody<Recipe>, crate::error::ApiError> {
+        let (req, mut payload) = request.to_http_parts();
+        JsonBody::<Recipe>::from_request(&req, &mut payload).await
+    }
+
+    #[actix_rt::test]
+    async fn extracts_recipe() {
+        let recipe = extract(TestRequest::post().header("content-type", "application/json").set_payload(RECIPE)).await;
+        assert_eq!(recipe.unwrap().title, "Spaghetti");
+    }
+
+    #[actix_rt::test]
+    async fn names_offending_field() {
+        let body = RECIPE.replace("\"Kilogramm\"", "5");
+        let error = extract(TestRequest::post().header("content-type", "application/json").set_payload(body)).await
+            .err().unwrap();
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.field.as_deref(), Some("ingredients[0].measurementUnit"));
+        assert!(error.detail.starts_with("invalid type: integer `5`, expected a string"), "{}", error.detail);
+
+        let body = RECIPE.replace("\"title\": \"Spaghetti\", ", "");
+        let error = extract(TestRequest::post().header("content-type", "application/json").set_payload(body)).await
+            .err().unwrap();
+        assert_eq!(error.field, None);
+        assert_eq!(error.detail, "missing field `title`");
+    }
+
+    #[actix_rt::test]
+    async fn rejects_malformed_body() {
+        let error = extract(TestRequest::post().header("content-type", "application/json").set_payload("{\"title\": ")).await
+            .err().unwrap();
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+
+        let error = extract(TestRequest::post().header("content-type", "text/plain").set_payload(RECIPE)).await
+            .err().unwrap();
+        assert_eq!(error.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let error = extract(TestRequest::post().header("content-type", "application/json")
+            .app_data(JsonBodyConfig { limit: 10 }).set_payload(RECIPE)).await
+            .err().unwrap();
+        assert_eq!(error.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
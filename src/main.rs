@@ -1,4 +1,6 @@
-#[macro_use]
+#![allow(clippy::needless_return, clippy::bool_assert_comparison)]
+
+#[cfg_attr(test, macro_use)]
 extern crate bson;
 #[macro_use]
 extern crate log;
@@ -6,86 +8,327 @@ extern crate mongodb;
 extern crate simplelog;
 
 use std::fs::File;
-use std::io::Bytes;
+use std::sync::Arc;
 
-use actix_web::{App, error, HttpResponse, HttpServer, web};
+use actix_cors::{Cors, CorsFactory};
+use actix_web::{App, HttpServer, web};
 use actix_web::middleware::Logger;
-use simplelog::{CombinedLogger, Config, LevelFilter, TerminalMode, TermLogger, WriteLogger};
+use simplelog::{CombinedLogger, LevelFilter, TerminalMode, TermLogger, WriteLogger};
 
+use crate::config::{Config, CorsConfig, DatabaseConfig, ImageBackend, LogConfig, StorageBackend};
 use crate::dao::Dao;
+use crate::error::ApiError;
+use crate::image::ImageConfig;
+use crate::image_store::filesystem::FileImageStore;
+use crate::image_store::gridfs::GridFsImageStore;
+use crate::image_store::ImageStore;
+use crate::image_store::memory::MemoryImageStore;
+use crate::ingredient_routes::IngredientRoutes;
+use crate::json_body::JsonBodyConfig;
 mod ssl;
 use crate::recipe_routes::RecipeRoutes;
+use crate::request_id::RequestId;
+use crate::store::memory::MemoryStore;
+use crate::store::RecipeStore;
+use crate::store::sqlite::SqliteStore;
+use crate::unit_routes::UnitRoutes;
 
+mod caching;
+mod config;
 mod model;
 mod dao;
+mod error;
+mod filter;
+mod image;
+mod image_store;
+mod ingredient_parser;
+mod ingredient_routes;
+mod json_body;
+mod migration;
+mod store;
 mod pagination;
 mod recipe_routes;
+mod request_id;
+mod search;
+mod text_import;
+mod unit_routes;
+mod validation;
+
+/// command that applies pending schema migrations and exits instead of serving
+const COMMAND_MIGRATE: &str = "migrate";
+
+/// the default format of `Logger` with the id `RequestId` assigned
+const LOG_FORMAT: &str = "%a \"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %T %{X-Request-Id}o";
 
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    init_logger();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let migrate_only = args.first().is_some_and(|arg| arg == COMMAND_MIGRATE);
+    if migrate_only {
+        args.remove(0);
+    }
+
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Refusing to start, {}", err);
+            std::process::exit(2);
+        }
+    };
 
-    let config = ssl::init();
+    init_logger(&config.log, config.log_level().unwrap());
 
-    let dao = Dao::new().await.unwrap();
+    if migrate_only {
+        std::process::exit(migrate(&config).await);
+    }
+
+    let tls = if config.tls.enabled {
+        match ssl::init(&config.tls) {
+            Ok(tls) => Some(tls),
+            Err(err) => {
+                error!("Refusing to start, {}", err);
+                std::process::exit(2);
+            }
+        }
+    } else {
+        None
+    };
 
-    let addr = "127.0.0.1:8080";
+    let store = init_store(&config.database).await;
+    let images = init_images(&config).await;
+    if config.database.migrate_on_start {
+        let (store, images) = (store.clone(), images.clone());
+        actix_rt::spawn(async move {
+            if let Err(err) = image_store::move_inline_images(store, images).await {
+                error!("Could not move inline images into the image store. Err={:#?}", err);
+            }
+        });
+    }
 
-    println!("Running on: {}", addr);
+    let cors = config.cors.clone();
+    let payload_limit = config.server.payload_limit;
+    let json_limit = config.server.json_limit;
+    let validation = config.validation.clone();
+    let cache = config.cache.clone();
 
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
-            .wrap(
-                actix_cors::Cors::new() // <- Construct CORS middleware builder
-                    .max_age(3600)
-                    .finish())
-            .data(dao.clone())
-            .data(web::PayloadConfig::new(5 << 20))
-            .app_data(web::JsonConfig::default().limit(5 << 20)
-                .error_handler(|err, req| {
-                    error!("Error={:#?}", err);
-                    error::InternalError::from_response(err, HttpResponse::BadRequest().finish()).into()
-                }))
+            .wrap(RequestId)
+            .wrap(Logger::new(LOG_FORMAT))
+            .wrap(init_cors(&cors))
+            .app_data(web::Data::from(store.clone()))
+            .app_data(web::Data::from(images.clone()))
+            .data(web::PayloadConfig::new(payload_limit))
+            .app_data(JsonBodyConfig { limit: json_limit })
+            .app_data(ImageConfig { limit: payload_limit })
+            .app_data(validation.clone())
+            .app_data(cache.clone())
+            .app_data(web::QueryConfig::default()
+                .error_handler(|err, _req| ApiError::from(err).into()))
             .service(
                 web::scope("/api/v1")
                     .service(web::resource("/recipes")
                         .route(web::get().to(RecipeRoutes::get_many_recipes))
                         .route(web::post().to(RecipeRoutes::add_many_recipes))
                     )
+                    .service(web::resource("/recipes/search")
+                        .route(web::get().to(RecipeRoutes::search_recipes))
+                    )
+                    .service(web::resource("/recipes/validate")
+                        .route(web::post().to(RecipeRoutes::validate_recipe))
+                    )
+                    .service(web::resource("/recipes/import/text")
+                        .route(web::post().to(RecipeRoutes::import_text))
+                    )
                     .service(web::resource("/recipes/{id}")
                         .route(web::post().to(RecipeRoutes::add_one_recipe))
                         .route(web::get().to(RecipeRoutes::get_one_recipe_without_image))
                         .route(web::put().to(RecipeRoutes::update_one_recipe_without_image))
                         .route(web::delete().to(RecipeRoutes::delete_one_recipe))
                     )
+                    .service(web::resource("/admin/recipes/scan")
+                        .route(web::get().to(RecipeRoutes::scan_recipes))
+                    )
                     .service(web::resource("/recipes/{id}/image")
                         .route(web::get().to(RecipeRoutes::get_one_recipe_image))
                         .route(web::put().to(RecipeRoutes::update_one_recipe_image))
                         .route(web::delete().to(RecipeRoutes::delete_one_recipe_image))
                     )
+                    .service(web::resource("/recipes/{id}/gallery")
+                        .route(web::post().to(RecipeRoutes::add_gallery_image))
+                        .route(web::put().to(RecipeRoutes::update_gallery))
+                    )
+                    .service(web::resource("/recipes/{id}/gallery/{imageId}")
+                        .route(web::get().to(RecipeRoutes::get_gallery_image))
+                        .route(web::delete().to(RecipeRoutes::delete_gallery_image))
+                    )
+                    .service(web::resource("/ingredients/parse")
+                        .route(web::post().to(IngredientRoutes::parse_ingredients))
+                    )
+                    .service(web::resource("/units")
+                        .route(web::get().to(UnitRoutes::get_units))
+                    )
+                    .service(web::resource("/units/{id}")
+                        .route(web::get().to(UnitRoutes::get_unit))
+                        .route(web::put().to(UnitRoutes::put_unit))
+                        .route(web::delete().to(UnitRoutes::delete_unit))
+                    )
             )
-    }).bind_rustls(addr, config)?.run().await
+    });
 
-    // }).bind(addr, config)?.run().await
+    for addr in &config.server.bind {
+        println!("Running on: {}", addr);
+        server = match &tls {
+            Some(tls) => server.bind_rustls(addr, tls.clone())?,
+            None => server.bind(addr)?
+        };
+    }
+
+    server.run().await
 }
 
 
-fn init_logger() {
+fn init_logger(config: &LogConfig, level: LevelFilter) {
     std::env::set_var("RUST_LOG", "actix_web=trace");
     CombinedLogger::init(
         vec![
-            TermLogger::new(LevelFilter::Info,
-                            Config::default(),
+            TermLogger::new(level,
+                            simplelog::Config::default(),
                             TerminalMode::Mixed),
-            WriteLogger::new(LevelFilter::Info,
-                             Config::default(),
-                             File::create("zellinotes.log").unwrap()),
+            WriteLogger::new(level,
+                             simplelog::Config::default(),
+                             File::create(&config.file).unwrap()),
         ]
     ).unwrap();
 }
 
+async fn init_store(config: &DatabaseConfig) -> Arc<dyn RecipeStore> {
+    match config.backend {
+        StorageBackend::Mongodb => {
+            let dao = Dao::new(config).await.unwrap();
+            if config.migrate_on_start {
+                if let Err(err) = migration::migrate(&dao).await {
+                    error!("Could not migrate recipes. Err={:#?}", err);
+                }
+            } else {
+                if let Err(err) = migration::stamp_new_collection(&dao).await {
+                    error!("Could not record schema version of new recipes. Err={:#?}", err);
+                }
+                match migration::schema_version(&dao).await {
+                    Ok(version) if version < migration::latest_version() =>
+                        warn!("Recipes have schema version {}, {} is current, run `{}` to migrate them",
+                              version, migration::latest_version(), COMMAND_MIGRATE),
+                    Ok(_) => {}
+                    Err(err) => error!("Could not read schema version of recipes. Err={:#?}", err)
+                }
+            }
+            Arc::new(dao)
+        }
+        StorageBackend::Sqlite => Arc::new(SqliteStore::open(&config.sqlite_path).unwrap()),
+        StorageBackend::Memory => {
+            warn!("Recipes are kept in memory only and are lost on shutdown");
+            Arc::new(MemoryStore::new())
+        }
+    }
+}
+
+async fn init_images(config: &Config) -> Arc<dyn ImageStore> {
+    match config.image_backend() {
+        ImageBackend::Gridfs => {
+            let database = dao::get_db_handler(&config.database).await.unwrap();
+            Arc::new(GridFsImageStore::new(database, &config.images.bucket).await)
+        }
+        ImageBackend::Filesystem => Arc::new(FileImageStore::open(&config.images.path).unwrap()),
+        ImageBackend::Memory => {
+            warn!("Images are kept in memory only and are lost on shutdown");
+            Arc::new(MemoryImageStore::new())
+        }
+    }
+}
+
+/// the `migrate` command, prints what it did and returns the exit code
+async fn migrate(config: &Config) -> i32 {
+    let store: Arc<dyn RecipeStore> = match config.database.backend {
+        StorageBackend::Mongodb => {
+            let dao = match Dao::new(&config.database).await {
+                Some(dao) => dao,
+                None => return 1
+            };
+            if !migrate_schema(&dao).await {
+                return 1;
+            }
+            Arc::new(dao)
+        }
+        StorageBackend::Sqlite => {
+            println!("The sqlite backend creates its schema itself");
+            match SqliteStore::open(&config.database.sqlite_path) {
+                Ok(store) => Arc::new(store),
+                Err(err) => {
+                    eprintln!("Could not open recipes, {:?}", err);
+                    return 1;
+                }
+            }
+        }
+        StorageBackend::Memory => {
+            println!("Nothing to migrate, the memory backend keeps no recipes");
+            return 0;
+        }
+    };
+
+    match image_store::move_inline_images(store.clone(), init_images(config).await).await {
+        Ok(moved) => println!("Moved {} inline images into the image store", moved),
+        Err(err) => {
+            eprintln!("Could not move inline images, {:?}", err);
+            return 1;
+        }
+    }
+
+    match store.scan_recipes().await {
+        Ok(report) if report.unreadable.is_empty() => 0,
+        Ok(report) => {
+            println!("{} documents cannot be read as recipe:", report.unreadable.len());
+            for document in &report.unreadable {
+                println!("  {}: {}", document.id, document.error);
+            }
+            3
+        }
+        Err(err) => {
+            eprintln!("Could not check recipes, {:?}", err);
+            1
+        }
+    }
+}
+
+/// applies the pending schema migrations, false when they could not run
+async fn migrate_schema(dao: &Dao) -> bool {
+    let reports = match migration::migrate(dao).await {
+        Ok(reports) => reports,
+        Err(err) => {
+            eprintln!("Could not migrate recipes, {:?}", err);
+            return false;
+        }
+    };
+    if reports.is_empty() {
+        println!("Recipes have schema version {} already", migration::latest_version());
+    }
+    for report in &reports {
+        println!("Schema version {}, {}: converted {} documents", report.version, report.description, report.converted);
+        for failed in &report.failed {
+            println!("  could not convert {}: {}", failed.id, failed.error);
+        }
+    }
+    true
+}
+
+fn init_cors(config: &CorsConfig) -> CorsFactory {
+    let mut cors = Cors::new().max_age(config.max_age);
+    for origin in &config.allowed_origins {
+        cors = cors.allowed_origin(origin);
+    }
+    cors.finish()
+}
+
 
 pub trait LogExtensionOk<T> {
     fn log_if_ok<F: FnOnce(&T)>(self, if_ok: F) -> Self;
@@ -0,0 +1,364 @@
+use std::convert::TryFrom;
+
+use bson::{Bson, doc, Document};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use mongodb::Collection;
+use mongodb::options::{FindOptions, UpdateOptions};
+
+use crate::dao::{Dao, document_id};
+use crate::model::quantity::Quantity;
+use crate::model::recipe::Recipe;
+use crate::store::{DaoError, now};
+
+/// Holds one document per recipe collection with the applied schema version and the history of runs.
+pub const SCHEMA_COLLECTION: &str = "schema_versions";
+
+/// at most this many failed documents are kept in the history, the log has all of them
+const MAX_RECORDED_FAILURES: usize = 100;
+
+/// One step in the shape of the stored recipe documents. Steps are applied in the order of
+/// `version`, each once. They have to be idempotent anyway, a crash may interrupt a step.
+/// Field names are spelled out instead of taken from the model, a step describes a past shape.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    /// the documents the step has to look at
+    query: fn() -> Document,
+    /// the update of one document, None when it has the new shape already
+    convert: fn(&Document) -> Result<Option<Document>, String>,
+}
+
+/// ordered by version, append new steps at the end and never change released ones
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            description: "store the modification date as date in last_modified",
+            query: || doc! { "$or": [
+                { "lastModified": { "$exists": true } },
+                { "last_modified": { "$not": { "$type": "date" } } },
+            ] },
+            convert: repair_last_modified,
+        },
+        Migration {
+            version: 2,
+            description: "store counts and amounts as 32 bit integers",
+            query: Document::new,
+            convert: whole_numbers,
+        },
+        Migration {
+            version: 3,
+            description: "fill in missing description, tags, ingredients and instructions",
+            query: || doc! { "$or": [
+                { "description": { "$exists": false } },
+                { "tags": { "$exists": false } },
+                { "ingredients": { "$exists": false } },
+                { "instructions": { "$exists": false } },
+            ] },
+            convert: fill_missing_lists,
+        },
+    ]
+}
+
+pub fn latest_version() -> u32 {
+    migrations().last().map_or(0, |migration| migration.version)
+}
+
+/// A document that could not be converted, it is left untouched.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FailedDocument {
+    pub id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MigrationReport {
+    pub version: u32,
+    pub description: &'static str,
+    pub converted: usize,
+    pub failed: Vec<FailedDocument>,
+}
+
+/// the schema version recorded for the recipe collection, 0 when nothing was applied yet
+pub async fn schema_version(dao: &Dao) -> Result<u32, DaoError> {
+    let record = schema(dao).find_one(doc! { "_id": &dao.recipe_collection }, None).await?;
+    Ok(record.and_then(|record| record.get_i32("version").ok()).map_or(0, |version| version as u32))
+}
+
+/// A recipe collection that does not exist yet is written in the latest shape only, it is recorded
+/// with the latest version and no step ever runs on it. False when the collection or its version exists.
+pub async fn stamp_new_collection(dao: &Dao) -> Result<bool, DaoError> {
+    let existing = dao.database.list_collection_names(doc! { "name": &dao.recipe_collection }).await?;
+    if !existing.is_empty() || schema(dao).find_one(doc! { "_id": &dao.recipe_collection }, None).await?.is_some() {
+        return Ok(false);
+    }
+    record(dao, &MigrationReport {
+        version: latest_version(),
+        description: "new collection, created in the latest shape",
+        converted: 0,
+        failed: vec![],
+    }).await?;
+    info!("Recorded schema version {} for the new recipe collection", latest_version());
+    Ok(true)
+}
+
+/// Applies every step newer than the recorded schema version and records each one.
+/// A document a step cannot convert does not stop the run, it is reported instead.
+pub async fn migrate(dao: &Dao) -> Result<Vec<MigrationReport>, DaoError> {
+    if stamp_new_collection(dao).await? {
+        return Ok(vec![]);
+    }
+    let current = schema_version(dao).await?;
+    if current > latest_version() {
+        warn!("Schema version {} of the recipes is newer than this service knows ({}), not migrating",
+              current, latest_version());
+        return Ok(vec![]);
+    }
+
+    let recipes = dao.database.collection(&dao.recipe_collection);
+    let mut reports = vec![];
+    for migration in migrations().into_iter().filter(|migration| migration.version > current) {
+        info!("Migrating recipes to schema version {}, {}", migration.version, migration.description);
+        let report = run(&recipes, &migration).await?;
+        record(dao, &report).await?;
+        info!("Migrated recipes to schema version {}, converted {} documents, {} failed",
+              report.version, report.converted, report.failed.len());
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+async fn run(recipes: &Collection, migration: &Migration) -> Result<MigrationReport, DaoError> {
+    let mut report = MigrationReport {
+        version: migration.version,
+        description: migration.description,
+        converted: 0,
+        failed: vec![],
+    };
+    let mut cursor = recipes.find((migration.query)(), without_image()).await?;
+    while let Some(document) = cursor.next().await {
+        let document = document?;
+        match (migration.convert)(&document) {
+            Ok(Some(update)) => {
+                let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+                recipes.update_one(doc! { "_id": id }, update, None).await?;
+                report.converted += 1;
+            }
+            Ok(None) => {}
+            Err(error) => {
+                let failed = FailedDocument { id: document_id(&document), error };
+                warn!("Could not migrate recipe to schema version {}. id={}, err={}", migration.version, failed.id, failed.error);
+                report.failed.push(failed);
+            }
+        }
+    }
+    Ok(report)
+}
+
+async fn record(dao: &Dao, report: &MigrationReport) -> Result<(), DaoError> {
+    let failed: Vec<Bson> = report.failed.iter()
+        .take(MAX_RECORDED_FAILURES)
+        .map(|failed| Bson::Document(doc! { "id": &failed.id, "error": &failed.error }))
+        .collect();
+    let run = doc! {
+        "version": report.version,
+        "description": report.description,
+        "applied": now(),
+        "converted": report.converted as i64,
+        "failed": failed,
+    };
+    let mut options = UpdateOptions::default();
+    options.upsert = Some(true);
+    schema(dao).update_one(doc! { "_id": &dao.recipe_collection },
+                           doc! { "$set": { "version": report.version }, "$push": { "history": run } },
+                           options).await?;
+    Ok(())
+}
+
+fn schema(dao: &Dao) -> Collection {
+    dao.database.collection(SCHEMA_COLLECTION)
+}
+
+/// images are large and no step touches them
+fn without_image() -> Option<FindOptions> {
+    let mut options = FindOptions::default();
+    options.projection = Some(Recipe::default_projection_no_image());
+    Some(options)
+}
+
+
+/// Older versions kept the modification date under the wire name `lastModified`, as RFC 3339 string,
+/// or not at all. Listings sort and filter by `last_modified`, the later date wins when both exist
+/// and `created` is the last resort.
+fn repair_last_modified(document: &Document) -> Result<Option<Document>, String> {
+    if !document.contains_key("lastModified") && matches!(document.get("last_modified"), Some(Bson::DateTime(_))) {
+        return Ok(None);
+    }
+    let last_modified = match (read_date(document.get("lastModified")), read_date(document.get("last_modified"))) {
+        (Some(wire), Some(stored)) => Some(wire.max(stored)),
+        (wire, stored) => wire.or(stored).or_else(|| read_date(document.get("created")))
+    };
+    let last_modified = last_modified.ok_or("no date found for last_modified")?;
+    Ok(Some(doc! { "$set": { "last_modified": last_modified }, "$unset": { "lastModified": "" } }))
+}
+
+fn read_date(value: Option<&Bson>) -> Option<DateTime<Utc>> {
+    match value {
+        Some(Bson::DateTime(date)) => Some(*date),
+        Some(Bson::String(date)) => DateTime::parse_from_rfc3339(date).ok().map(|date| date.with_timezone(&Utc)),
+        _ => None
+    }
+}
+
+/// Imports and other clients wrote numbers as 64 bit integers, doubles or strings.
+/// Whole numbers in range are kept, anything else is reported. Amounts are quantities
+/// since fractions, ranges and `to taste` exist, see `quantity`.
+fn whole_numbers(document: &Document) -> Result<Option<Document>, String> {
+    let mut set = Document::new();
+    for field in ["cookingTimeInMinutes", "version", "defaultServings"].iter() {
+        if let Some(value) = document.get(field) {
+            if let Some(converted) = whole_number(value).ok_or_else(|| format!("{} is no whole number: {}", field, value))? {
+                set.insert(*field, converted);
+            }
+        }
+    }
+
+    if let Some(Bson::Array(ingredients)) = document.get("ingredients") {
+        let mut changed = false;
+        let mut converted = vec![];
+        for (index, ingredient) in ingredients.iter().enumerate() {
+            let mut ingredient = ingredient.clone();
+            if let Bson::Document(ingredient) = &mut ingredient {
+                if let Some(amount) = ingredient.get("amount") {
+                    let amount = quantity(amount)
+                        .ok_or_else(|| format!("ingredients[{}].amount is no quantity: {}", index, amount))?;
+                    if let Some(amount) = amount {
+                        ingredient.insert("amount", amount);
+                        changed = true;
+                    }
+                }
+            }
+            converted.push(ingredient);
+        }
+        if changed {
+            set.insert("ingredients", converted);
+        }
+    }
+
+    Ok(if set.is_empty() { None } else { Some(doc! { "$set": set }) })
+}
+
+/// None when the number does not fit, Some(None) when it is stored as 32 bit integer already
+fn whole_number(value: &Bson) -> Option<Option<i32>> {
+    match value {
+        Bson::Int32(_) => Some(None),
+        Bson::Int64(number) => i32::try_from(*number).ok().map(Some),
+        Bson::Double(number) if number.fract() == 0.0 && *number >= i32::MIN as f64 && *number <= i32::MAX as f64 =>
+            Some(Some(*number as i32)),
+        Bson::String(number) => number.trim().parse::<i32>().ok().map(Some),
+        _ => None
+    }
+}
+
+/// None when the amount is no quantity, Some(None) when it is stored the way `Quantity` writes it already
+fn quantity(value: &Bson) -> Option<Option<Bson>> {
+    let stored = Bson::from(Quantity::try_from(value).ok()?);
+    Some(if &stored == value { None } else { Some(stored) })
+}
+
+/// the first versions left out empty text and lists
+fn fill_missing_lists(document: &Document) -> Result<Option<Document>, String> {
+    let mut set = Document::new();
+    if !document.contains_key("description") {
+        set.insert("description", "");
+    }
+    for field in ["tags", "ingredients", "instructions"].iter() {
+        if !document.contains_key(field) {
+            set.insert(*field, Bson::Array(vec![]));
+        }
+    }
+    Ok(if set.is_empty() { None } else { Some(doc! { "$set": set }) })
+}
+
+
+#[cfg(test)]
+mod migration_tests {
+    use bson::Bson;
+    use chrono::{TimeZone, Utc};
+
+    use crate::migration::{fill_missing_lists, migrations, repair_last_modified, whole_numbers};
+
+    #[test]
+    fn versions_ascend() {
+        let versions: Vec<u32> = migrations().iter().map(|migration| migration.version).collect();
+        assert_eq!(versions, (1..=versions.len() as u32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn repairs_last_modified() {
+        let created = Utc.timestamp_millis(1_600_000_000_000);
+        let later = Utc.timestamp_millis(1_600_000_100_000);
+
+        let wire_name = doc! { "created": created, "lastModified": "2020-09-13T12:28:20+00:00" };
+        assert_eq!(repair_last_modified(&wire_name), Ok(Some(doc! {
+            "$set": { "last_modified": later }, "$unset": { "lastModified": "" } })));
+
+        let both = doc! { "created": created, "last_modified": created, "lastModified": later };
+        assert_eq!(repair_last_modified(&both).unwrap().unwrap().get_document("$set").unwrap(),
+                   &doc! { "last_modified": later });
+
+        let missing = doc! { "created": created };
+        assert_eq!(repair_last_modified(&missing).unwrap().unwrap().get_document("$set").unwrap(),
+                   &doc! { "last_modified": created });
+
+        assert_eq!(repair_last_modified(&doc! { "last_modified": created }), Ok(None));
+        assert!(repair_last_modified(&doc! { "created": "yesterday" }).is_err());
+    }
+
+    #[test]
+    fn converts_whole_numbers() {
+        let document = doc! {
+            "cookingTimeInMinutes": 20_i64, "version": 3.0, "defaultServings": 2,
+            "ingredients": [{ "id": "0", "amount": "200" }, { "id": "1", "amount": 5 }],
+        };
+        assert_eq!(whole_numbers(&document), Ok(Some(doc! { "$set": {
+            "cookingTimeInMinutes": 20, "version": 3,
+            "ingredients": [{ "id": "0", "amount": 200 }, { "id": "1", "amount": 5 }],
+        } })));
+
+        let converted = doc! { "cookingTimeInMinutes": 20, "ingredients": [{ "amount": 5 }] };
+        assert_eq!(whole_numbers(&converted), Ok(None));
+
+        assert_eq!(whole_numbers(&doc! { "version": 1.5 }), Err("version is no whole number: 1.5".to_string()));
+        assert_eq!(whole_numbers(&doc! { "ingredients": [{ "amount": "a pinch" }] }),
+                   Err("ingredients[0].amount is no quantity: \"a pinch\"".to_string()));
+        assert!(whole_numbers(&doc! { "ingredients": [{ "amount": true }] }).is_err());
+        assert!(whole_numbers(&doc! { "defaultServings": 5_000_000_000_i64 }).is_err());
+    }
+
+    #[test]
+    fn keeps_quantities() {
+        let quantities = doc! { "ingredients": [
+            { "amount": "½" }, { "amount": { "min": 2, "max": 3 } }, { "amount": "to taste" },
+            { "amount": Bson::Null }, { "id": "4" }, { "amount": 5_000_000_000_i64 },
+        ] };
+        assert_eq!(whole_numbers(&quantities), Ok(None));
+
+        let written_by_clients = doc! { "ingredients": [{ "amount": 1.5 }, { "amount": "1 1/2" }, { "amount": { "min": 2_i64, "max": "3" } }] };
+        assert_eq!(whole_numbers(&written_by_clients), Ok(Some(doc! { "$set": {
+            "ingredients": [{ "amount": 1.5 }, { "amount": "1½" }, { "amount": { "min": 2, "max": 3 } }],
+        } })));
+    }
+
+    #[test]
+    fn fills_missing_lists() {
+        let document = doc! { "title": "Spaghetti", "tags": ["pasta"] };
+        let set = fill_missing_lists(&document).unwrap().unwrap();
+        assert_eq!(set.get_document("$set").unwrap(), &doc! {
+            "description": "", "ingredients": Bson::Array(vec![]), "instructions": Bson::Array(vec![]) });
+
+        let complete = doc! { "description": "", "tags": [], "ingredients": [], "instructions": [] };
+        assert_eq!(fill_missing_lists(&complete), Ok(None));
+    }
+}
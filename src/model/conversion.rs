@@ -0,0 +1,206 @@
+use serde::Deserialize;
+
+use crate::model::measurement_unit::MeasurementUnit;
+use crate::model::quantity::Quantity;
+use crate::model::scaling;
+use crate::model::unit::{Dimension, Unit, UnitCatalogue, UnitSystem};
+
+/// how far a whole amount of a larger unit may be off before a smaller unit is taken
+const TOLERANCE: f64 = 0.1;
+
+/// `GET /recipes/{id}?units=imperial`
+#[derive(Deserialize, Debug, Default)]
+pub struct UnitsQuery {
+    pub units: Option<UnitSystem>,
+}
+
+/// How heavy a milliliter of an ingredient is and how recipes of each system give it,
+/// flour is weighed in metric recipes and measured in cups in American ones.
+#[derive(Debug, PartialEq)]
+pub struct Density {
+    /// endings of the words of ingredient titles, German and English, `mehl` finds Weizenmehl
+    pub names: &'static [&'static str],
+    pub grams_per_milliliter: f64,
+    pub metric: Dimension,
+    pub imperial: Dimension,
+}
+
+/// The ingredients whose mass and volume convert into each other, the first matching entry is taken.
+pub const DENSITIES: &[Density] = &[
+    Density { names: &["puderzucker", "powdered", "icing"], grams_per_milliliter: 0.56, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["mehl", "flour"], grams_per_milliliter: 0.53, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["zucker", "sugar"], grams_per_milliliter: 0.85, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["milch", "milk"], grams_per_milliliter: 1.03, metric: Dimension::Volume, imperial: Dimension::Volume },
+    Density { names: &["butter"], grams_per_milliliter: 0.91, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["honig", "honey"], grams_per_milliliter: 1.42, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["salz", "salt"], grams_per_milliliter: 1.2, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["reis", "rice"], grams_per_milliliter: 0.85, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["haferflocken", "oats"], grams_per_milliliter: 0.41, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["kakao", "cocoa"], grams_per_milliliter: 0.42, metric: Dimension::Mass, imperial: Dimension::Volume },
+    Density { names: &["sahne", "cream"], grams_per_milliliter: 1.0, metric: Dimension::Volume, imperial: Dimension::Volume },
+    Density { names: &["öl", "oil"], grams_per_milliliter: 0.92, metric: Dimension::Volume, imperial: Dimension::Volume },
+    Density { names: &["wasser", "water"], grams_per_milliliter: 1.0, metric: Dimension::Volume, imperial: Dimension::Volume },
+];
+
+/// the density of the ingredient called `title`, None when the table does not know it
+pub fn density_of(title: &str) -> Option<&'static Density> {
+    let title = title.to_lowercase();
+    let words: Vec<&str> = title.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect();
+    DENSITIES.iter()
+        .find(|density| words.iter().any(|word| density.names.iter().any(|name| word.ends_with(name))))
+}
+
+/// `amount` of `from` in `to`. Mass and volume convert with the density in grams per milliliter,
+/// None without it and for units without factor, between counts of different units as well.
+pub fn convert(amount: f64, from: &Unit, to: &Unit, grams_per_milliliter: Option<f64>) -> Option<f64> {
+    if from.id == to.id {
+        return Some(amount);
+    }
+    let base = amount * from.factor?;
+    let base = match (from.dimension, to.dimension) {
+        (Dimension::Mass, Dimension::Mass) | (Dimension::Volume, Dimension::Volume) => base,
+        (Dimension::Mass, Dimension::Volume) => base / grams_per_milliliter?,
+        (Dimension::Volume, Dimension::Mass) => base * grams_per_milliliter?,
+        _ => return None
+    };
+    Some(base / to.factor?)
+}
+
+/// The amounts in the units recipes of `system`, whole like scaled amounts. Units of no system
+/// and of the system itself are kept, so are units the catalogue does not know and amounts to taste.
+/// Ingredients of `DENSITIES` change to the dimension the system gives them in. Both ends of a range
+/// take the unit of its min.
+pub fn to_system(quantity: &Quantity, unit: &MeasurementUnit, title: &str, system: UnitSystem, units: &UnitCatalogue) -> (Quantity, MeasurementUnit) {
+    let from = match units.get(unit.id()) {
+        Some(from) if from.system.is_some_and(|unit_system| unit_system != system) => from,
+        _ => return (quantity.clone(), unit.clone())
+    };
+    let density = density_of(title);
+    let dimension = match (density, system) {
+        (Some(density), UnitSystem::Metric) => density.metric,
+        (Some(density), UnitSystem::Imperial) => density.imperial,
+        (None, _) => from.dimension
+    };
+    let base_unit = if dimension == Dimension::Mass { builtin(units, &MeasurementUnit::GRAMM) } else { builtin(units, &MeasurementUnit::MILLILITER) };
+    let amounts = quantity.values();
+    let bases = match amounts.iter()
+        .map(|amount| convert(*amount, from, base_unit, density.map(|density| density.grams_per_milliliter)))
+        .collect::<Option<Vec<f64>>>() {
+        Some(bases) if !bases.is_empty() => bases,
+        _ => return (quantity.clone(), unit.clone())
+    };
+    let (converted, unit): (Vec<f64>, &Unit) = match system {
+        UnitSystem::Metric => (bases.iter().map(|base| scaling::round_metric(*base)).collect(), base_unit),
+        UnitSystem::Imperial => {
+            let unit = imperial(bases[0], dimension, density.is_some_and(|density| density.metric == Dimension::Mass), units);
+            (bases.iter().map(|base| (base / unit.factor.unwrap_or(1.0)).round()).collect(), unit)
+        }
+    };
+    let converted: Vec<f64> = converted.iter().zip(&amounts)
+        .map(|(converted, amount)| if *amount > 0.0 && *converted < 1.0 { 1.0 } else { *converted })
+        .collect();
+    (quantity.with_whole(&converted), unit.id.clone())
+}
+
+/// The largest unit whose whole amount is close to `base` grams or milliliters. Fluid ounces are
+/// for liquids only, nobody measures flour in them.
+fn imperial(base: f64, dimension: Dimension, dry: bool, units: &UnitCatalogue) -> &Unit {
+    let ids = match dimension {
+        Dimension::Mass => vec![MeasurementUnit::POUND, MeasurementUnit::OUNCE],
+        _ if dry => vec![MeasurementUnit::CUP, MeasurementUnit::TABLESPOON, MeasurementUnit::TEASPOON],
+        _ => vec![MeasurementUnit::CUP, MeasurementUnit::FLUID_OUNCE, MeasurementUnit::TABLESPOON, MeasurementUnit::TEASPOON],
+    };
+    let candidates: Vec<(f64, &Unit)> = ids.iter()
+        .map(|id| builtin(units, id))
+        .map(|unit| (base / unit.factor.unwrap_or(1.0), unit))
+        .collect();
+    let (_, unit) = candidates.iter()
+        .find(|(amount, _)| *amount >= 1.0 && (amount.round() - amount).abs() <= amount * TOLERANCE)
+        .unwrap_or_else(|| candidates.last().unwrap());
+    unit
+}
+
+/// builtin units cannot be removed from a catalogue
+fn builtin<'a>(units: &'a UnitCatalogue, id: &MeasurementUnit) -> &'a Unit {
+    units.get(id.id()).expect("every catalogue has the builtin units")
+}
+
+
+#[cfg(test)]
+mod conversion_tests {
+    use crate::model::conversion::{convert, density_of, to_system};
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::quantity::{Amount, Quantity};
+    use crate::model::unit::{Dimension, UnitCatalogue, UnitSystem};
+
+    fn close(actual: Option<f64>, expected: f64) -> bool {
+        actual.is_some_and(|actual| (actual - expected).abs() < 0.01)
+    }
+
+    fn convert_between(amount: f64, from: MeasurementUnit, to: MeasurementUnit, grams_per_milliliter: Option<f64>) -> Option<f64> {
+        let units = UnitCatalogue::builtin();
+        convert(amount, units.get(from.id()).unwrap(), units.get(to.id()).unwrap(), grams_per_milliliter)
+    }
+
+    fn to_system_builtin(amount: i32, unit: MeasurementUnit, title: &str, system: UnitSystem) -> (i32, MeasurementUnit) {
+        let (quantity, unit) = to_system(&Quantity::from(amount), &unit, title, system, &UnitCatalogue::builtin());
+        (quantity.values()[0] as i32, unit)
+    }
+
+    #[test]
+    fn converts_within_dimension() {
+        assert!(close(convert_between(1.0, MeasurementUnit::POUND, MeasurementUnit::GRAMM, None), 453.59));
+        assert!(close(convert_between(1.0, MeasurementUnit::POUND, MeasurementUnit::OUNCE, None), 16.0));
+        assert!(close(convert_between(1.0, MeasurementUnit::CUP, MeasurementUnit::TABLESPOON, None), 16.0));
+        assert!(close(convert_between(1.0, MeasurementUnit::TABLESPOON, MeasurementUnit::TEASPOON, None), 3.0));
+        assert!(close(convert_between(2.0, MeasurementUnit::LITER, MeasurementUnit::FLUID_OUNCE, None), 67.63));
+        assert!(close(convert_between(2.0, MeasurementUnit::ESSLOEFFEL, MeasurementUnit::MILLILITER, None), 30.0));
+        assert!(close(convert_between(3.0, MeasurementUnit::PIECE, MeasurementUnit::PIECE, None), 3.0));
+        assert_eq!(convert_between(1.0, MeasurementUnit::PIECE, MeasurementUnit::PACK, None), None);
+        assert_eq!(convert_between(1.0, MeasurementUnit::PRISE, MeasurementUnit::GRAMM, None), None);
+    }
+
+    #[test]
+    fn converts_mass_and_volume_with_density() {
+        let flour = density_of("Weizenmehl Type 405").unwrap();
+        assert!(close(convert_between(1.0, MeasurementUnit::CUP, MeasurementUnit::GRAMM, Some(flour.grams_per_milliliter)), 125.39));
+        assert_eq!(convert_between(1.0, MeasurementUnit::CUP, MeasurementUnit::GRAMM, None), None);
+
+        assert_eq!(density_of("all-purpose flour").unwrap().metric, Dimension::Mass);
+        assert_eq!(density_of("Puderzucker").unwrap().grams_per_milliliter, 0.56);
+        assert_eq!(density_of("Buttermilch").unwrap().names, &["milch", "milk"]);
+        assert_eq!(density_of("Zwiebeln"), None);
+    }
+
+    #[test]
+    fn renders_in_unit_system() {
+        assert_eq!(to_system_builtin(2, MeasurementUnit::CUP, "all-purpose flour", UnitSystem::Metric), (250, MeasurementUnit::GRAMM));
+        assert_eq!(to_system_builtin(1, MeasurementUnit::CUP, "milk", UnitSystem::Metric), (240, MeasurementUnit::MILLILITER));
+        assert_eq!(to_system_builtin(8, MeasurementUnit::OUNCE, "cheddar", UnitSystem::Metric), (230, MeasurementUnit::GRAMM));
+        assert_eq!(to_system_builtin(1, MeasurementUnit::TEASPOON, "salt", UnitSystem::Metric), (6, MeasurementUnit::GRAMM));
+
+        assert_eq!(to_system_builtin(500, MeasurementUnit::GRAMM, "Mehl", UnitSystem::Imperial), (4, MeasurementUnit::CUP));
+        assert_eq!(to_system_builtin(375, MeasurementUnit::MILLILITER, "Milch", UnitSystem::Imperial), (13, MeasurementUnit::FLUID_OUNCE));
+        assert_eq!(to_system_builtin(200, MeasurementUnit::GRAMM, "Bergkäse", UnitSystem::Imperial), (7, MeasurementUnit::OUNCE));
+        assert_eq!(to_system_builtin(1, MeasurementUnit::KILOGRAMM, "Kartoffeln", UnitSystem::Imperial), (2, MeasurementUnit::POUND));
+        assert_eq!(to_system_builtin(5, MeasurementUnit::GRAMM, "Salz", UnitSystem::Imperial), (1, MeasurementUnit::TEASPOON));
+        assert_eq!(to_system_builtin(2, MeasurementUnit::ESSLOEFFEL, "Olivenöl", UnitSystem::Imperial), (1, MeasurementUnit::FLUID_OUNCE));
+
+        assert_eq!(to_system_builtin(3, MeasurementUnit::PIECE, "Eier", UnitSystem::Imperial), (3, MeasurementUnit::PIECE));
+        assert_eq!(to_system_builtin(1, MeasurementUnit::PRISE, "Salz", UnitSystem::Imperial), (1, MeasurementUnit::PRISE));
+        assert_eq!(to_system_builtin(123, MeasurementUnit::GRAMM, "Mehl", UnitSystem::Metric), (123, MeasurementUnit::GRAMM));
+        assert_eq!(to_system_builtin(2, MeasurementUnit::new("Eimer"), "Wasser", UnitSystem::Imperial), (2, MeasurementUnit::new("Eimer")));
+    }
+
+    #[test]
+    fn renders_fractions_and_ranges_in_unit_system() {
+        let units = UnitCatalogue::builtin();
+        let half = Quantity::Exact(Amount::fraction(1, 2).unwrap());
+        assert_eq!(to_system(&half, &MeasurementUnit::CUP, "milk", UnitSystem::Metric, &units), (Quantity::from(120), MeasurementUnit::MILLILITER));
+        let range = Quantity::Range { min: Amount::whole(200), max: Amount::whole(300) };
+        assert_eq!(to_system(&range, &MeasurementUnit::GRAMM, "Bergkäse", UnitSystem::Imperial, &units),
+                   (Quantity::Range { min: Amount::whole(7), max: Amount::whole(11) }, MeasurementUnit::OUNCE));
+        assert_eq!(to_system(&Quantity::ToTaste, &MeasurementUnit::GRAMM, "Salz", UnitSystem::Imperial, &units),
+                   (Quantity::ToTaste, MeasurementUnit::GRAMM));
+    }
+}
@@ -0,0 +1,131 @@
+use std::convert::TryFrom;
+
+use bson::{Bson, Document};
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::model::recipe::RecipeFormatError;
+
+const JSON_ATTR_ID: &str = "id";
+const JSON_ATTR_IMAGE: &str = "image";
+const JSON_ATTR_CAPTION: &str = "caption";
+const JSON_ATTR_COVER: &str = "cover";
+const JSON_ATTR_STEP: &str = "step";
+
+
+/// One photo of a recipe. The gallery keeps them in the order they are shown,
+/// the one marked as cover is the image of the recipe.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct GalleryImage {
+    pub id: String,
+    /// `sha256:<hex>` of the image store, base64 for a cover older versions kept inline
+    pub image: String,
+    pub caption: String,
+    pub cover: bool,
+    /// position in `instructions` of the step the photo shows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<u32>,
+}
+
+
+impl TryFrom<Bson> for GalleryImage {
+    type Error = RecipeFormatError;
+
+    fn try_from(bson: Bson) -> Result<Self, Self::Error> {
+        let doc = bson.as_document()
+            .ok_or("Error getting gallery from document")?;
+
+        return Ok(Self {
+            id: doc.get_str(JSON_ATTR_ID)
+                .map(String::from)
+                .map_err(|_| RecipeFormatError::from(
+                    "Error getting id from gallery image from document"))?,
+            image: doc.get_str(JSON_ATTR_IMAGE)
+                .map(String::from)
+                .map_err(|_| RecipeFormatError::from(
+                    "Error getting image from gallery image from document"))?,
+            caption: doc.get_str(JSON_ATTR_CAPTION)
+                .map(String::from)
+                .map_err(|_| RecipeFormatError::from(
+                    "Error getting caption from gallery image from document"))?,
+            cover: doc.get_bool(JSON_ATTR_COVER)
+                .map_err(|_| RecipeFormatError::from(
+                    "Error getting cover from gallery image from document"))?,
+            step: match doc.get(JSON_ATTR_STEP) {
+                None | Some(Bson::Null) => None,
+                Some(Bson::Int32(step)) if *step >= 0 => Some(*step as u32),
+                _ => return Err(RecipeFormatError::from("Error getting step from gallery image from document"))
+            },
+        });
+    }
+}
+
+impl From<GalleryImage> for Bson {
+    fn from(image: GalleryImage) -> Self {
+        let mut doc = Document::new();
+        doc.insert(JSON_ATTR_ID, image.id);
+        doc.insert(JSON_ATTR_IMAGE, image.image);
+        doc.insert(JSON_ATTR_CAPTION, image.caption);
+        doc.insert(JSON_ATTR_COVER, image.cover);
+        doc.insert(JSON_ATTR_STEP, image.step.map_or(Bson::Null, |step| Bson::Int32(step as i32)));
+        Bson::Document(doc)
+    }
+}
+
+/// `POST /recipes/{id}/gallery?caption=..&step=2&cover=true`, the body is the image
+#[derive(Deserialize, Debug, Default)]
+pub struct GalleryQuery {
+    pub caption: Option<String>,
+    pub step: Option<u32>,
+    pub cover: Option<bool>,
+}
+
+/// An entry of `PUT /recipes/{id}/gallery`, which lists every image in the new order.
+/// The image itself cannot be changed, only what is shown with it.
+#[derive(Deserialize, Debug, Clone)]
+pub struct GalleryChange {
+    pub id: String,
+    #[serde(default)]
+    pub caption: String,
+    #[serde(default)]
+    pub cover: bool,
+    #[serde(default)]
+    pub step: Option<u32>,
+}
+
+/// the image of the recipe, the one of the cover
+pub fn cover(gallery: &[GalleryImage]) -> Option<&GalleryImage> {
+    gallery.iter().find(|image| image.cover)
+}
+
+
+#[cfg(test)]
+mod gallery_tests {
+    use std::convert::TryFrom;
+
+    use bson::Bson;
+
+    use crate::model::gallery::GalleryImage;
+
+    #[test]
+    fn bson_round_trip() {
+        let image = GalleryImage {
+            id: "5f7333360051027600b01a36".to_string(),
+            image: "sha256:ab".to_string(),
+            caption: "Teig nach dem Gehen".to_string(),
+            cover: false,
+            step: Some(2),
+        };
+        assert_eq!(GalleryImage::try_from(Bson::from(image.clone())).unwrap(), image);
+
+        let cover = GalleryImage { step: None, cover: true, ..image };
+        assert_eq!(GalleryImage::try_from(Bson::from(cover.clone())).unwrap(), cover);
+    }
+
+    #[test]
+    fn wrong_bson_fails() {
+        assert!(GalleryImage::try_from(Bson::Document(doc! { "id": "1", "image": "sha256:ab" })).is_err());
+        assert!(GalleryImage::try_from(Bson::Document(
+            doc! { "id": "1", "image": "sha256:ab", "caption": "", "cover": false, "step": -1 })).is_err());
+    }
+}
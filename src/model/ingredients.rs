@@ -5,6 +5,7 @@ use serde::Deserialize;
 use serde::Serialize;
 
 use crate::model::measurement_unit::MeasurementUnit;
+use crate::model::quantity::Quantity;
 use crate::model::recipe::RecipeFormatError;
 
 const JSON_ATTR_ID: &str = "id";
@@ -16,7 +17,9 @@ const JSON_ATTR_MEASUREMENT_UNIT: &str = "measurementUnit";
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct Ingredient {
     pub id: String,
-    pub amount: i32,
+    /// unspecified when the JSON leaves it out
+    #[serde(default)]
+    pub amount: Quantity,
     pub title: String,
     #[serde(rename = "measurementUnit")]
     pub measurement_unit: MeasurementUnit,
@@ -28,15 +31,16 @@ impl TryFrom<Bson> for Ingredient {
 
     fn try_from(bson: Bson) -> Result<Self, Self::Error> {
         let doc = bson.as_document()
-            .ok_or_else(|| "Error getting ingredients from document")?;
+            .ok_or("Error getting ingredients from document")?;
 
         return Ok(Self {
             id: doc.get_str(JSON_ATTR_ID)
                 .map(String::from)
                 .map_err(|_| RecipeFormatError::from(
                     "Error getting id from ingredient from document"))?,
-            amount: doc.get_i32(JSON_ATTR_AMOUNT)
-                .map_err(|_| RecipeFormatError::from(
+            amount: doc.get(JSON_ATTR_AMOUNT)
+                .and_then(|amount| Quantity::try_from(amount).ok())
+                .ok_or_else(|| RecipeFormatError::from(
                     "Error getting amount from ingredient from document"))?,
             title: doc.get_str(JSON_ATTR_TITLE)
                 .map(String::from)
@@ -54,10 +58,10 @@ impl TryFrom<Bson> for Ingredient {
 
 
 impl Ingredient {
-    pub fn new(id: &str, amount: i32, title: &str, measurement_unit: MeasurementUnit) -> Self {
+    pub fn new(id: &str, amount: impl Into<Quantity>, title: &str, measurement_unit: MeasurementUnit) -> Self {
         return Self {
             id: id.to_string(),
-            amount,
+            amount: amount.into(),
             title: title.to_string(),
             measurement_unit,
         };
@@ -91,6 +95,7 @@ mod ingredients_tests {
 
     use crate::model::ingredients::{Ingredient, JSON_ATTR_AMOUNT, JSON_ATTR_ID, JSON_ATTR_MEASUREMENT_UNIT, JSON_ATTR_TITLE};
     use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::quantity::{Amount, Quantity};
 
     #[test]
     fn from_bson_to_ingredient_test() {
@@ -101,8 +106,8 @@ mod ingredients_tests {
             "measurementUnit": "Kilogramm"
         })).unwrap();
         assert_eq!(ingredient.title, "Bread");
-        assert_eq!(ingredient.amount, 1000);
-        assert_eq!(ingredient.measurement_unit, MeasurementUnit::Kilogramm);
+        assert_eq!(ingredient.amount, Quantity::from(1000));
+        assert_eq!(ingredient.measurement_unit, MeasurementUnit::KILOGRAMM);
         assert_eq!(ingredient.id, "0");
     }
 
@@ -129,6 +134,10 @@ mod ingredients_tests {
             doc! { "id": null,"amount": 1000 }));
         assert_eq!(ingredient.is_err(), true);
 
+        let ingredient = Ingredient::try_from(Bson::Document(
+            doc! { "id": "0", "amount": "a pinch", "title": "Salz", "measurementUnit": "Gramm" }));
+        assert_eq!(ingredient.is_err(), true);
+
         let ingredient = Ingredient::try_from(Bson::Document(
             doc! { "id": null,"amount": 1000, "measurementUnit": "wrong" }));
         assert_eq!(ingredient.is_err(), true);
@@ -139,15 +148,34 @@ mod ingredients_tests {
     fn from_ingredient_to_bson_test() {
         let ingredient = Ingredient {
             id: "0".to_string(),
-            amount: 200,
+            amount: Quantity::from(200),
             title: "wheat".to_string(),
-            measurement_unit: MeasurementUnit::Kilogramm,
+            measurement_unit: MeasurementUnit::KILOGRAMM,
         };
         let bson: Document = Bson::from(ingredient).as_document().unwrap().to_owned();
 
         assert_eq!(bson.get_str(JSON_ATTR_ID).unwrap(), "0");
         assert_eq!(bson.get_i32(JSON_ATTR_AMOUNT).unwrap(), 200);
         assert_eq!(bson.get_str(JSON_ATTR_TITLE).unwrap(), "wheat");
-        assert_eq!(bson.get_str(JSON_ATTR_MEASUREMENT_UNIT).unwrap(), MeasurementUnit::Kilogramm.to_string());
+        assert_eq!(bson.get_str(JSON_ATTR_MEASUREMENT_UNIT).unwrap(), MeasurementUnit::KILOGRAMM.to_string());
+    }
+
+    #[test]
+    fn keeps_fractions_and_ranges() {
+        let ingredient = Ingredient::try_from(Bson::Document(doc! {
+            "id": "0",
+            "amount": {"min": 2, "max": 3},
+            "title": "Eier",
+            "measurementUnit": "Piece"
+        })).unwrap();
+        assert_eq!(ingredient.amount, Quantity::Range { min: Amount::whole(2), max: Amount::whole(3) });
+
+        let ingredient = Ingredient::new("1", Quantity::Exact(Amount::fraction(1, 2).unwrap()), "Zitrone", MeasurementUnit::PIECE);
+        let bson = Bson::from(ingredient.clone());
+        assert_eq!(bson.as_document().unwrap().get_str(JSON_ATTR_AMOUNT).unwrap(), "½");
+        assert_eq!(Ingredient::try_from(bson).unwrap(), ingredient);
+
+        let json: Ingredient = serde_json::from_str(r#"{"id": "2", "title": "Pfeffer", "measurementUnit": "Prise"}"#).unwrap();
+        assert_eq!(json.amount, Quantity::Unspecified);
     }
 }
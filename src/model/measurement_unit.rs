@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Formatter;
@@ -14,15 +15,57 @@ const STR_MILLILITER: &str = "Milliliter";
 const STR_LITER: &str = "Liter";
 const STR_PIECE: &str = "Piece";
 const STR_PACK: &str = "Pack";
+const STR_OUNCE: &str = "Ounce";
+const STR_POUND: &str = "Pound";
+const STR_CUP: &str = "Cup";
+const STR_TABLESPOON: &str = "Tablespoon";
+const STR_TEASPOON: &str = "Teaspoon";
+const STR_FLUID_OUNCE: &str = "FluidOunce";
+const STR_ESSLOEFFEL: &str = "Essloeffel";
+const STR_TEELOEFFEL: &str = "Teeloeffel";
+const STR_PRISE: &str = "Prise";
+const STR_MESSERSPITZE: &str = "Messerspitze";
+const STR_BUND: &str = "Bund";
+const STR_DOSE: &str = "Dose";
+const STR_ZEHE: &str = "Zehe";
+const STR_SCHEIBE: &str = "Scheibe";
+
+/// The id of a unit of the catalogue, see `model::unit`. Any id can be read, which ones a recipe
+/// may use is checked against the catalogue by `Validate`. The builtin units are the constants,
+/// the imperial ones are the US units, a cup holds 236.6 ml.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq, Hash)]
+#[serde(transparent)]
+pub struct MeasurementUnit(Cow<'static, str>);
+
+impl MeasurementUnit {
+    pub const KILOGRAMM: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_KILOGRAMM));
+    pub const GRAMM: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_GRAMM));
+    pub const MILLILITER: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_MILLILITER));
+    pub const LITER: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_LITER));
+    pub const PIECE: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_PIECE));
+    pub const PACK: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_PACK));
+    pub const OUNCE: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_OUNCE));
+    pub const POUND: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_POUND));
+    pub const CUP: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_CUP));
+    pub const TABLESPOON: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_TABLESPOON));
+    pub const TEASPOON: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_TEASPOON));
+    pub const FLUID_OUNCE: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_FLUID_OUNCE));
+    pub const ESSLOEFFEL: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_ESSLOEFFEL));
+    pub const TEELOEFFEL: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_TEELOEFFEL));
+    pub const PRISE: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_PRISE));
+    pub const MESSERSPITZE: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_MESSERSPITZE));
+    pub const BUND: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_BUND));
+    pub const DOSE: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_DOSE));
+    pub const ZEHE: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_ZEHE));
+    pub const SCHEIBE: MeasurementUnit = MeasurementUnit(Cow::Borrowed(STR_SCHEIBE));
+
+    pub fn new(id: impl Into<String>) -> Self {
+        MeasurementUnit(Cow::Owned(id.into()))
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
-pub enum MeasurementUnit {
-    Kilogramm,
-    Gramm,
-    Milliliter,
-    Liter,
-    Piece,
-    Pack,
+    pub fn id(&self) -> &str {
+        &self.0
+    }
 }
 
 impl From<MeasurementUnit> for Bson {
@@ -31,24 +74,20 @@ impl From<MeasurementUnit> for Bson {
     }
 }
 
+/// Every id but the empty one, whether the catalogue knows it is up to `Validate`.
 impl TryFrom<&str> for MeasurementUnit {
     type Error = RecipeFormatError;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            STR_KILOGRAMM => Ok(MeasurementUnit::Kilogramm),
-            STR_GRAMM => Ok(MeasurementUnit::Gramm),
-            STR_MILLILITER => Ok(MeasurementUnit::Milliliter),
-            STR_LITER => Ok(MeasurementUnit::Liter),
-            STR_PIECE => Ok(MeasurementUnit::Piece),
-            STR_PACK => Ok(MeasurementUnit::Pack),
-            _ => Err(format!("Could not create MeasurementUnit from string: {}", value).into())
+        if value.is_empty() {
+            return Err("Could not create MeasurementUnit from an empty string".into());
         }
+        Ok(MeasurementUnit::new(value))
     }
 }
 
 impl fmt::Display for MeasurementUnit {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "{:?}", self)
+        write!(f, "{}", self.0)
     }
 }
 
@@ -60,36 +99,57 @@ mod measurement_unit_tests {
     use bson::Bson;
 
     use crate::model::measurement_unit::{MeasurementUnit,
+                                         STR_CUP,
+                                         STR_ESSLOEFFEL,
+                                         STR_FLUID_OUNCE,
                                          STR_GRAMM,
                                          STR_KILOGRAMM,
                                          STR_LITER,
                                          STR_MILLILITER,
+                                         STR_OUNCE,
                                          STR_PACK,
-                                         STR_PIECE};
+                                         STR_PIECE,
+                                         STR_POUND,
+                                         STR_TABLESPOON,
+                                         STR_TEASPOON};
 
     #[test]
     fn measurement_unit_to_bson_test() {
-        assert_eq!(Bson::from(MeasurementUnit::Kilogramm).as_str().unwrap(), STR_KILOGRAMM);
-        assert_eq!(Bson::from(MeasurementUnit::Gramm).as_str().unwrap(), STR_GRAMM);
-        assert_eq!(Bson::from(MeasurementUnit::Milliliter).as_str().unwrap(), STR_MILLILITER);
-        assert_eq!(Bson::from(MeasurementUnit::Liter).as_str().unwrap(), STR_LITER);
-        assert_eq!(Bson::from(MeasurementUnit::Piece).as_str().unwrap(), STR_PIECE);
-        assert_eq!(Bson::from(MeasurementUnit::Pack).as_str().unwrap(), STR_PACK);
+        assert_eq!(Bson::from(MeasurementUnit::KILOGRAMM).as_str().unwrap(), STR_KILOGRAMM);
+        assert_eq!(Bson::from(MeasurementUnit::GRAMM).as_str().unwrap(), STR_GRAMM);
+        assert_eq!(Bson::from(MeasurementUnit::MILLILITER).as_str().unwrap(), STR_MILLILITER);
+        assert_eq!(Bson::from(MeasurementUnit::LITER).as_str().unwrap(), STR_LITER);
+        assert_eq!(Bson::from(MeasurementUnit::PIECE).as_str().unwrap(), STR_PIECE);
+        assert_eq!(Bson::from(MeasurementUnit::PACK).as_str().unwrap(), STR_PACK);
+        assert_eq!(Bson::from(MeasurementUnit::FLUID_OUNCE).as_str().unwrap(), STR_FLUID_OUNCE);
+        assert_eq!(Bson::from(MeasurementUnit::ESSLOEFFEL).as_str().unwrap(), STR_ESSLOEFFEL);
     }
 
 
     #[test]
     fn string_to_measurement_unit_test() {
-        assert_eq!(MeasurementUnit::try_from(STR_KILOGRAMM).unwrap(), MeasurementUnit::Kilogramm);
-        assert_eq!(MeasurementUnit::try_from(STR_GRAMM).unwrap(), MeasurementUnit::Gramm);
-        assert_eq!(MeasurementUnit::try_from(STR_MILLILITER).unwrap(), MeasurementUnit::Milliliter);
-        assert_eq!(MeasurementUnit::try_from(STR_LITER).unwrap(), MeasurementUnit::Liter);
-        assert_eq!(MeasurementUnit::try_from(STR_PIECE).unwrap(), MeasurementUnit::Piece);
-        assert_eq!(MeasurementUnit::try_from(STR_PACK).unwrap(), MeasurementUnit::Pack);
-        assert_eq!(MeasurementUnit::try_from("kilogramm").is_err(), true);
-        assert_eq!(MeasurementUnit::try_from("grammm").is_err(), true);
+        assert_eq!(MeasurementUnit::try_from(STR_KILOGRAMM).unwrap(), MeasurementUnit::KILOGRAMM);
+        assert_eq!(MeasurementUnit::try_from(STR_GRAMM).unwrap(), MeasurementUnit::GRAMM);
+        assert_eq!(MeasurementUnit::try_from(STR_MILLILITER).unwrap(), MeasurementUnit::MILLILITER);
+        assert_eq!(MeasurementUnit::try_from(STR_LITER).unwrap(), MeasurementUnit::LITER);
+        assert_eq!(MeasurementUnit::try_from(STR_PIECE).unwrap(), MeasurementUnit::PIECE);
+        assert_eq!(MeasurementUnit::try_from(STR_PACK).unwrap(), MeasurementUnit::PACK);
+        assert_eq!(MeasurementUnit::try_from(STR_OUNCE).unwrap(), MeasurementUnit::OUNCE);
+        assert_eq!(MeasurementUnit::try_from(STR_POUND).unwrap(), MeasurementUnit::POUND);
+        assert_eq!(MeasurementUnit::try_from(STR_CUP).unwrap(), MeasurementUnit::CUP);
+        assert_eq!(MeasurementUnit::try_from(STR_TABLESPOON).unwrap(), MeasurementUnit::TABLESPOON);
+        assert_eq!(MeasurementUnit::try_from(STR_TEASPOON).unwrap(), MeasurementUnit::TEASPOON);
+        assert_eq!(MeasurementUnit::try_from(STR_FLUID_OUNCE).unwrap(), MeasurementUnit::FLUID_OUNCE);
+        assert_eq!(MeasurementUnit::try_from("Bucket").unwrap().id(), "Bucket");
         assert_eq!(MeasurementUnit::try_from("").is_err(), true);
     }
-}
-
 
+    #[test]
+    fn json_keeps_the_id() {
+        assert_eq!(serde_json::to_string(&MeasurementUnit::KILOGRAMM).unwrap(), "\"Kilogramm\"");
+        let unit: MeasurementUnit = serde_json::from_str("\"Kilogramm\"").unwrap();
+        assert_eq!(unit, MeasurementUnit::KILOGRAMM);
+        let unit: MeasurementUnit = serde_json::from_str("\"Bund\"").unwrap();
+        assert_eq!(unit, MeasurementUnit::BUND);
+    }
+}
@@ -1,4 +1,9 @@
 pub mod recipe;
 pub mod ingredients;
+pub mod gallery;
 pub mod difficulty;
+pub mod conversion;
 pub mod measurement_unit;
+pub mod quantity;
+pub mod scaling;
+pub mod unit;
@@ -0,0 +1,451 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::Formatter;
+
+use bson::{Bson, Document};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::SerializeMap;
+
+use crate::model::recipe::RecipeFormatError;
+
+const TO_TASTE: &str = "to taste";
+const TO_TASTE_DE: &str = "nach Geschmack";
+const JSON_ATTR_MIN: &str = "min";
+const JSON_ATTR_MAX: &str = "max";
+
+/// the unicode vulgar fractions, numerator and denominator
+const FRACTIONS: &[(char, i64, i64)] = &[
+    ('½', 1, 2), ('⅓', 1, 3), ('⅔', 2, 3), ('¼', 1, 4), ('¾', 3, 4),
+    ('⅕', 1, 5), ('⅖', 2, 5), ('⅗', 3, 5), ('⅘', 4, 5), ('⅙', 1, 6), ('⅚', 5, 6),
+    ('⅛', 1, 8), ('⅜', 3, 8), ('⅝', 5, 8), ('⅞', 7, 8),
+];
+
+/// An exact number, kept as reduced fraction. A fraction like ½ is written as fraction again,
+/// whole numbers and decimals like 1.5 as JSON numbers.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Amount {
+    numerator: i64,
+    denominator: i64,
+    fraction: bool,
+}
+
+impl Amount {
+    pub fn whole(number: i64) -> Self {
+        Amount { numerator: number, denominator: 1, fraction: false }
+    }
+
+    /// None for a zero denominator
+    pub fn fraction(numerator: i64, denominator: i64) -> Option<Self> {
+        Self::reduced(numerator, denominator, true)
+    }
+
+    fn reduced(numerator: i64, denominator: i64, fraction: bool) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        let (numerator, denominator) = (numerator / divisor, denominator / divisor);
+        Some(Amount { numerator, denominator, fraction: fraction && denominator != 1 })
+    }
+
+    /// `1.25` or `1,25`, None when the digits do not fit into an i64
+    fn decimal(text: &str) -> Option<Self> {
+        let (whole, decimals) = text.split_once(['.', ',']).unwrap_or((text, ""));
+        if whole.is_empty() && decimals.is_empty()
+            || !whole.chars().chain(decimals.chars()).all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let denominator = 10_i64.checked_pow(decimals.len() as u32)?;
+        let numerator = format!("{}{}", whole, decimals).parse().ok()?;
+        Self::reduced(numerator, denominator, false)
+    }
+
+    /// the shortest decimal that reads as `value` again
+    fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        Self::parse(&value.to_string())
+    }
+
+    /// `2`, `1.5`, `½`, `1½`, `1 ½`, `1/2` or `1 1/2`
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text.trim_start()),
+            None => (false, text),
+        };
+        let amount = if let Some(&(glyph, numerator, denominator)) = FRACTIONS.iter().find(|(glyph, _, _)| text.ends_with(*glyph)) {
+            let whole = text[..text.len() - glyph.len_utf8()].trim();
+            Self::mixed(if whole.is_empty() { "0" } else { whole }, numerator, denominator)?
+        } else if let Some((left, denominator)) = text.split_once('/') {
+            let (whole, numerator) = left.trim().rsplit_once(' ').unwrap_or(("0", left));
+            Self::mixed(whole.trim(), numerator.trim().parse().ok()?, denominator.trim().parse().ok()?)?
+        } else {
+            Self::decimal(text)?
+        };
+        Some(if negative { Amount { numerator: -amount.numerator, ..amount } } else { amount })
+    }
+
+    /// `whole` and a fraction like the 1 and ½ of 1½
+    fn mixed(whole: &str, numerator: i64, denominator: i64) -> Option<Self> {
+        if !whole.chars().all(|c| c.is_ascii_digit()) || numerator < 0 || denominator <= 0 {
+            return None;
+        }
+        let whole: i64 = whole.parse().ok()?;
+        Self::fraction(whole.checked_mul(denominator)?.checked_add(numerator)?, denominator)
+    }
+
+    pub fn value(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+/// greatest common divisor, 1 for zero
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let rest = a % b;
+        a = b;
+        b = rest;
+    }
+    a.max(1)
+}
+
+impl fmt::Display for Amount {
+    /// fractions with the unicode character if there is one, `1½` but `2 3/7`
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        if !self.fraction {
+            return write!(f, "{}", if self.denominator == 1 { self.numerator.to_string() } else { self.value().to_string() });
+        }
+        let (whole, rest) = (self.numerator / self.denominator, (self.numerator % self.denominator).abs());
+        if self.numerator < 0 {
+            write!(f, "-")?;
+        }
+        let glyph = FRACTIONS.iter().find(|(_, numerator, denominator)| *numerator == rest && *denominator == self.denominator);
+        match (whole.abs(), glyph) {
+            (0, Some((glyph, _, _))) => write!(f, "{}", glyph),
+            (whole, Some((glyph, _, _))) => write!(f, "{}{}", whole, glyph),
+            (0, None) => write!(f, "{}/{}", rest, self.denominator),
+            (whole, None) => write!(f, "{} {}/{}", whole, rest, self.denominator),
+        }
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Amount { fraction: true, .. } => serializer.collect_str(self),
+            Amount { numerator, denominator: 1, .. } => serializer.serialize_i64(*numerator),
+            amount => serializer.serialize_f64(amount.value()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match deserializer.deserialize_any(QuantityVisitor)? {
+            Quantity::Exact(amount) => Ok(amount),
+            _ => Err(de::Error::custom("expected a number or a fraction like \"½\"")),
+        }
+    }
+}
+
+impl From<Amount> for Bson {
+    fn from(amount: Amount) -> Self {
+        match amount {
+            Amount { fraction: true, .. } => Bson::String(amount.to_string()),
+            Amount { numerator, denominator: 1, .. } => i32::try_from(numerator).map(Bson::Int32).unwrap_or(Bson::Int64(numerator)),
+            amount => Bson::Double(amount.value()),
+        }
+    }
+}
+
+/// How much of an ingredient a recipe takes. JSON has numbers for whole and decimal amounts,
+/// strings for fractions like `"1½"` and `"to taste"`, `{"min": 2, "max": 3}` for ranges and
+/// null for unspecified amounts. BSON stores the same, integers of older documents are exact amounts.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub enum Quantity {
+    Exact(Amount),
+    /// from `min` to `max`, like 2–3 eggs
+    Range { min: Amount, max: Amount },
+    /// as much as the cook likes
+    ToTaste,
+    /// no amount at all, like oil for the pan
+    #[default]
+    Unspecified,
+}
+
+impl Quantity {
+    /// `200`, `1½`, `2–3`, `2-3`, `to taste`, `nach Geschmack`, an empty text is unspecified
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Some(Quantity::Unspecified);
+        }
+        if text.eq_ignore_ascii_case(TO_TASTE) || text.eq_ignore_ascii_case(TO_TASTE_DE) {
+            return Some(Quantity::ToTaste);
+        }
+        match text.char_indices().skip(1).find(|(_, c)| *c == '–' || *c == '-') {
+            Some((index, dash)) => Some(Quantity::Range {
+                min: Amount::parse(&text[..index])?,
+                max: Amount::parse(&text[index + dash.len_utf8()..])?,
+            }),
+            None => Amount::parse(text).map(Quantity::Exact),
+        }
+    }
+
+    /// the values of the amounts, min before max, none for quantities without amount
+    pub fn values(&self) -> Vec<f64> {
+        match self {
+            Quantity::Exact(amount) => vec![amount.value()],
+            Quantity::Range { min, max } => vec![min.value(), max.value()],
+            Quantity::ToTaste | Quantity::Unspecified => vec![],
+        }
+    }
+
+    /// the same kind of quantity with whole amounts, `values` in the order `values()` returns them
+    pub fn with_whole(&self, values: &[f64]) -> Self {
+        match (self, values) {
+            (Quantity::Exact(_), [value]) => Quantity::Exact(Amount::whole(*value as i64)),
+            (Quantity::Range { .. }, [min, max]) => Quantity::Range { min: Amount::whole(*min as i64), max: Amount::whole(*max as i64) },
+            _ => self.clone()
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.values().iter().any(|value| *value < 0.0)
+    }
+
+    /// false for ranges whose min is larger than their max
+    pub fn is_ordered(&self) -> bool {
+        match self {
+            Quantity::Range { min, max } => min.value() <= max.value(),
+            _ => true
+        }
+    }
+}
+
+impl From<i32> for Quantity {
+    fn from(amount: i32) -> Self {
+        Quantity::Exact(Amount::whole(amount as i64))
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Quantity::Exact(amount) => write!(f, "{}", amount),
+            Quantity::Range { min, max } => write!(f, "{}–{}", min, max),
+            Quantity::ToTaste => write!(f, "{}", TO_TASTE),
+            Quantity::Unspecified => Ok(()),
+        }
+    }
+}
+
+impl Serialize for Quantity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Quantity::Exact(amount) => amount.serialize(serializer),
+            Quantity::Range { min, max } => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry(JSON_ATTR_MIN, min)?;
+                map.serialize_entry(JSON_ATTR_MAX, max)?;
+                map.end()
+            }
+            Quantity::ToTaste => serializer.serialize_str(TO_TASTE),
+            Quantity::Unspecified => serializer.serialize_none(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(QuantityVisitor)
+    }
+}
+
+struct QuantityVisitor;
+
+impl<'de> de::Visitor<'de> for QuantityVisitor {
+    type Value = Quantity;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "a number, a fraction like \"½\", {{\"min\": .., \"max\": ..}}, \"{}\" or null", TO_TASTE)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Quantity, E> {
+        Ok(Quantity::Exact(Amount::whole(value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Quantity, E> {
+        i64::try_from(value)
+            .map(|value| Quantity::Exact(Amount::whole(value)))
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Quantity, E> {
+        Amount::from_f64(value)
+            .map(Quantity::Exact)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Float(value), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Quantity, E> {
+        Quantity::parse(value).ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Quantity, E> {
+        Ok(Quantity::Unspecified)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Quantity, E> {
+        Ok(Quantity::Unspecified)
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Quantity, A::Error> {
+        let (mut min, mut max) = (None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                JSON_ATTR_MIN => min = Some(map.next_value::<Amount>()?),
+                JSON_ATTR_MAX => max = Some(map.next_value::<Amount>()?),
+                _ => return Err(de::Error::unknown_field(&key, &[JSON_ATTR_MIN, JSON_ATTR_MAX])),
+            }
+        }
+        Ok(Quantity::Range {
+            min: min.ok_or_else(|| de::Error::missing_field(JSON_ATTR_MIN))?,
+            max: max.ok_or_else(|| de::Error::missing_field(JSON_ATTR_MAX))?,
+        })
+    }
+}
+
+impl From<Quantity> for Bson {
+    fn from(quantity: Quantity) -> Self {
+        match quantity {
+            Quantity::Exact(amount) => amount.into(),
+            Quantity::Range { min, max } => {
+                let mut doc = Document::new();
+                doc.insert(JSON_ATTR_MIN, min);
+                doc.insert(JSON_ATTR_MAX, max);
+                Bson::Document(doc)
+            }
+            Quantity::ToTaste => Bson::String(TO_TASTE.to_string()),
+            Quantity::Unspecified => Bson::Null,
+        }
+    }
+}
+
+/// Int32 amounts of the documents before quantities read as whole amounts.
+impl TryFrom<&Bson> for Quantity {
+    type Error = RecipeFormatError;
+
+    fn try_from(bson: &Bson) -> Result<Self, Self::Error> {
+        let amount = |bson: Option<&Bson>| match Quantity::try_from(bson.unwrap_or(&Bson::Null))? {
+            Quantity::Exact(amount) => Ok(amount),
+            _ => Err(RecipeFormatError::from("Error getting min and max of a quantity")),
+        };
+        match bson {
+            Bson::Int32(value) => Ok(Quantity::Exact(Amount::whole(*value as i64))),
+            Bson::Int64(value) => Ok(Quantity::Exact(Amount::whole(*value))),
+            Bson::Double(value) => Amount::from_f64(*value).map(Quantity::Exact).ok_or_else(|| "Error converting a double to a quantity".into()),
+            Bson::String(text) => Quantity::parse(text).ok_or_else(|| format!("Error converting '{}' to a quantity", text).into()),
+            Bson::Document(doc) => Ok(Quantity::Range { min: amount(doc.get(JSON_ATTR_MIN))?, max: amount(doc.get(JSON_ATTR_MAX))? }),
+            Bson::Null => Ok(Quantity::Unspecified),
+            _ => Err("Error converting a quantity".into())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod quantity_tests {
+    use std::convert::TryFrom;
+
+    use bson::Bson;
+
+    use crate::model::quantity::{Amount, Quantity};
+
+    fn fraction(numerator: i64, denominator: i64) -> Amount {
+        Amount::fraction(numerator, denominator).unwrap()
+    }
+
+    #[test]
+    fn parses_amounts() {
+        assert_eq!(Amount::parse("200"), Some(Amount::whole(200)));
+        assert_eq!(Amount::parse("1.5").unwrap().value(), 1.5);
+        assert_eq!(Amount::parse("1,5"), Amount::parse("1.5"));
+        assert_eq!(Amount::parse("½"), Some(fraction(1, 2)));
+        assert_eq!(Amount::parse("1½"), Some(fraction(3, 2)));
+        assert_eq!(Amount::parse("1 ½"), Some(fraction(3, 2)));
+        assert_eq!(Amount::parse("1 1/2"), Some(fraction(3, 2)));
+        assert_eq!(Amount::parse("2/4"), Some(fraction(1, 2)));
+        assert_eq!(Amount::parse("4/2"), Some(Amount::whole(2)));
+        assert_eq!(Amount::parse("-1"), Some(Amount::whole(-1)));
+        assert_eq!(Amount::parse("1/0"), None);
+        assert_eq!(Amount::parse("a pinch"), None);
+        assert_eq!(Amount::parse("1.2.3"), None);
+        assert_eq!(Amount::parse("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn renders_unicode_fractions() {
+        assert_eq!(fraction(1, 2).to_string(), "½");
+        assert_eq!(fraction(7, 4).to_string(), "1¾");
+        assert_eq!(fraction(-1, 3).to_string(), "-⅓");
+        assert_eq!(fraction(17, 7).to_string(), "2 3/7");
+        assert_eq!(Amount::parse("1.25").unwrap().to_string(), "1.25");
+        assert_eq!(Quantity::parse("2–3").unwrap().to_string(), "2–3");
+        assert_eq!(Quantity::ToTaste.to_string(), "to taste");
+    }
+
+    #[test]
+    fn parses_quantities() {
+        assert_eq!(Quantity::parse("2-3"), Some(Quantity::Range { min: Amount::whole(2), max: Amount::whole(3) }));
+        assert_eq!(Quantity::parse("½ – 1"), Some(Quantity::Range { min: fraction(1, 2), max: Amount::whole(1) }));
+        assert_eq!(Quantity::parse("-2"), Some(Quantity::Exact(Amount::whole(-2))));
+        assert_eq!(Quantity::parse("Nach Geschmack"), Some(Quantity::ToTaste));
+        assert_eq!(Quantity::parse(""), Some(Quantity::Unspecified));
+        assert_eq!(Quantity::parse("2-"), None);
+        assert!(!Quantity::parse("3-2").unwrap().is_ordered());
+    }
+
+    #[test]
+    fn json_of_quantities() {
+        let cases = vec![
+            (serde_json::json!(200), Quantity::from(200)),
+            (serde_json::json!(1.5), Quantity::Exact(Amount::parse("1.5").unwrap())),
+            (serde_json::json!("1½"), Quantity::Exact(fraction(3, 2))),
+            (serde_json::json!({"min": 2, "max": "2½"}), Quantity::Range { min: Amount::whole(2), max: fraction(5, 2) }),
+            (serde_json::json!("to taste"), Quantity::ToTaste),
+            (serde_json::json!(null), Quantity::Unspecified),
+        ];
+        for (json, quantity) in cases {
+            assert_eq!(serde_json::from_value::<Quantity>(json.clone()).unwrap(), quantity);
+            assert_eq!(serde_json::to_value(&quantity).unwrap(), json);
+        }
+        assert_eq!(serde_json::from_value::<Quantity>(serde_json::json!("1 1/2")).unwrap().to_string(), "1½");
+        assert!(serde_json::from_value::<Quantity>(serde_json::json!("a pinch")).is_err());
+        assert!(serde_json::from_value::<Quantity>(serde_json::json!({"min": 2})).is_err());
+        assert!(serde_json::from_value::<Quantity>(serde_json::json!({"min": 2, "max": "to taste"})).is_err());
+        assert!(serde_json::from_value::<Quantity>(serde_json::json!(true)).is_err());
+    }
+
+    #[test]
+    fn bson_of_quantities() {
+        let cases = vec![
+            Quantity::from(200),
+            Quantity::Exact(Amount::parse("0.75").unwrap()),
+            Quantity::Exact(fraction(1, 3)),
+            Quantity::Range { min: fraction(1, 2), max: Amount::whole(1) },
+            Quantity::ToTaste,
+            Quantity::Unspecified,
+        ];
+        for quantity in cases {
+            assert_eq!(Quantity::try_from(&Bson::from(quantity.clone())).unwrap(), quantity);
+        }
+        assert_eq!(Bson::from(Quantity::from(200)), Bson::Int32(200));
+        assert_eq!(Bson::from(Quantity::Exact(fraction(1, 2))), Bson::String("½".to_string()));
+        assert_eq!(Quantity::try_from(&Bson::Int64(5)).unwrap(), Quantity::from(5));
+        assert!(Quantity::try_from(&Bson::Boolean(true)).is_err());
+        assert!(Quantity::try_from(&Bson::String("a pinch".to_string())).is_err());
+    }
+}
@@ -7,7 +7,11 @@ use serde::{Deserialize, Serializer};
 use serde::Serialize;
 
 use crate::model::difficulty::Difficulty;
+use crate::model::gallery::GalleryImage;
+use crate::model::conversion;
 use crate::model::ingredients::Ingredient;
+use crate::model::scaling::{self, ScaledRecipe};
+use crate::model::unit::{UnitCatalogue, UnitSystem};
 
 const JSON_ATTR_ID: &str = "_id";
 const JSON_ATTR_COOKING_TIME: &str = "cookingTimeInMinutes";
@@ -22,6 +26,7 @@ const JSON_ATTR_TAGS: &str = "tags";
 const JSON_ATTR_IMAGE: &str = "image";
 const JSON_ATTR_INSTRUCTIONS: &str = "instructions";
 const JSON_ATTR_DEFAULT_SERVINGS: &str = "defaultServings";
+const JSON_ATTR_GALLERY: &str = "gallery";
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct Recipe {
@@ -31,8 +36,10 @@ pub struct Recipe {
     pub _id: ObjectId,
     #[serde(rename = "cookingTimeInMinutes")]
     pub cooking_time_in_minutes: u32,
+    /// created and lastModified are set by the server, clients may leave them out
+    #[serde(default = "Utc::now")]
     pub created: DateTime<Utc>,
-    #[serde(rename = "lastModified")]
+    #[serde(rename = "lastModified", default = "Utc::now")]
     pub last_modified: DateTime<Utc>,
     pub ingredients: Vec<Ingredient>,
     pub version: u32,
@@ -40,12 +47,16 @@ pub struct Recipe {
     pub description: String,
     pub title: String,
     pub tags: Vec<String>,
+    /// `sha256:<hex>` of the image store, base64 as sent for images not moved yet
     #[serde(rename = "image")]
     // #[serde(skip_deserializing)]
     pub image_base64: Option<String>,
     pub instructions: Vec<String>,
     #[serde(rename = "defaultServings")]
     pub default_servings: u32,
+    /// changed by the gallery routes only, a recipe sent by a client keeps the stored one
+    #[serde(default)]
+    pub gallery: Vec<GalleryImage>,
 }
 
 
@@ -83,6 +94,7 @@ impl TryFrom<Document> for Recipe {
             image_base64: Recipe::extract_image(&doc)?,
             instructions: Recipe::extract_instructions(&doc)?,
             default_servings: Recipe::extract_default_servings(&doc)?,
+            gallery: Recipe::extract_gallery(&doc)?,
         });
     }
 }
@@ -100,14 +112,47 @@ impl From<Recipe> for Document {
         doc.insert(JSON_ATTR_DESCRIPTION, recipe.description);
         doc.insert(JSON_ATTR_TITLE, recipe.title);
         doc.insert(JSON_ATTR_TAGS, recipe.tags);
-        doc.insert(JSON_ATTR_IMAGE, recipe.image_base64.map_or_else(|| Bson::Null, |oid| Bson::String(oid)));
+        doc.insert(JSON_ATTR_IMAGE, recipe.image_base64.map_or_else(|| Bson::Null, Bson::String));
         doc.insert(JSON_ATTR_INSTRUCTIONS, recipe.instructions);
         doc.insert(JSON_ATTR_DEFAULT_SERVINGS, recipe.default_servings);
+        doc.insert(JSON_ATTR_GALLERY, recipe.gallery);
         doc
     }
 }
 
 impl Recipe {
+    /// strong entity tag of this version, sent as `ETag` and expected in `If-Match`
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.version)
+    }
+
+    /// The recipe for `servings`, every amount scaled by `servings / defaultServings`, see `scaling::scale_amount`.
+    /// None for recipes without default servings, there is nothing to scale from.
+    pub fn scaled(&self, servings: u32) -> Option<ScaledRecipe> {
+        if self.default_servings == 0 {
+            return None;
+        }
+        let scale_factor = servings as f64 / self.default_servings as f64;
+        let mut recipe = self.clone();
+        recipe.default_servings = servings;
+        for ingredient in recipe.ingredients.iter_mut() {
+            let (amount, unit) = scaling::scale_amount(&ingredient.amount, &ingredient.measurement_unit, scale_factor);
+            ingredient.amount = amount;
+            ingredient.measurement_unit = unit;
+        }
+        Some(ScaledRecipe { recipe, scale_factor })
+    }
+
+    /// The recipe with its amounts in the units of `system`, see `conversion::to_system`.
+    pub fn in_units(mut self, system: UnitSystem, units: &UnitCatalogue) -> Recipe {
+        for ingredient in self.ingredients.iter_mut() {
+            let (amount, unit) = conversion::to_system(&ingredient.amount, &ingredient.measurement_unit, &ingredient.title, system, units);
+            ingredient.amount = amount;
+            ingredient.measurement_unit = unit;
+        }
+        self
+    }
+
     pub fn default_projection_no_image() -> Document {
         let mut doc = Document::new();
         doc.insert(JSON_ATTR_IMAGE, 0);
@@ -132,7 +177,7 @@ impl Recipe {
         doc.get_array(JSON_ATTR_TAGS)
             .map_err(|_| RecipeFormatError::from("Error getting tag from document"))
             .map(|tags| {
-                tags.into_iter()
+                tags.iter()
                     .map(|f| f.as_str().map(String::from))
                     .collect::<Option<Vec<String>>>()
                     .ok_or_else(|| RecipeFormatError::from("Error getting tag from document"))
@@ -152,13 +197,24 @@ impl Recipe {
     fn extract_instructions(doc: &Document) -> Result<Vec<String>, RecipeFormatError> {
         doc.get_array(JSON_ATTR_INSTRUCTIONS)
             .map_err(|_| RecipeFormatError::from("Error getting instructions from document"))
-            .map(|instructions| instructions.into_iter()
+            .map(|instructions| instructions.iter()
                 .map(|instruction| instruction.as_str().map(String::from))
                 .collect::<Option<Vec<String>>>()
                 .ok_or_else(|| RecipeFormatError::from("Error getting instructions from document"))
             )?
     }
 
+    /// recipes stored before galleries existed have none
+    fn extract_gallery(doc: &Document) -> Result<Vec<GalleryImage>, RecipeFormatError> {
+        match doc.get(JSON_ATTR_GALLERY) {
+            None | Some(Bson::Null) => Ok(vec![]),
+            Some(Bson::Array(images)) => images.iter()
+                .map(|image| GalleryImage::try_from(image.clone()))
+                .collect(),
+            _ => Err(RecipeFormatError::from("Error getting gallery from document"))
+        }
+    }
+
     fn extract_default_servings(doc: &Document) -> Result<u32, RecipeFormatError> {
         doc.get_i32(JSON_ATTR_DEFAULT_SERVINGS)
             .map(|x| if x < 1 { 1 } else { x as u32 })
@@ -186,9 +242,8 @@ impl Recipe {
     fn extract_ingredients(doc: &Document) -> Result<Vec<Ingredient>, RecipeFormatError> {
         doc.get_array(JSON_ATTR_INGREDIENTS)
             .map_err(|_| RecipeFormatError::from("Error getting ingredients from document"))
-            .map(|ingredients| ingredients.into_iter()
-                .map(|ing| Ingredient::try_from(ing.clone())
-                    .map_err(|_| RecipeFormatError::from("")))
+            .map(|ingredients| ingredients.iter()
+                .map(|ing| Ingredient::try_from(ing.clone()))
                 .collect::<Result<Vec<Ingredient>, RecipeFormatError>>()
             )?
     }
@@ -256,9 +311,9 @@ mod convert_tests {
         doc.insert(JSON_ATTR_LAST_MODIFIED, DateTime::from(SystemTime::now()));
         doc.insert(JSON_ATTR_INGREDIENTS, vec![
             Ingredient::new("0", 100, "Cheese",
-                            MeasurementUnit::Kilogramm),
+                            MeasurementUnit::KILOGRAMM),
             Ingredient::new("1", 200, "Bread",
-                            MeasurementUnit::Piece)]);
+                            MeasurementUnit::PIECE)]);
         doc.insert(JSON_ATTR_VERSION, 1);
         doc.insert(JSON_ATTR_DIFFICULTY, Difficulty::Easy);
         doc.insert(JSON_ATTR_DESCRIPTION, "Recipe desciption");
@@ -517,22 +572,22 @@ mod recipe_tests {
 
         doc.insert(JSON_ATTR_INGREDIENTS, vec![
             Ingredient::new("0", 100, "Cheese",
-                            MeasurementUnit::Kilogramm)]);
+                            MeasurementUnit::KILOGRAMM)]);
         let result = Recipe::extract_ingredients(&doc);
         assert_eq!(result.is_ok(), true);
 
         let mut ing = Document::new();
         ing.insert("id", "0");
-        ing.insert("amount", "100");
+        ing.insert("amount", "a pinch");
         doc.insert(JSON_ATTR_INGREDIENTS, vec![
             ing,
             Ingredient::new("0",
                             100,
                             "Cheese",
-                            MeasurementUnit::Kilogramm).into()
+                            MeasurementUnit::KILOGRAMM).into()
         ]);
         let result = Recipe::extract_ingredients(&doc);
-        assert_eq!(result.is_err(), true);
+        assert_eq!(result.unwrap_err().error, "Error getting amount from ingredient from document");
     }
 
 
@@ -0,0 +1,158 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::model::measurement_unit::MeasurementUnit;
+use crate::model::quantity::Quantity;
+use crate::model::recipe::Recipe;
+
+/// `GET /recipes/{id}?servings=4`
+#[derive(Deserialize, Debug, Default)]
+pub struct ServingsQuery {
+    pub servings: Option<u32>,
+}
+
+/// The unit a fraction of a larger unit is given in and how many of them the larger one holds.
+const SMALLER: &[(MeasurementUnit, MeasurementUnit, f64)] = &[
+    (MeasurementUnit::KILOGRAMM, MeasurementUnit::GRAMM, 1000.0),
+    (MeasurementUnit::LITER, MeasurementUnit::MILLILITER, 1000.0),
+    (MeasurementUnit::POUND, MeasurementUnit::OUNCE, 16.0),
+    (MeasurementUnit::CUP, MeasurementUnit::TABLESPOON, 16.0),
+    (MeasurementUnit::TABLESPOON, MeasurementUnit::TEASPOON, 3.0),
+    (MeasurementUnit::ESSLOEFFEL, MeasurementUnit::TEELOEFFEL, 3.0),
+];
+
+/// A recipe for other servings than it was written for. `defaultServings` holds the requested
+/// servings, the amounts were multiplied by `scaleFactor`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScaledRecipe {
+    #[serde(flatten)]
+    pub recipe: Recipe,
+    pub scale_factor: f64,
+}
+
+/// The amounts multiplied by `factor` and rounded to what a cook can measure, in the unit they are
+/// given in. Pieces and packs stay whole, grams and milliliters get coarser the larger they are.
+/// Amounts of larger units that are no longer whole are given in the next smaller unit, see `SMALLER`,
+/// both ends of a range alike. An ingredient never scales down to nothing, amounts to taste stay as they are.
+pub fn scale_amount(quantity: &Quantity, unit: &MeasurementUnit, factor: f64) -> (Quantity, MeasurementUnit) {
+    let amounts = quantity.values();
+    if factor == 1.0 || amounts.is_empty() {
+        return (quantity.clone(), unit.clone());
+    }
+    let scaled: Vec<f64> = amounts.iter().map(|amount| amount * factor).collect();
+    let (scaled, unit) = match SMALLER.iter().find(|(larger, _, _)| larger == unit) {
+        Some((_, smaller, holds)) if scaled.iter().any(|amount| amount.fract() != 0.0) =>
+            (scaled.iter().map(|amount| amount * holds).collect(), smaller.clone()),
+        _ => (scaled, unit.clone())
+    };
+    let metric = unit == MeasurementUnit::GRAMM || unit == MeasurementUnit::MILLILITER;
+    let rounded: Vec<f64> = scaled.iter().zip(&amounts)
+        .map(|(scaled, amount)| {
+            let rounded = if metric { round_metric(*scaled) } else { scaled.round() };
+            if *amount > 0.0 && rounded < 1.0 { 1.0 } else { rounded }
+        })
+        .collect();
+    (quantity.with_whole(&rounded), unit)
+}
+
+/// grams or milliliters rounded to what can be measured
+pub fn round_metric(amount: f64) -> f64 {
+    round_to(amount, step(amount))
+}
+
+/// the precision of grams and milliliters, a scale shows single grams, a cup does not
+fn step(amount: f64) -> f64 {
+    match amount {
+        amount if amount < 10.0 => 1.0,
+        amount if amount < 100.0 => 5.0,
+        amount if amount < 1000.0 => 10.0,
+        _ => 50.0,
+    }
+}
+
+fn round_to(amount: f64, step: f64) -> f64 {
+    (amount / step).round() * step
+}
+
+
+#[cfg(test)]
+mod scaling_tests {
+    use crate::dao::dao_tests::create_one_recipe_without_image;
+    use crate::model::ingredients::Ingredient;
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::quantity::{Amount, Quantity};
+    use crate::model::scaling::scale_amount;
+
+    fn scale(amount: i32, unit: &MeasurementUnit, factor: f64) -> (i32, MeasurementUnit) {
+        let (quantity, unit) = scale_amount(&Quantity::from(amount), unit, factor);
+        (quantity.values()[0] as i32, unit)
+    }
+
+    #[test]
+    fn rounds_per_unit() {
+        assert_eq!(scale(3, &MeasurementUnit::PIECE, 0.5), (2, MeasurementUnit::PIECE));
+        assert_eq!(scale(1, &MeasurementUnit::PACK, 0.25), (1, MeasurementUnit::PACK));
+        assert_eq!(scale(123, &MeasurementUnit::GRAMM, 1.0), (123, MeasurementUnit::GRAMM));
+        assert_eq!(scale(5, &MeasurementUnit::GRAMM, 1.5), (8, MeasurementUnit::GRAMM));
+        assert_eq!(scale(7, &MeasurementUnit::GRAMM, 1.5), (10, MeasurementUnit::GRAMM));
+        assert_eq!(scale(30, &MeasurementUnit::GRAMM, 1.5), (45, MeasurementUnit::GRAMM));
+        assert_eq!(scale(250, &MeasurementUnit::MILLILITER, 0.75), (190, MeasurementUnit::MILLILITER));
+        assert_eq!(scale(500, &MeasurementUnit::GRAMM, 2.7), (1350, MeasurementUnit::GRAMM));
+        assert_eq!(scale(1, &MeasurementUnit::GRAMM, 0.1), (1, MeasurementUnit::GRAMM));
+        assert_eq!(scale(0, &MeasurementUnit::GRAMM, 3.0), (0, MeasurementUnit::GRAMM));
+    }
+
+    #[test]
+    fn fractions_become_smaller_units() {
+        assert_eq!(scale(2, &MeasurementUnit::KILOGRAMM, 1.5), (3, MeasurementUnit::KILOGRAMM));
+        assert_eq!(scale(1, &MeasurementUnit::KILOGRAMM, 1.5), (1500, MeasurementUnit::GRAMM));
+        assert_eq!(scale(1, &MeasurementUnit::LITER, 1.0 / 3.0), (330, MeasurementUnit::MILLILITER));
+        assert_eq!(scale(1, &MeasurementUnit::CUP, 1.5), (24, MeasurementUnit::TABLESPOON));
+        assert_eq!(scale(1, &MeasurementUnit::POUND, 0.5), (8, MeasurementUnit::OUNCE));
+        assert_eq!(scale(3, &MeasurementUnit::TEASPOON, 0.5), (2, MeasurementUnit::TEASPOON));
+        assert_eq!(scale(1, &MeasurementUnit::ESSLOEFFEL, 0.5), (2, MeasurementUnit::TEELOEFFEL));
+        assert_eq!(scale(1, &MeasurementUnit::PRISE, 2.5), (3, MeasurementUnit::PRISE));
+    }
+
+    #[test]
+    fn scales_fractions_and_ranges() {
+        let half = Quantity::Exact(Amount::fraction(1, 2).unwrap());
+        assert_eq!(scale_amount(&half, &MeasurementUnit::PIECE, 1.0), (half.clone(), MeasurementUnit::PIECE));
+        assert_eq!(scale_amount(&half, &MeasurementUnit::PIECE, 4.0), (Quantity::from(2), MeasurementUnit::PIECE));
+        let liter = Quantity::Exact(Amount::parse("1.5").unwrap());
+        assert_eq!(scale_amount(&liter, &MeasurementUnit::LITER, 0.5), (Quantity::from(750), MeasurementUnit::MILLILITER));
+
+        let range = Quantity::Range { min: Amount::whole(1), max: Amount::whole(2) };
+        assert_eq!(scale_amount(&range, &MeasurementUnit::KILOGRAMM, 1.5),
+                   (Quantity::Range { min: Amount::whole(1500), max: Amount::whole(3000) }, MeasurementUnit::GRAMM));
+        assert_eq!(scale_amount(&Quantity::ToTaste, &MeasurementUnit::PRISE, 3.0), (Quantity::ToTaste, MeasurementUnit::PRISE));
+        assert_eq!(scale_amount(&Quantity::Unspecified, &MeasurementUnit::GRAMM, 3.0), (Quantity::Unspecified, MeasurementUnit::GRAMM));
+    }
+
+    #[test]
+    fn scales_recipe_to_servings() {
+        let mut recipe = create_one_recipe_without_image();
+        recipe.default_servings = 4;
+        recipe.ingredients = vec![
+            Ingredient::new("0", 3, "Eier", MeasurementUnit::PIECE),
+            Ingredient::new("1", 500, "Mehl", MeasurementUnit::GRAMM),
+        ];
+
+        let scaled = recipe.scaled(6).unwrap();
+        assert_eq!(scaled.scale_factor, 1.5);
+        assert_eq!(scaled.recipe.default_servings, 6);
+        assert_eq!(scaled.recipe.ingredients, vec![
+            Ingredient::new("0", 5, "Eier", MeasurementUnit::PIECE),
+            Ingredient::new("1", 750, "Mehl", MeasurementUnit::GRAMM),
+        ]);
+        assert_eq!(recipe.scaled(4).unwrap().recipe, recipe);
+
+        let json = serde_json::to_value(&scaled).unwrap();
+        assert_eq!(json["scaleFactor"], 1.5);
+        assert_eq!(json["title"], recipe.title.as_str());
+
+        recipe.default_servings = 0;
+        assert_eq!(recipe.scaled(2), None);
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::model::measurement_unit::MeasurementUnit;
+
+/// What a unit measures, only units of the same dimension convert without a density.
+/// `Other` are the units nobody weighs, like a pinch.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Dimension {
+    Mass,
+    Volume,
+    Count,
+    Other,
+}
+
+/// `?units=metric` or `?units=imperial`
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    Metric,
+    Imperial,
+}
+
+/// An entry of the unit catalogue, `id` is what an ingredient keeps as `measurementUnit`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub id: MeasurementUnit,
+    /// by language, like `de` and `en`
+    pub names: BTreeMap<String, String>,
+    #[serde(default)]
+    pub abbreviations: Vec<String>,
+    pub dimension: Dimension,
+    /// grams of a mass, milliliters of a volume, None for counts and other units
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factor: Option<f64>,
+    /// None for units every system uses, like counts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<UnitSystem>,
+    /// builtin units come with the server and cannot be changed
+    #[serde(default, skip_deserializing)]
+    pub builtin: bool,
+}
+
+/// The builtin units followed by the ones added through `/api/v1/units`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitCatalogue {
+    units: Vec<Unit>,
+}
+
+impl UnitCatalogue {
+    pub fn builtin() -> Self {
+        UnitCatalogue { units: builtin_units() }
+    }
+
+    /// custom units with the id of a builtin one are left out, the builtin one wins
+    pub fn with_custom(custom: Vec<Unit>) -> Self {
+        let mut catalogue = UnitCatalogue::builtin();
+        for mut unit in custom {
+            if catalogue.get(unit.id.id()).is_none() {
+                unit.builtin = false;
+                catalogue.units.push(unit);
+            }
+        }
+        catalogue
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Unit> {
+        self.units.iter().find(|unit| unit.id.id() == id)
+    }
+
+    /// The unit a text calls `word`, by id, name or abbreviation. Exact matches win over ones
+    /// ignoring case, `T` is a tablespoon and `t` a teaspoon.
+    pub fn find(&self, word: &str) -> Option<&Unit> {
+        let words = |unit: &Unit| -> Vec<String> {
+            let mut words = vec![unit.id.to_string()];
+            words.extend(unit.names.values().cloned());
+            words.extend(unit.abbreviations.iter().cloned());
+            words
+        };
+        self.units.iter().find(|unit| words(unit).iter().any(|candidate| candidate == word))
+            .or_else(|| {
+                let word = word.to_lowercase();
+                self.units.iter().find(|unit| words(unit).iter().any(|candidate| candidate.to_lowercase() == word))
+            })
+    }
+
+    pub fn units(&self) -> &[Unit] {
+        &self.units
+    }
+}
+
+fn builtin_units() -> Vec<Unit> {
+    use Dimension::*;
+    use UnitSystem::*;
+    vec![
+        builtin(MeasurementUnit::KILOGRAMM, "Kilogramm", "kilogram", &["kg"], Mass, Some(1000.0), Some(Metric)),
+        builtin(MeasurementUnit::GRAMM, "Gramm", "gram", &["g", "gr"], Mass, Some(1.0), Some(Metric)),
+        builtin(MeasurementUnit::MILLILITER, "Milliliter", "milliliter", &["ml"], Volume, Some(1.0), Some(Metric)),
+        builtin(MeasurementUnit::LITER, "Liter", "liter", &["l"], Volume, Some(1000.0), Some(Metric)),
+        builtin(MeasurementUnit::PIECE, "Stück", "piece", &["Stk", "St", "pc"], Count, None, None),
+        builtin(MeasurementUnit::PACK, "Packung", "pack", &["Pck", "Pkg", "pk"], Count, None, None),
+        builtin(MeasurementUnit::OUNCE, "Unze", "ounce", &["oz"], Mass, Some(28.349_523_125), Some(Imperial)),
+        builtin(MeasurementUnit::POUND, "Pfund", "pound", &["lb", "lbs"], Mass, Some(453.592_37), Some(Imperial)),
+        builtin(MeasurementUnit::CUP, "Cup", "cup", &["c"], Volume, Some(236.588_236_5), Some(Imperial)),
+        builtin(MeasurementUnit::TABLESPOON, "Esslöffel (US)", "tablespoon", &["tbsp", "Tbsp", "T"], Volume, Some(14.786_764_781_25), Some(Imperial)),
+        builtin(MeasurementUnit::TEASPOON, "Teelöffel (US)", "teaspoon", &["tsp", "t"], Volume, Some(4.928_921_593_75), Some(Imperial)),
+        builtin(MeasurementUnit::FLUID_OUNCE, "Flüssigunze", "fluid ounce", &["fl oz", "fl. oz."], Volume, Some(29.573_529_562_5), Some(Imperial)),
+        builtin(MeasurementUnit::ESSLOEFFEL, "Esslöffel", "tablespoon (metric)", &["EL", "Essl."], Volume, Some(15.0), Some(Metric)),
+        builtin(MeasurementUnit::TEELOEFFEL, "Teelöffel", "teaspoon (metric)", &["TL", "Teel."], Volume, Some(5.0), Some(Metric)),
+        builtin(MeasurementUnit::PRISE, "Prise", "pinch", &["Pr."], Other, None, None),
+        builtin(MeasurementUnit::MESSERSPITZE, "Messerspitze", "knife tip", &["Msp."], Other, None, None),
+        builtin(MeasurementUnit::BUND, "Bund", "bunch", &["Bd."], Count, None, None),
+        builtin(MeasurementUnit::DOSE, "Dose", "can", &["Ds."], Count, None, None),
+        builtin(MeasurementUnit::ZEHE, "Zehe", "clove", &[], Count, None, None),
+        builtin(MeasurementUnit::SCHEIBE, "Scheibe", "slice", &["Sch."], Count, None, None),
+    ]
+}
+
+fn builtin(id: MeasurementUnit, de: &str, en: &str, abbreviations: &[&str], dimension: Dimension,
+           factor: Option<f64>, system: Option<UnitSystem>) -> Unit {
+    Unit {
+        id,
+        names: vec![("de".to_string(), de.to_string()), ("en".to_string(), en.to_string())].into_iter().collect(),
+        abbreviations: abbreviations.iter().map(|abbreviation| abbreviation.to_string()).collect(),
+        dimension,
+        factor,
+        system,
+        builtin: true,
+    }
+}
+
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::unit::{Dimension, Unit, UnitCatalogue, UnitSystem};
+
+    fn bucket() -> Unit {
+        serde_json::from_value(serde_json::json!({
+            "id": "Eimer",
+            "names": {"de": "Eimer", "en": "bucket"},
+            "dimension": "volume",
+            "factor": 10000.0,
+            "builtin": true,
+        })).unwrap()
+    }
+
+    #[test]
+    fn knows_every_former_unit() {
+        let catalogue = UnitCatalogue::builtin();
+        for id in &["Kilogramm", "Gramm", "Milliliter", "Liter", "Piece", "Pack"] {
+            assert!(catalogue.get(id).is_some_and(|unit| unit.builtin), "{}", id);
+        }
+        let units = catalogue.units();
+        assert!(units.iter().all(|unit| unit.factor.is_some() == matches!(unit.dimension, Dimension::Mass | Dimension::Volume)));
+        assert!(units.iter().all(|unit| unit.system.is_none() || unit.factor.is_some()));
+        assert_eq!(catalogue.get("Essloeffel").unwrap().factor, Some(15.0));
+        assert_eq!(catalogue.get("Prise").unwrap().dimension, Dimension::Other);
+    }
+
+    #[test]
+    fn finds_units_by_name_and_abbreviation() {
+        let catalogue = UnitCatalogue::builtin();
+        let find = |word: &str| catalogue.find(word).map(|unit| unit.id.clone());
+        assert_eq!(find("EL"), Some(MeasurementUnit::ESSLOEFFEL));
+        assert_eq!(find("el"), Some(MeasurementUnit::ESSLOEFFEL));
+        assert_eq!(find("Esslöffel"), Some(MeasurementUnit::ESSLOEFFEL));
+        assert_eq!(find("T"), Some(MeasurementUnit::TABLESPOON));
+        assert_eq!(find("t"), Some(MeasurementUnit::TEASPOON));
+        assert_eq!(find("fl oz"), Some(MeasurementUnit::FLUID_OUNCE));
+        assert_eq!(find("pinch"), Some(MeasurementUnit::PRISE));
+        assert_eq!(find("Gramm"), Some(MeasurementUnit::GRAMM));
+        assert_eq!(find("Mehl"), None);
+    }
+
+    #[test]
+    fn adds_custom_units() {
+        let bucket = bucket();
+        assert_eq!(bucket.builtin, false);
+        assert_eq!(bucket.abbreviations, Vec::<String>::new());
+        assert_eq!(bucket.system, None);
+
+        let gramm = Unit { id: MeasurementUnit::GRAMM, factor: Some(2.0), system: Some(UnitSystem::Imperial), ..bucket.clone() };
+        let catalogue = UnitCatalogue::with_custom(vec![bucket.clone(), gramm]);
+        assert_eq!(catalogue.get("Eimer"), Some(&bucket));
+        assert_eq!(catalogue.get("Gramm").unwrap().factor, Some(1.0));
+        assert_eq!(catalogue.units().len(), UnitCatalogue::builtin().units().len() + 1);
+        assert_eq!(catalogue.get("eimer"), None);
+
+        assert_eq!(catalogue.find("bucket"), Some(&bucket));
+
+        let json = serde_json::to_value(catalogue.get("Gramm").unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "id": "Gramm",
+            "names": {"de": "Gramm", "en": "gram"},
+            "abbreviations": ["g", "gr"],
+            "dimension": "mass",
+            "factor": 1.0,
+            "system": "metric",
+            "builtin": true,
+        }));
+    }
+}
@@ -1,24 +1,496 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+use bson::oid::ObjectId;
 use serde::Deserialize;
 use serde::Serialize;
 
-#[derive(Deserialize, Serialize, Debug, Copy, Clone)]
+use crate::model::recipe::Recipe;
+
+/// Query of a recipe listing. The filters are kept as sent and parsed by `RecipeFilter`,
+/// so an invalid value can be reported with the name of its parameter.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Pagination {
     pub page: Option<usize>,
     pub items: Option<usize>,
     pub sorting: Option<i32>,
+    /// comma separated fields, '-' sorts descending
+    pub sort: Option<String>,
+    /// empty for the first page of the keyset mode, afterwards the `nextCursor` of the last page
+    pub cursor: Option<String>,
+    /// wraps the page in an object with the totals, like the `PAGE_MEDIA_TYPE` Accept header
+    pub envelope: Option<bool>,
+    /// leaves stored recipes that cannot be read out and lists them under `warnings`
+    pub lenient: Option<bool>,
+    pub difficulty: Option<String>,
+    pub tags: Option<String>,
+    #[serde(rename = "tagMatch")]
+    pub tag_match: Option<String>,
+    #[serde(rename = "minCookingTime")]
+    pub min_cooking_time: Option<String>,
+    #[serde(rename = "maxCookingTime")]
+    pub max_cooking_time: Option<String>,
+    #[serde(rename = "createdAfter")]
+    pub created_after: Option<String>,
+    #[serde(rename = "modifiedSince")]
+    pub modified_since: Option<String>,
+    #[serde(rename = "hasImage")]
+    pub has_image: Option<String>,
+    pub ingredient: Option<String>,
 }
 
 impl Pagination {
     pub fn is_fully_set(&self) -> bool {
         return self.page.is_some() && self.page.unwrap() > 0
             && self.items.is_some() && self.items.unwrap() > 0
-            && self.sorting.is_some() && (self.sorting.unwrap() == 1 || self.sorting.unwrap() == -1);
+            && ((self.sorting.is_some() && (self.sorting.unwrap() == 1 || self.sorting.unwrap() == -1))
+            || (self.sorting.is_none() && self.sort.is_some()));
     }
 
     pub fn is_fully_empty(&self) -> bool {
         return self.page.is_none() && self.items.is_none() && self.sorting.is_none();
     }
+
+    pub fn is_cursor_mode(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// The part of the listing every store applies: order, offset, size and keyset position.
+    pub fn listing(&self) -> Result<Listing, ParameterError> {
+        let sort = self.sort.as_deref().map(Sort::parse).transpose()?;
+
+        if let Some(cursor) = &self.cursor {
+            if self.page.is_some() || self.sorting.is_some() {
+                return Err(ParameterError::new("cursor", "can not be combined with page or sorting"));
+            }
+            let items = match self.items {
+                Some(items) if items > 0 => items,
+                _ => return Err(ParameterError::new("items", "expected the number of recipes per page"))
+            };
+            let sort = sort.unwrap_or_else(|| Sort::created(false));
+            let after = match cursor.as_str() {
+                "" => None,
+                cursor => Some(Cursor::decode(cursor, &sort)?)
+            };
+            return Ok(Listing { sort: Some(sort), skip: 0, limit: Some(items), after });
+        }
+
+        if sort.is_some() && self.sorting.is_some() {
+            return Err(ParameterError::new("sort", "can not be combined with sorting"));
+        }
+        if self.is_fully_empty() {
+            return Ok(Listing { sort, ..Default::default() });
+        }
+        if !self.is_fully_set() {
+            let parameter = match (self.page, self.items) {
+                (Some(page), _) if page > 0 => if self.items.unwrap_or(0) > 0 { "sorting" } else { "items" },
+                _ => "page"
+            };
+            return Err(ParameterError::new(parameter, "page, items and sorting or sort have to be given together"));
+        }
+
+        let items = self.items.unwrap();
+        return Ok(Listing {
+            sort: Some(sort.unwrap_or_else(|| Sort::created(self.sorting.unwrap() < 0))),
+            skip: (self.page.unwrap() - 1) * items,
+            limit: Some(items),
+            after: None,
+        });
+    }
+}
+
+/// Media type of a listing wrapped in an object with the totals.
+pub const PAGE_MEDIA_TYPE: &str = "application/vnd.zellinotes.recipe-page+json";
+
+/// Position of a page or of the unpaginated listing among all matching recipes.
+#[derive(Debug, Eq, PartialEq)]
+pub struct PageInfo {
+    pub page: usize,
+    pub items_per_page: usize,
+    pub total_items: usize,
+    /// at least one, an empty listing still has an empty first page
+    pub total_pages: usize,
+    paged: bool,
+}
+
+impl PageInfo {
+    pub fn new(params: &Pagination, total_items: usize) -> Self {
+        if !params.is_fully_set() {
+            return PageInfo { page: 1, items_per_page: total_items, total_items, total_pages: 1, paged: false };
+        }
+        let items_per_page = params.items.unwrap();
+        PageInfo {
+            page: params.page.unwrap(),
+            items_per_page,
+            total_items,
+            total_pages: total_items.div_ceil(items_per_page).max(1),
+            paged: true,
+        }
+    }
+
+    /// Start of the envelope, the recipes and "]}" have to follow.
+    pub fn envelope_start(&self) -> String {
+        format!("{{\"page\":{},\"itemsPerPage\":{},\"totalItems\":{},\"totalPages\":{},\"items\":[",
+                self.page, self.items_per_page, self.total_items, self.total_pages)
+    }
+
+    /// RFC 8288 Link header value with first, prev, next and last page, None without pagination.
+    /// The other parameters of the query are kept as sent.
+    pub fn links(&self, path: &str, query: &str) -> Option<String> {
+        if !self.paged {
+            return None;
+        }
+        let others: Vec<&str> = query.split('&')
+            .filter(|pair| !pair.is_empty() && !pair.starts_with("page="))
+            .collect();
+        let link = |page: usize, rel: &str| {
+            let mut pairs = others.clone();
+            let page = format!("page={}", page);
+            pairs.push(&page);
+            format!("<{}?{}>; rel=\"{}\"", path, pairs.join("&"), rel)
+        };
+
+        let mut links = vec![link(1, "first")];
+        if self.page > 1 {
+            links.push(link((self.page - 1).min(self.total_pages), "prev"));
+        }
+        if self.page < self.total_pages {
+            links.push(link(self.page + 1, "next"));
+        }
+        links.push(link(self.total_pages, "last"));
+        return Some(links.join(", "));
+    }
 }
 
+#[derive(Debug, Serialize, Eq, PartialEq)]
+pub struct ParameterError {
+    pub parameter: &'static str,
+    pub error: String,
+}
+
+impl ParameterError {
+    pub fn new(parameter: &'static str, error: &str) -> Self {
+        ParameterError { parameter, error: format!("Invalid value for parameter '{}': {}", parameter, error) }
+    }
+}
 
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
 
+/// What a store has to return of the matching recipes.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Listing {
+    /// None keeps the order of the store
+    pub sort: Option<Sort>,
+    pub skip: usize,
+    pub limit: Option<usize>,
+    /// only recipes behind this position of `sort`
+    pub after: Option<Cursor>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SortField {
+    Title,
+    Created,
+    LastModified,
+    CookingTime,
+    DefaultServings,
+}
+
+impl SortField {
+    const ALL: [SortField; 5] = [SortField::Title, SortField::Created, SortField::LastModified,
+        SortField::CookingTime, SortField::DefaultServings];
+
+    /// name in the query, the same as in the JSON of a recipe
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortField::Title => "title",
+            SortField::Created => "created",
+            SortField::LastModified => "lastModified",
+            SortField::CookingTime => "cookingTimeInMinutes",
+            SortField::DefaultServings => "defaultServings",
+        }
+    }
+
+    /// dates in milliseconds, the precision every store keeps
+    pub fn value(&self, recipe: &Recipe) -> SortValue {
+        match self {
+            SortField::Title => SortValue::Text(recipe.title.clone()),
+            SortField::Created => SortValue::Integer(recipe.created.timestamp_millis()),
+            SortField::LastModified => SortValue::Integer(recipe.last_modified.timestamp_millis()),
+            SortField::CookingTime => SortValue::Integer(recipe.cooking_time_in_minutes.into()),
+            SortField::DefaultServings => SortValue::Integer(recipe.default_servings.into()),
+        }
+    }
+}
+
+impl TryFrom<&str> for SortField {
+    type Error = ParameterError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        SortField::ALL.iter()
+            .find(|field| field.name() == value)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = SortField::ALL.iter().map(SortField::name).collect();
+                ParameterError::new("sort", &format!("unknown field '{}', expected one of {}", value, names.join(", ")))
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, PartialOrd, Ord)]
+#[serde(untagged)]
+pub enum SortValue {
+    Integer(i64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+/// Order of a listing. Recipes with equal keys are ordered by ascending id,
+/// so the order is total and a cursor never skips or repeats a recipe.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Sort {
+    pub keys: Vec<SortKey>,
+}
+
+impl Sort {
+    pub fn created(descending: bool) -> Self {
+        Sort { keys: vec![SortKey { field: SortField::Created, descending }] }
+    }
+
+    fn parse(value: &str) -> Result<Self, ParameterError> {
+        let mut keys: Vec<SortKey> = vec![];
+        for key in value.split(',').map(str::trim) {
+            let (name, descending) = match key.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (key, false)
+            };
+            let field = SortField::try_from(name)?;
+            if keys.iter().any(|key| key.field == field) {
+                return Err(ParameterError::new("sort", &format!("field '{}' is given twice", name)));
+            }
+            keys.push(SortKey { field, descending });
+        }
+        return Ok(Sort { keys });
+    }
+
+    pub fn compare(&self, a: &Recipe, b: &Recipe) -> Ordering {
+        self.keys.iter()
+            .map(|key| directed(key, key.field.value(a).cmp(&key.field.value(b))))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| a._id.bytes().cmp(&b._id.bytes()))
+    }
+
+    pub fn is_after(&self, recipe: &Recipe, cursor: &Cursor) -> bool {
+        self.keys.iter().zip(&cursor.values)
+            .map(|(key, value)| directed(key, key.field.value(recipe).cmp(value)))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| recipe._id.bytes().cmp(&cursor.id.bytes())) == Ordering::Greater
+    }
+
+    pub fn cursor(&self, recipe: &Recipe) -> Cursor {
+        Cursor {
+            values: self.keys.iter().map(|key| key.field.value(recipe)).collect(),
+            id: recipe._id.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.keys.iter()
+            .map(|key| format!("{}{}", if key.descending { "-" } else { "" }, key.field.name()))
+            .collect();
+        write!(f, "{}", keys.join(","))
+    }
+}
+
+fn directed(key: &SortKey, ordering: Ordering) -> Ordering {
+    if key.descending { ordering.reverse() } else { ordering }
+}
+
+/// Position in a sorted listing: the sort values and id of the last recipe of a page.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Cursor {
+    pub values: Vec<SortValue>,
+    pub id: ObjectId,
+}
+
+/// What the opaque cursor string contains, the sort is kept to reject it for another order.
+#[derive(Serialize, Deserialize)]
+struct EncodedCursor {
+    sort: String,
+    values: Vec<SortValue>,
+    id: String,
+}
+
+impl Cursor {
+    pub fn encode(&self, sort: &Sort) -> String {
+        let encoded = EncodedCursor { sort: sort.to_string(), values: self.values.clone(), id: self.id.to_hex() };
+        base64::encode_config(serde_json::to_vec(&encoded).unwrap(), base64::URL_SAFE_NO_PAD)
+    }
+
+    fn decode(value: &str, sort: &Sort) -> Result<Self, ParameterError> {
+        let invalid = || ParameterError::new("cursor", "expected the nextCursor of a previous page");
+        let bytes = base64::decode_config(value, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+        let encoded: EncodedCursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        if encoded.sort != sort.to_string() {
+            return Err(ParameterError::new("cursor", &format!("was created for sort '{}'", encoded.sort)));
+        }
+        let types_match = encoded.values.len() == sort.keys.len()
+            && sort.keys.iter().zip(&encoded.values).all(|(key, value)| match (key.field, value) {
+                (SortField::Title, SortValue::Text(_)) => true,
+                (SortField::Title, _) | (_, SortValue::Text(_)) => false,
+                _ => true
+            });
+        if !types_match {
+            return Err(invalid());
+        }
+        let id = ObjectId::with_string(&encoded.id).map_err(|_| invalid())?;
+        return Ok(Cursor { values: encoded.values, id });
+    }
+}
+
+
+#[cfg(test)]
+mod pagination_tests {
+    use actix_web::web::Query;
+    use bson::oid::ObjectId;
+
+    use crate::dao::dao_tests::create_one_recipe_without_image;
+    use crate::pagination::{Cursor, Listing, PageInfo, Pagination, ParameterError, Sort, SortField, SortKey, SortValue};
+
+    fn listing(query: &str) -> Result<Listing, ParameterError> {
+        Query::<Pagination>::from_query(query).unwrap().listing()
+    }
+
+    fn invalid_parameter(query: &str) -> &'static str {
+        listing(query).err().unwrap().parameter
+    }
+
+    fn page_info(query: &str, total_items: usize) -> PageInfo {
+        PageInfo::new(&Query::<Pagination>::from_query(query).unwrap(), total_items)
+    }
+
+    #[test]
+    fn parses_sort() {
+        let sort = Sort::parse("title,-lastModified, cookingTimeInMinutes").unwrap();
+        assert_eq!(sort.keys, vec![
+            SortKey { field: SortField::Title, descending: false },
+            SortKey { field: SortField::LastModified, descending: true },
+            SortKey { field: SortField::CookingTime, descending: false },
+        ]);
+        assert_eq!(sort.to_string(), "title,-lastModified,cookingTimeInMinutes");
+
+        assert!(Sort::parse("title,").err().unwrap().error.contains("unknown field ''"));
+        assert!(Sort::parse("image").err().unwrap().error.contains("expected one of title, created"));
+        assert!(Sort::parse("title,-title").err().unwrap().error.contains("twice"));
+    }
+
+    #[test]
+    fn resolves_listing() {
+        assert_eq!(listing("").unwrap(), Listing::default());
+        assert_eq!(listing("sort=-title").unwrap().sort, Some(Sort::parse("-title").unwrap()));
+        assert_eq!(listing("page=3&items=5&sorting=-1").unwrap(), Listing {
+            sort: Some(Sort::created(true)),
+            skip: 10,
+            limit: Some(5),
+            after: None,
+        });
+        assert_eq!(listing("page=1&items=5&sort=title").unwrap().sort, Some(Sort::parse("title").unwrap()));
+        assert_eq!(listing("cursor=&items=5").unwrap(), Listing {
+            sort: Some(Sort::created(false)),
+            skip: 0,
+            limit: Some(5),
+            after: None,
+        });
+    }
+
+    #[test]
+    fn names_the_invalid_parameter() {
+        assert_eq!(invalid_parameter("page=1"), "items");
+        assert_eq!(invalid_parameter("page=1&items=5"), "sorting");
+        assert_eq!(invalid_parameter("page=1&items=5&sorting=2"), "sorting");
+        assert_eq!(invalid_parameter("page=0&items=5&sorting=1"), "page");
+        assert_eq!(invalid_parameter("page=1&items=5&sorting=1&sort=title"), "sort");
+        assert_eq!(invalid_parameter("sort=image"), "sort");
+        assert_eq!(invalid_parameter("cursor="), "items");
+        assert_eq!(invalid_parameter("cursor=&items=5&page=1"), "cursor");
+        assert_eq!(invalid_parameter("cursor=nonsense&items=5"), "cursor");
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let sort = Sort::parse("title,-created").unwrap();
+        let mut recipe = create_one_recipe_without_image();
+        recipe.title = "Käse".to_string();
+        let cursor = sort.cursor(&recipe);
+        assert_eq!(cursor.values, vec![SortValue::Text("Käse".to_string()), SortValue::Integer(recipe.created.timestamp_millis())]);
+
+        let encoded = cursor.encode(&sort);
+        assert!(encoded.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(Cursor::decode(&encoded, &sort).unwrap(), cursor);
+
+        let other = Sort::parse("title").unwrap();
+        assert!(Cursor::decode(&encoded, &other).err().unwrap().error.contains("title,-created"));
+    }
+
+    #[test]
+    fn orders_with_id_as_tie_breaker() {
+        let sort = Sort::parse("-cookingTimeInMinutes").unwrap();
+        let mut first = create_one_recipe_without_image();
+        first._id = ObjectId::with_bytes([1; 12]);
+        let mut second = first.clone();
+        second._id = ObjectId::with_bytes([2; 12]);
+        let mut longer = first.clone();
+        longer._id = ObjectId::with_bytes([0; 12]);
+        longer.cooking_time_in_minutes += 1;
+
+        let mut recipes = vec![second.clone(), first.clone(), longer.clone()];
+        recipes.sort_by(|a, b| sort.compare(a, b));
+        assert_eq!(recipes, vec![longer.clone(), first.clone(), second.clone()]);
+
+        let cursor = sort.cursor(&first);
+        assert!(!sort.is_after(&longer, &cursor));
+        assert!(!sort.is_after(&first, &cursor));
+        assert!(sort.is_after(&second, &cursor));
+    }
+
+    #[test]
+    fn counts_pages() {
+        let info = page_info("page=2&items=5&sorting=1", 11);
+        assert_eq!((info.page, info.items_per_page, info.total_items, info.total_pages), (2, 5, 11, 3));
+        assert_eq!(page_info("page=1&items=5&sorting=1", 10).total_pages, 2);
+        assert_eq!(page_info("page=1&items=5&sorting=1", 0).total_pages, 1);
+        let info = page_info("", 7);
+        assert_eq!((info.page, info.items_per_page, info.total_items, info.total_pages), (1, 7, 7, 1));
+        assert_eq!(info.envelope_start(), "{\"page\":1,\"itemsPerPage\":7,\"totalItems\":7,\"totalPages\":1,\"items\":[");
+    }
+
+    #[test]
+    fn links_pages() {
+        let query = "items=5&page=2&sorting=1&tags=vegan%2Cschnell";
+        assert_eq!(page_info(query, 11).links("/api/v1/recipes", query).unwrap(),
+                   "</api/v1/recipes?items=5&sorting=1&tags=vegan%2Cschnell&page=1>; rel=\"first\", \
+                    </api/v1/recipes?items=5&sorting=1&tags=vegan%2Cschnell&page=1>; rel=\"prev\", \
+                    </api/v1/recipes?items=5&sorting=1&tags=vegan%2Cschnell&page=3>; rel=\"next\", \
+                    </api/v1/recipes?items=5&sorting=1&tags=vegan%2Cschnell&page=3>; rel=\"last\"");
+
+        let query = "page=1&items=5&sorting=1";
+        assert_eq!(page_info(query, 3).links("/recipes", query).unwrap(),
+                   "</recipes?items=5&sorting=1&page=1>; rel=\"first\", </recipes?items=5&sorting=1&page=1>; rel=\"last\"");
+
+        let query = "page=9&items=5&sorting=1";
+        assert!(page_info(query, 11).links("/recipes", query).unwrap().contains("page=3>; rel=\"prev\""));
+        assert_eq!(page_info("", 11).links("/recipes", ""), None);
+    }
+}
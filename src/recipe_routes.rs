@@ -1,157 +1,718 @@
-use actix_web::{Either, HttpRequest, HttpResponse, Responder, web};
-use actix_web::web::{Json, Query};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use actix_web::{Error, HttpRequest, HttpResponse, web};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::{header, StatusCode};
+use actix_web::web::{Bytes, Query};
 use bson::oid::ObjectId;
-
-use crate::dao::{Dao, DaoError};
+use futures_util::future;
+use futures_util::stream::{self, LocalBoxStream, Stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+
+use crate::caching::{self, Validators};
+use crate::error::ApiError;
+use crate::filter::RecipeFilter;
+use crate::image::{self, ImageFormat, ImageQuery, ImageUpload};
+use crate::image_store::{self, ImageStore, referenced_hash, variants};
+use crate::ingredient_parser;
+use crate::json_body::JsonBody;
+use crate::LogExtensionErr;
+use crate::model::conversion::UnitsQuery;
+use crate::model::gallery::{self, GalleryChange, GalleryImage, GalleryQuery};
 use crate::model::recipe::Recipe;
-use crate::pagination::Pagination;
+use crate::model::scaling::ServingsQuery;
+use crate::model::unit::{UnitCatalogue, UnitSystem};
+use crate::pagination::{Listing, PAGE_MEDIA_TYPE, PageInfo, Pagination, ParameterError, Sort};
+use crate::search::{self, SearchParams, SearchQuery, SearchResults};
+use crate::store::{self, DaoError, RecipeStore, RecipeStream, RecipeSummary, Resource, UnreadableRecipe};
+use crate::text_import::{self, TextImport};
+use crate::validation::{self, Validate, Validated, Violation};
 
 pub struct RecipeRoutes {}
 
+/// the recipes a listing writes, in lenient mode without the unreadable ones
+type Recipes = LocalBoxStream<'static, Result<Recipe, DaoError>>;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredImage {
+    content_type: &'static str,
+    size: usize,
+}
+
+#[derive(Serialize)]
+struct ValidationReport {
+    valid: bool,
+    violations: Vec<Violation>,
+}
+
 impl RecipeRoutes {
-    pub async fn update_one_recipe_without_image(req: HttpRequest, database: web::Data<Dao>, recipe: Json<Recipe>) -> impl Responder {
-        let id = match extract_id_from_req(req) {
-            Some(id) => id,
-            None => return HttpResponse::BadRequest()
-        };
+    /// Only updates the version the client read, given by `If-Match` or else the `version` of the body.
+    /// A stale update is answered with 412 and the current recipe.
+    pub async fn update_one_recipe_without_image(req: HttpRequest, database: web::Data<dyn RecipeStore>, recipe: Validated<Recipe>) -> Result<HttpResponse, ApiError> {
+        let id = extract_id_from_req(&req)?;
+        let recipe = recipe.into_inner();
+        let expected_version = if_match_version(&req)?.unwrap_or(recipe.version);
+        let updated = database.update_recipe_ignore_image(id, recipe, expected_version).await?;
+        Ok(HttpResponse::Ok().header(header::ETAG, updated.etag()).json(updated))
+    }
+
+    pub async fn add_one_recipe(database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>, recipe: Validated<Recipe>) -> Result<HttpResponse, ApiError> {
+        let recipe = store_cover_image(&**images, recipe.into_inner()).await?;
+        let id = database.insert_recipe(stamp_created(recipe)).await?;
+        Ok(HttpResponse::Ok().json(id))
+    }
+
+    pub async fn delete_one_recipe(req: HttpRequest, database: web::Data<dyn RecipeStore>) -> Result<HttpResponse, ApiError> {
+        let id = extract_id_from_req(&req)?;
+        database.delete_one_recipe(id).await?;
+        Ok(HttpResponse::Ok().finish())
+    }
 
-        match database.update_recipe_ignore_image(id, recipe.into_inner()).await {
-            Ok(_) => HttpResponse::Ok(),
-            Err(DaoError::DocumentNotFound) => HttpResponse::NotFound(),
-            Err(DaoError::DatabaseError(_)) => HttpResponse::InternalServerError(),
-            Err(DaoError::RecipeFormatError(_)) => HttpResponse::InternalServerError(),
+    pub async fn add_many_recipes(database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>, recipes: Validated<Vec<Recipe>>) -> Result<HttpResponse, ApiError> {
+        let mut stored = vec![];
+        for recipe in recipes.into_inner() {
+            stored.push(stamp_created(store_cover_image(&**images, recipe).await?));
         }
+        let ids = database.add_many_recipes(stored).await?;
+        Ok(HttpResponse::Ok().json(ids))
+    }
+
+    /// Dry run of the checks `add_one_recipe` makes, nothing is stored.
+    pub async fn validate_recipe(req: HttpRequest, recipe: JsonBody<Recipe>) -> Result<HttpResponse, ApiError> {
+        let violations = recipe.validate(&validation::limits(&req), &validation::units(&req).await?);
+        Ok(HttpResponse::Ok().json(ValidationReport { valid: violations.is_empty(), violations }))
     }
 
-    pub async fn add_one_recipe(database: web::Data<Dao>, recipe: Json<Recipe>) -> Either<impl Responder, impl Responder> {
-        match database.insert_recipe(recipe.into_inner()).await {
-            Ok(bson) => Either::A(HttpResponse::Ok().json(bson)),
-            Err(DaoError::DocumentNotFound) => Either::B(HttpResponse::NotFound()),
-            Err(DaoError::DatabaseError(_)) => Either::B(HttpResponse::InternalServerError()),
-            Err(DaoError::RecipeFormatError(_)) => Either::B(HttpResponse::InternalServerError()),
+    /// A draft recipe worked out of pasted text with the lines that could not be placed, see
+    /// `text_import::import_text`. Nothing is stored, the client adds the reviewed draft.
+    /// Like `parse_ingredients` at most `validation.max_ingredients` ingredients and
+    /// `validation.max_instructions` instructions are imported at once. Text with more than twice
+    /// as many lines is turned away before the import, steps and headings may take several lines.
+    pub async fn import_text(req: HttpRequest, database: web::Data<dyn RecipeStore>, body: JsonBody<TextImport>) -> Result<HttpResponse, ApiError> {
+        let limits = validation::limits(&req);
+        let too_many_lines = || ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "too-many-lines", "Too many lines",
+                                              format!("At most {} ingredients and {} instructions can be imported at once",
+                                                      limits.max_ingredients, limits.max_instructions))
+            .with_field("text");
+        if ingredient_parser::has_more_lines(&body.text, 2 * (limits.max_ingredients + limits.max_instructions)) {
+            return Err(too_many_lines());
         }
+        let imported = text_import::import_text(&body.text, &database.unit_catalogue().await?);
+        if imported.recipe.ingredients.len() > limits.max_ingredients || imported.recipe.instructions.len() > limits.max_instructions {
+            return Err(too_many_lines());
+        }
+        Ok(HttpResponse::Ok().json(imported))
     }
 
-    pub async fn delete_one_recipe(req: HttpRequest, database: web::Data<Dao>) -> impl Responder {
-        let id = match extract_id_from_req(req) {
-            Some(id) => id,
-            None => return HttpResponse::BadRequest()
+    /// Answers 304 while the client has the version, by `If-None-Match` or `If-Modified-Since`.
+    /// With `servings` the ingredients are scaled, see `Recipe::scaled`, with `units` converted afterwards.
+    pub async fn get_one_recipe_without_image(req: HttpRequest, query: Query<ServingsQuery>, units: Query<UnitsQuery>, database: web::Data<dyn RecipeStore>) -> Result<HttpResponse, ApiError> {
+        let id = extract_id_from_req(&req)?;
+        let max_servings = validation::limits(&req).max_servings;
+        if query.servings.is_some_and(|servings| servings == 0 || servings > max_servings) {
+            return Err(ParameterError::new("servings", &format!("has to be between 1 and {}", max_servings)).into());
+        }
+        let recipe = database.get_one_recipe_without_image(id).await?;
+        let system = match units.units {
+            Some(system) => Some((system, database.unit_catalogue().await?)),
+            None => None
         };
-
-        match database.delete_one_recipe(id).await {
-            Ok(_) => HttpResponse::Ok(),
-            Err(DaoError::DocumentNotFound) => HttpResponse::NotFound(),
-            Err(DaoError::DatabaseError(_)) => HttpResponse::InternalServerError(),
-            Err(DaoError::RecipeFormatError(_)) => HttpResponse::InternalServerError(),
+        let etag = recipe_etag(&req, &recipe, query.servings, system.as_ref().map(|(_, units)| units));
+        let mut validators = Validators::new(etag, caching::config(&req).recipe);
+        // a change of the catalogue changes converted amounts but not lastModified
+        if system.is_none() {
+            validators = validators.last_modified(recipe.last_modified);
         }
+        if let Some(not_modified) = validators.not_modified(&req) {
+            return Ok(not_modified);
+        }
+        let in_units = |recipe: Recipe| match &system {
+            Some((system, units)) => recipe.in_units(*system, units),
+            None => recipe
+        };
+        match query.servings {
+            Some(servings) => {
+                let mut scaled = recipe.scaled(servings).ok_or_else(|| ApiError::new(
+                    StatusCode::UNPROCESSABLE_ENTITY, "not-scalable", "Recipe cannot be scaled",
+                    "the recipe has no default servings to scale from").with_field("servings"))?;
+                scaled.recipe = in_units(scaled.recipe);
+                Ok(validators.ok().json(scaled))
+            }
+            None => Ok(validators.ok().json(in_units(recipe)))
+        }
+    }
 
+    /// The cover image, see `image_response`.
+    pub async fn get_one_recipe_image(req: HttpRequest, query: Query<ImageQuery>, database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>) -> Result<HttpResponse, ApiError> {
+        let id = extract_id_from_req(&req)?;
+        let image = database.get_one_recipe_image(id).await?;
+        image_response(&req, &**images, image, &query, caching::config(&req).image).await
     }
 
-    pub async fn add_many_recipes(database: web::Data<Dao>, recipes: Json<Vec<Recipe>>) -> Either<impl Responder, impl Responder> {
-        match database.add_many_recipes(recipes.into_inner()).await {
-            Ok(bson) => Either::A(HttpResponse::Ok().json(bson)),
-            Err(DaoError::DocumentNotFound) =>  Either::B(HttpResponse::NotFound()),
-            Err(DaoError::DatabaseError(_)) => Either::B(HttpResponse::InternalServerError()),
-            Err(DaoError::RecipeFormatError(_)) =>  Either::B(HttpResponse::InternalServerError()),
-        }
+    /// Replaces the image of the cover, or adds the cover when the gallery has none.
+    /// Answers with the media type and size the image was stored with, without its metadata.
+    /// The image the recipe had before stays in the image store, another recipe may show it too.
+    pub async fn update_one_recipe_image(req: HttpRequest, database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>, image: ImageUpload) -> Result<HttpResponse, ApiError> {
+        let content_type = image.format.media_type();
+        let (reference, size) = store_image(&**images, image.bytes).await?;
+        let new_cover = cover_image(reference.clone());
+        change_gallery(&req, &**database, &**images, |gallery| {
+            match gallery.iter_mut().find(|image| image.cover) {
+                Some(cover) => cover.image = reference.clone(),
+                None => gallery.insert(0, new_cover.clone())
+            }
+            Ok(())
+        }).await?;
+        Ok(HttpResponse::Ok().json(StoredImage { content_type, size }))
     }
 
-    pub async fn get_one_recipe_without_image(req: HttpRequest, database: web::Data<Dao>) -> Either<impl Responder, impl Responder> {
-        let id = match extract_id_from_req(req) {
-            Some(id) => id,
-            None => return Either::B(HttpResponse::BadRequest())
+    /// Removes the cover from the gallery, the next image becomes the cover.
+    pub async fn delete_one_recipe_image(req: HttpRequest, database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>) -> Result<HttpResponse, ApiError> {
+        change_gallery(&req, &**database, &**images, |gallery| {
+            gallery.retain(|image| !image.cover);
+            Ok(())
+        }).await?;
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    /// Adds the uploaded image, accepted like `update_one_recipe_image`, at the end of the gallery.
+    /// Answers 201 with the new entry, the first image of a gallery becomes its cover.
+    pub async fn add_gallery_image(req: HttpRequest, query: Query<GalleryQuery>, database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>, image: ImageUpload) -> Result<HttpResponse, ApiError> {
+        let query = query.into_inner();
+        let (reference, _) = store_image(&**images, image.bytes).await?;
+        let added = GalleryImage {
+            caption: query.caption.unwrap_or_default(),
+            cover: query.cover.unwrap_or(false),
+            step: query.step,
+            ..cover_image(reference)
         };
+        let (recipe, _) = change_gallery(&req, &**database, &**images, |gallery| {
+            if added.cover {
+                gallery.iter_mut().for_each(|image| image.cover = false);
+            }
+            gallery.push(added.clone());
+            Ok(())
+        }).await?;
+        let added = recipe.gallery.iter().find(|image| image.id == added.id).cloned()
+            .ok_or_else(|| ApiError::internal("The image was not added to the gallery"))?;
+        Ok(HttpResponse::Created()
+            .header(header::LOCATION, format!("{}/{}", req.path().trim_end_matches('/'), added.id))
+            .header(header::ETAG, recipe.etag())
+            .json(added))
+    }
 
-        match database.get_one_recipe_without_image(id).await {
-            Ok(recipe) => Either::A(HttpResponse::Ok().json(recipe)),
-            Err(DaoError::DocumentNotFound) =>  Either::B(HttpResponse::NotFound()),
-            Err(DaoError::DatabaseError(_)) => Either::B(HttpResponse::InternalServerError()),
-            Err(DaoError::RecipeFormatError(_)) =>  Either::B(HttpResponse::InternalServerError()),
-        }
+    /// An image of the gallery, see `image_response`.
+    pub async fn get_gallery_image(req: HttpRequest, query: Query<ImageQuery>, database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>) -> Result<HttpResponse, ApiError> {
+        let id = extract_id_from_req(&req)?;
+        let image_id = req.match_info().get("imageId").unwrap_or_default();
+        let recipe = database.get_one_recipe_without_image(id).await?;
+        let image = recipe.gallery.into_iter().find(|image| image.id == image_id)
+            .ok_or_else(|| gallery_image_not_found(image_id))?;
+        image_response(&req, &**images, image.image, &query, caching::config(&req).gallery_image).await
     }
 
-    pub async fn get_one_recipe_image(req: HttpRequest, database: web::Data<Dao>) -> Either<impl Responder, impl Responder> {
-        let id = match extract_id_from_req(req) {
-            Some(id) => id,
-            None => return Either::A(HttpResponse::BadRequest())
-        };
+    /// Reorders the gallery and changes captions, cover and steps. The body lists every image
+    /// of the gallery once, in the new order, and is answered with the new gallery.
+    pub async fn update_gallery(req: HttpRequest, database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>, changes: JsonBody<Vec<GalleryChange>>) -> Result<HttpResponse, ApiError> {
+        let changes = changes.into_inner();
+        let (recipe, _) = change_gallery(&req, &**database, &**images, |gallery| {
+            let mut violations = vec![];
+            let mut reordered = vec![];
+            for (index, change) in changes.iter().enumerate() {
+                match gallery.iter().position(|image| image.id == change.id) {
+                    Some(position) => reordered.push(GalleryImage {
+                        caption: change.caption.clone(),
+                        cover: change.cover,
+                        step: change.step,
+                        ..gallery.remove(position)
+                    }),
+                    None => violations.push(Violation {
+                        field: Some(format!("[{}].id", index)),
+                        message: format!("'{}' is no image of the gallery or listed twice", change.id),
+                    })
+                }
+            }
+            violations.extend(gallery.iter().map(|image| Violation { field: None, message: format!("the image '{}' is missing", image.id) }));
+            if !violations.is_empty() {
+                return Err(ApiError::invalid_recipe(violations));
+            }
+            *gallery = reordered;
+            Ok(())
+        }).await?;
+        Ok(HttpResponse::Ok().header(header::ETAG, recipe.etag()).json(recipe.gallery))
+    }
 
-        match database.get_one_recipe_image(id).await {
-            Ok(image) => Either::B(HttpResponse::Ok().body(image)),
-            Err(DaoError::DocumentNotFound) => Either::A(HttpResponse::NotFound()),
-            Err(DaoError::DatabaseError(_)) => Either::A(HttpResponse::InternalServerError()),
-            Err(DaoError::RecipeFormatError(_)) => Either::A(HttpResponse::InternalServerError()),
-        }
+    /// The next image becomes the cover when the cover is deleted. The image stays in the image store.
+    pub async fn delete_gallery_image(req: HttpRequest, database: web::Data<dyn RecipeStore>, images: web::Data<dyn ImageStore>) -> Result<HttpResponse, ApiError> {
+        let image_id = req.match_info().get("imageId").unwrap_or_default().to_string();
+        let (recipe, _) = change_gallery(&req, &**database, &**images, |gallery| {
+            let position = gallery.iter().position(|image| image.id == image_id)
+                .ok_or_else(|| gallery_image_not_found(&image_id))?;
+            gallery.remove(position);
+            Ok(())
+        }).await?;
+        Ok(HttpResponse::Ok().header(header::ETAG, recipe.etag()).finish())
     }
 
-    pub async fn update_one_recipe_image(req: HttpRequest, database: web::Data<Dao>, image: String) -> impl Responder {
-        let id = match extract_id_from_req(req) {
-            Some(id) => id,
-            None => return HttpResponse::BadRequest()
+    pub async fn get_many_recipes(req: HttpRequest, params: Query<Pagination>, units: Query<UnitsQuery>, database: web::Data<dyn RecipeStore>) -> Result<HttpResponse, ApiError> {
+        let params = params.into_inner();
+        let (mut listing, filter) = params.listing()
+            .and_then(|listing| Ok((listing, RecipeFilter::try_from(&params)?)))
+            .log_if_err(|err| info!("Rejected recipe listing. {}", err))?;
+
+        let skipped = if params.lenient.unwrap_or(false) { Some(Skipped::default()) } else { None };
+        let envelope = params.envelope.unwrap_or(false) || accepts(&req, PAGE_MEDIA_TYPE);
+
+        let summary = database.summarize_recipes(&filter).await?;
+        let system = match units.units {
+            Some(system) => Some((system, database.unit_catalogue().await?)),
+            None => None
         };
+        let etag = listing_etag(&req, &summary, envelope, system.as_ref().map(|(_, units)| units));
+        let validators = Validators::new(etag, caching::config(&req).recipes)
+            .vary(header::ACCEPT);
+        if let Some(not_modified) = validators.not_modified(&req) {
+            return Ok(not_modified);
+        }
 
-        match database.update_one_recipe_image(id, Some(image)).await {
-            Ok(_) => HttpResponse::Ok(),
-            Err(DaoError::DocumentNotFound) => HttpResponse::NotFound(),
-            Err(DaoError::DatabaseError(_)) => HttpResponse::InternalServerError(),
-            Err(DaoError::RecipeFormatError(_)) => HttpResponse::InternalServerError(),
+        // one recipe more than the page holds tells whether there is a next page
+        let page_size = listing.limit.unwrap_or_default();
+        if params.is_cursor_mode() {
+            // skipped documents must not take the place of that recipe, the stream is only read as far as needed
+            listing.limit = if skipped.is_some() { None } else { Some(page_size + 1) };
+        }
+        let mut recipes = leave_out_unreadable(database.stream_many_recipes(&listing, &filter).await?, &skipped);
+        if skipped.is_none() {
+            recipes = read_ahead(recipes, &listing).await?;
+        }
+        let recipes = in_units(recipes, system);
+        if params.is_cursor_mode() {
+            return Ok(validators.ok()
+                .content_type("application/json")
+                .streaming(json_cursor_page(recipes, page_size, listing.sort.unwrap(), skipped)));
+        }
+
+        let page = PageInfo::new(&params, summary.count);
+        let mut response = validators.ok();
+        response.header("X-Total-Count", summary.count.to_string());
+        if let Some(links) = page.links(req.path(), req.query_string()) {
+            response.header(header::LINK, links);
+        }
+        if envelope {
+            Ok(response.content_type(PAGE_MEDIA_TYPE)
+                .streaming(json_array_in(page.envelope_start(), recipes, move || format!("]{}}}", warnings(&skipped)))))
+        } else if skipped.is_some() {
+            Ok(response.content_type("application/json")
+                .streaming(json_array_in("{\"items\":[".to_string(), recipes, move || format!("]{}}}", warnings(&skipped)))))
+        } else {
+            Ok(response.content_type("application/json")
+                .streaming(json_array(recipes)))
         }
     }
 
-    pub async fn delete_one_recipe_image(req: HttpRequest, database: web::Data<Dao>) -> impl Responder {
-        let id = match extract_id_from_req(req) {
-            Some(id) => id,
-            None => return HttpResponse::BadRequest()
-        };
+    /// Reads every stored recipe and reports the ones a listing could not return.
+    pub async fn scan_recipes(database: web::Data<dyn RecipeStore>) -> Result<HttpResponse, ApiError> {
+        let report = database.scan_recipes().await?;
+        Ok(HttpResponse::Ok().json(report))
+    }
 
-        match database.update_one_recipe_image(id, None).await {
-            Ok(_) => HttpResponse::Ok(),
-            Err(DaoError::DocumentNotFound) => HttpResponse::NotFound(),
-            Err(DaoError::DatabaseError(_)) => HttpResponse::InternalServerError(),
-            Err(DaoError::RecipeFormatError(_)) => HttpResponse::InternalServerError(),
+    pub async fn search_recipes(params: Query<SearchParams>, database: web::Data<dyn RecipeStore>) -> Result<HttpResponse, ApiError> {
+        let params = params.into_inner();
+        let query = SearchQuery::parse(&params.q)
+            .ok_or_else(|| ParameterError::new("q", "the query has to contain at least one word"))?;
+        let offset = params.offset.unwrap_or(0);
+        let limit = params.limit.unwrap_or(search::DEFAULT_LIMIT);
+        if limit == 0 || limit > search::MAX_LIMIT {
+            return Err(ParameterError::new("limit", &format!("has to be between 1 and {}", search::MAX_LIMIT)).into());
         }
+
+        let recipes = database.stream_many_recipes(&Listing::default(), &RecipeFilter::default()).await?;
+        let hits = search::search(recipes, &query).await?;
+        Ok(HttpResponse::Ok().json(SearchResults {
+            total: hits.len(),
+            offset,
+            limit,
+            results: hits.into_iter().skip(offset).take(limit).collect(),
+        }))
     }
+}
 
-    pub async fn get_many_recipes(params: Query<Pagination>, database: web::Data<Dao>) -> Either<impl Responder, impl Responder> {
-        let result = if params.0.is_fully_set() {
-            database.get_many_recipes(Some(params.0)).await
-        } else if params.is_fully_empty() {
-            database.get_many_recipes(None).await
-        } else {
-            return Either::B(HttpResponse::BadRequest());
+
+/// What a strict listing reads before the status is sent, so an unreadable recipe is answered with 500
+/// instead of a body that breaks off. A page is read once and written from memory, a listing without
+/// limit is not held, only its first recipe is read ahead and a later unreadable one aborts the body.
+async fn read_ahead(recipes: Recipes, listing: &Listing) -> Result<Recipes, ApiError> {
+    if listing.limit.is_some() {
+        let page: Vec<Recipe> = recipes.try_collect().await?;
+        return Ok(stream::iter(page.into_iter().map(Ok)).boxed_local());
+    }
+    let (first, rest) = recipes.into_future().await;
+    let first = first.transpose()?;
+    Ok(stream::iter(first.map(Ok)).chain(rest).boxed_local())
+}
+
+/// Writes the recipes as one JSON array while they arrive from the store, so a listing
+/// never has to be held in memory. An error after the first byte can only abort the body,
+/// strict listings read ahead with `read_ahead`.
+fn json_array(recipes: Recipes) -> impl Stream<Item=Result<Bytes, Error>> + Unpin {
+    json_array_in("[".to_string(), recipes, || "]".to_string())
+}
+
+/// Like `json_array`, with `start` written before the first recipe and `end` after the last.
+fn json_array_in<F>(start: String, recipes: Recipes, end: F) -> impl Stream<Item=Result<Bytes, Error>> + Unpin
+    where F: FnOnce() -> String + 'static {
+    let mut first = true;
+    let items = recipes.map(move |recipe| {
+        let separator = if first { "" } else { "," };
+        first = false;
+        recipe
+            .map_err(|err| {
+                error!("Aborting recipe stream. Err={:#?}", err);
+                ErrorInternalServerError("could not read recipes")
+            })
+            .and_then(|recipe| to_json_bytes(separator, &recipe))
+    });
+
+    stream::once(async { Ok(Bytes::from(start)) })
+        .chain(items)
+        .chain(stream::once(async move { Ok(Bytes::from(end())) }))
+        .boxed_local()
+}
+
+/// The documents a lenient listing left out, shared with the writer of the body.
+#[derive(Clone, Default)]
+struct Skipped(Rc<RefCell<Vec<UnreadableRecipe>>>);
+
+/// In lenient mode unreadable recipes are taken out of the stream and kept in `skipped`.
+fn leave_out_unreadable(recipes: RecipeStream, skipped: &Option<Skipped>) -> Recipes {
+    let skipped = match skipped {
+        Some(skipped) => skipped.clone(),
+        None => return recipes.boxed_local()
+    };
+    recipes
+        .filter_map(move |recipe| future::ready(match recipe {
+            Err(DaoError::UnreadableRecipe(unreadable)) => {
+                warn!("Leaving unreadable recipe out of listing. id={}, err={}", unreadable.id, unreadable.error);
+                skipped.0.borrow_mut().push(unreadable);
+                None
+            }
+            recipe => Some(recipe)
+        }))
+        .boxed_local()
+}
+
+/// the recipes with their amounts in the units of `system`, see `Recipe::in_units`
+fn in_units(recipes: Recipes, system: Option<(UnitSystem, UnitCatalogue)>) -> Recipes {
+    match system {
+        Some((system, units)) => recipes.map(move |recipe| recipe.map(|recipe| recipe.in_units(system, &units))).boxed_local(),
+        None => recipes
+    }
+}
+
+/// `,"warnings":[...]` with the skipped documents, nothing outside of lenient mode
+fn warnings(skipped: &Option<Skipped>) -> String {
+    match skipped {
+        Some(skipped) => format!(",\"warnings\":{}", serde_json::to_string(&*skipped.0.borrow()).unwrap_or_default()),
+        None => String::new()
+    }
+}
+
+/// whether the Accept header lists the media type, parameters like q are ignored
+fn accepts(req: &HttpRequest, media_type: &str) -> bool {
+    req.headers().get_all(header::ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|accepted| accepted.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case(media_type))
+}
+
+/// Writes one page of the keyset mode as `{"items": [...], "nextCursor": ...}`. The stream holds
+/// up to one recipe more than the page, only then the cursor of the last written recipe is sent.
+fn json_cursor_page(recipes: Recipes, page_size: usize, sort: Sort, skipped: Option<Skipped>) -> impl Stream<Item=Result<Bytes, Error>> + Unpin {
+    let items = stream::unfold(Some((recipes, 0, None)), move |state| {
+        let sort = sort.clone();
+        let skipped = skipped.clone();
+        async move {
+            let (mut recipes, written, last): (Recipes, usize, Option<Recipe>) = state?;
+            let next_cursor = match recipes.next().await {
+                Some(Ok(recipe)) if written < page_size => {
+                    let bytes = to_json_bytes(if written == 0 { "" } else { "," }, &recipe);
+                    return Some((bytes, Some((recipes, written + 1, Some(recipe)))));
+                }
+                Some(Ok(_)) => last.map(|recipe| sort.cursor(&recipe).encode(&sort)),
+                None => None,
+                Some(Err(err)) => {
+                    error!("Aborting recipe stream. Err={:#?}", err);
+                    return Some((Err(ErrorInternalServerError("could not read recipes")), None));
+                }
+            };
+            let end = to_json_bytes("],\"nextCursor\":", &next_cursor)
+                .map(|bytes| Bytes::from([&bytes[..], warnings(&skipped).as_bytes(), b"}"].concat()));
+            Some((end, None))
+        }
+    });
+
+    stream::once(async { Ok(Bytes::from_static(b"{\"items\":[")) })
+        .chain(items)
+        .boxed_local()
+}
+
+fn to_json_bytes<T: Serialize>(prefix: &str, value: &T) -> Result<Bytes, Error> {
+    let mut bytes = prefix.as_bytes().to_vec();
+    serde_json::to_writer(&mut bytes, value).map_err(ErrorInternalServerError)?;
+    Ok(Bytes::from(bytes))
+}
+
+/// A recipe sent with its image as base64 keeps a reference to the image store instead,
+/// the image becomes the cover of the gallery. References are kept, they were read from another recipe,
+/// one to an image the store does not have is answered with 422.
+/// The gallery a client sent is replaced, images are added with the gallery routes.
+async fn store_cover_image(images: &dyn ImageStore, mut recipe: Recipe) -> Result<Recipe, ApiError> {
+    recipe.gallery = vec![];
+    if let Some(image) = recipe.image_base64.take() {
+        let reference = match referenced_hash(&image) {
+            Some(hash) if !images.contains(hash).await? => {
+                return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "unknown-image", "Unknown image",
+                                         format!("no image is stored as '{}'", image)).with_field("image"));
+            }
+            Some(_) => image,
+            None => store_image(images, ImageUpload::from_base64(image.as_bytes())?.bytes).await?.0
         };
+        recipe.gallery.push(cover_image(reference.clone()));
+        recipe.image_base64 = Some(reference);
+    }
+    Ok(recipe)
+}
 
-        match result {
-            Ok(recipes) => Either::A(HttpResponse::Ok().json(recipes)),
-            Err(DaoError::DatabaseError(_)) => Either::B(HttpResponse::InternalServerError()),
-            Err(DaoError::DocumentNotFound) => Either::B(HttpResponse::NotFound()),
-            Err(DaoError::RecipeFormatError(_)) => Either::B(HttpResponse::InternalServerError()),
+/// a new gallery entry marked as cover, without caption
+fn cover_image(reference: String) -> GalleryImage {
+    GalleryImage { id: ObjectId::new().to_hex(), image: reference, caption: String::new(), cover: true, step: None }
+}
+
+/// The version for the stored recipe, the tag `If-Match` takes. A scaled or converted recipe is another
+/// representation, its tag also depends on the query and on the catalogue the amounts were converted with.
+fn recipe_etag(req: &HttpRequest, recipe: &Recipe, servings: Option<u32>, units: Option<&UnitCatalogue>) -> String {
+    if servings.is_none() && units.is_none() {
+        return recipe.etag();
+    }
+    let representation = format!("{}|{}|{}", recipe.version, req.query_string(), catalogue_state(units));
+    format!("\"{}-{}\"", recipe.version, &image_store::hash(representation.as_bytes())[..16])
+}
+
+/// the units amounts were converted with, empty without conversion
+fn catalogue_state(units: Option<&UnitCatalogue>) -> String {
+    units.map_or_else(String::new, |units| serde_json::to_string(units.units()).unwrap_or_default())
+}
+
+/// A listing is streamed, its tag has to be known before the first recipe is read, see `RecipeSummary`.
+/// The listing sends no `Last-Modified`, the latest change stays the same when a recipe is deleted.
+/// With `units` the catalogue is part of the tag, see `recipe_etag`.
+fn listing_etag(req: &HttpRequest, summary: &RecipeSummary, envelope: bool, units: Option<&UnitCatalogue>) -> String {
+    let last_modified = summary.last_modified.map_or(0, |last_modified| last_modified.timestamp_millis());
+    let listing = format!("{}|{}|{}|{}|{}|{}", req.query_string(), summary.count, summary.versions, last_modified, envelope,
+                          catalogue_state(units));
+    format!("\"{}\"", image_store::hash(listing.as_bytes()))
+}
+
+/// The bytes of a stored image with their media type, or the image as base64 text with `encoding=base64`.
+/// `size` answers a variant. Images stored by older versions that are no known format are answered as text, as they were sent.
+/// The tag is the hash of the stored image, a client that has it gets 304 without the image being read.
+async fn image_response(req: &HttpRequest, images: &dyn ImageStore, image: String, query: &ImageQuery, cache_control: String) -> Result<HttpResponse, ApiError> {
+    let hash = referenced_hash(&image).map_or_else(|| image_store::hash(image.as_bytes()), String::from);
+    let validators = Validators::new(format!("\"{}\"", hash), cache_control);
+    if let Some(not_modified) = validators.not_modified(req) {
+        return Ok(not_modified);
+    }
+
+    let (bytes, inline) = match referenced_hash(&image) {
+        Some(hash) => {
+            let bytes = match query.size {
+                Some(size) => variants::get_variant(images, hash, size).await,
+                None => images.get(hash).await
+            };
+            (Some(bytes.map_err(|err| dangling_reference(err, hash))?), None)
+        }
+        // kept inline until the background migration moves it, served at full size
+        None => (base64::decode(&image).ok(), Some(image))
+    };
+    let format = bytes.as_deref().and_then(ImageFormat::sniff);
+
+    let mut response = validators.ok();
+    response.header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    match (bytes, format) {
+        (Some(bytes), Some(format)) if !query.wants_base64() => Ok(response.content_type(format.media_type()).body(bytes)),
+        // only encoded when the text is sent
+        (bytes, _) => {
+            let text = inline.unwrap_or_else(|| base64::encode(bytes.unwrap_or_default()));
+            Ok(response.content_type("text/plain; charset=utf-8").body(text))
         }
     }
 }
 
+/// A reference to an image the image store does not have is answered with 404 image-not-found,
+/// not with the 404 of a missing recipe.
+fn dangling_reference(err: DaoError, hash: &str) -> ApiError {
+    match err {
+        DaoError::DocumentNotFound(_) => {
+            error!("Referenced image is not stored. hash={}", hash);
+            ApiError::new(StatusCode::NOT_FOUND, "image-not-found", "Image not found",
+                          format!("the image 'sha256:{}' is missing in the image store", hash))
+        }
+        err => err.into()
+    }
+}
 
-fn extract_id_from_req(req: HttpRequest) -> Option<ObjectId> {
-    match req.match_info().get("id") {
-        Some(id) => match ObjectId::with_string(&id) {
-            Ok(oid) => return Some(oid),
-            _ => error!("Error provided id is no Object id")
+/// How often a gallery change is retried when the recipe changed while it was made
+const GALLERY_ATTEMPTS: usize = 3;
+
+/// Applies `change` to the gallery of the recipe in the path and stores it, the first image becomes
+/// the cover when there is none. With `If-Match` only that version is changed, otherwise the change
+/// is made again on the current recipe when another request changed it in between.
+async fn change_gallery<T, F>(req: &HttpRequest, database: &dyn RecipeStore, images: &dyn ImageStore, mut change: F) -> Result<(Recipe, T), ApiError>
+    where F: FnMut(&mut Vec<GalleryImage>) -> Result<T, ApiError> {
+    let id = extract_id_from_req(req)?;
+    let expected_version = if_match_version(req)?;
+    let mut attempts = 0;
+    loop {
+        let recipe = database.get_one_recipe_without_image(id.clone()).await?;
+        let mut gallery = with_older_image(database, images, &recipe).await?;
+        let result = change(&mut gallery)?;
+        if gallery::cover(&gallery).is_none() {
+            if let Some(first) = gallery.first_mut() {
+                first.cover = true;
+            }
+        }
+        let violations = validation::gallery_violations(&gallery, recipe.instructions.len(), &validation::limits(req));
+        if !violations.is_empty() {
+            return Err(ApiError::invalid_recipe(violations));
         }
-        None => error!("Error getting id param from HTTP request={:#?}", req)
+        attempts += 1;
+        match database.update_gallery(id.clone(), gallery, expected_version.unwrap_or(recipe.version)).await {
+            Ok(updated) => return Ok((updated, result)),
+            Err(DaoError::VersionConflict(_)) if expected_version.is_none() && attempts < GALLERY_ATTEMPTS => continue,
+            Err(err) => return Err(err.into())
+        }
+    }
+}
+
+/// The gallery, with the image recipes stored before galleries existed as cover.
+/// An image still kept inline is moved into the image store when it can be decoded.
+async fn with_older_image(database: &dyn RecipeStore, images: &dyn ImageStore, recipe: &Recipe) -> Result<Vec<GalleryImage>, ApiError> {
+    let mut gallery = recipe.gallery.clone();
+    if gallery::cover(&gallery).is_some() {
+        return Ok(gallery);
+    }
+    let image = match database.get_one_recipe_image(recipe._id.clone()).await {
+        Ok(image) => image,
+        Err(DaoError::DocumentNotFound(Resource::Image)) => return Ok(gallery),
+        Err(err) => return Err(err.into())
+    };
+    let reference = match (referenced_hash(&image), base64::decode(image.trim())) {
+        (None, Ok(bytes)) => store_image(images, bytes).await.map(|(reference, _)| reference).unwrap_or(image),
+        _ => image
+    };
+    gallery.insert(0, cover_image(reference));
+    Ok(gallery)
+}
+
+fn gallery_image_not_found(image_id: &str) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "image-not-found", "Image not found",
+                  format!("the gallery has no image '{}'", image_id))
+}
+
+/// Stores the image without its metadata together with its variants,
+/// returns the reference and the stored size.
+async fn store_image(images: &dyn ImageStore, bytes: Vec<u8>) -> Result<(String, usize), ApiError> {
+    let bytes = variants::clean(bytes).await
+        .map_err(|err| image::invalid_image(&format!("the image could not be decoded, {}", err)))?;
+    let reference = images.put(&bytes).await?;
+    let size = bytes.len();
+    if let Some(hash) = referenced_hash(&reference) {
+        variants::put_variants(images, hash, bytes).await?;
     }
-    return None;
+    Ok((reference, size))
+}
+
+/// Updates keep the stored created, see `RecipeStore::update_recipe_ignore_image`.
+fn stamp_created(mut recipe: Recipe) -> Recipe {
+    recipe.created = store::now();
+    recipe.last_modified = recipe.created;
+    recipe
 }
 
+/// the version in `If-Match`, None when the header is missing or `*`.
+/// If-Match compares strongly (RFC 7232 3.1), a weak tag never matches and is answered with 412.
+fn if_match_version(req: &HttpRequest) -> Result<Option<u32>, ApiError> {
+    let value = match req.headers().get(header::IF_MATCH) {
+        Some(value) => value.to_str().unwrap_or_default().trim(),
+        None => return Ok(None)
+    };
+    if value == "*" {
+        return Ok(None);
+    }
+    if value.starts_with("W/") {
+        return Err(ApiError::new(StatusCode::PRECONDITION_FAILED, "weak-precondition", "Precondition failed",
+                                 format!("'{}' is a weak entity tag, If-Match only matches strong ones like \"3\"", value))
+            .with_field("If-Match"));
+    }
+    value.strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .and_then(|version| version.parse::<u32>().ok())
+        .map(Some)
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "invalid-precondition", "Invalid precondition",
+                                     format!("'{}' is no entity tag of a recipe like \"3\"", value))
+            .with_field("If-Match"))
+}
+
+fn extract_id_from_req(req: &HttpRequest) -> Result<ObjectId, ApiError> {
+    let id = req.match_info().get("id").unwrap_or_default();
+    ObjectId::with_string(id).map_err(|_| {
+        info!("Rejected request, provided id is no object id. id={}", id);
+        ApiError::invalid_id(id)
+    })
+}
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use actix_web::{App, test, web};
-    use actix_web::http::StatusCode;
+    use actix_web::http::{header, StatusCode};
     use bson::Bson;
-    use serial_test::serial;
-
-    use crate::dao::dao_tests::{before, cleanup_after};
+    use chrono::{Duration, Utc};
+
+    use ::image::ImageFormat as Codec;
+
+    use crate::config::{CacheConfig, ValidationConfig};
+    use crate::image_store::{self, ImageStore};
+    use crate::image_store::memory::MemoryImageStore;
+    use crate::image_store::variants::variants_tests::encoded;
+    use crate::model::difficulty::Difficulty;
+    use crate::model::recipe::Recipe;
+    use crate::model::unit::{Unit, UnitCatalogue, UnitSystem};
+    use crate::pagination::PAGE_MEDIA_TYPE;
     use crate::recipe_routes::RecipeRoutes;
+    use crate::store::memory::MemoryStore;
+    use crate::store::RecipeStore;
+    use crate::store::sqlite::SqliteStore;
+
+    fn memory_store() -> web::Data<dyn RecipeStore> {
+        web::Data::from(Arc::new(MemoryStore::new()) as Arc<dyn RecipeStore>)
+    }
+
+    fn png() -> Vec<u8> {
+        encoded(20, 10, Codec::Png)
+    }
+
+    fn jpeg() -> Vec<u8> {
+        encoded(300, 200, Codec::Jpeg)
+    }
+
+    fn memory_images() -> web::Data<dyn ImageStore> {
+        web::Data::from(Arc::new(MemoryImageStore::new()) as Arc<dyn ImageStore>)
+    }
 
     fn create_many_recipes() -> Bson {
         let vector = vec!(create_one_recipe_no_ingredients(),
@@ -182,7 +743,8 @@ mod tests {
     fn create_one_recipe_with_image() -> Bson {
         let bson = create_one_recipe_no_ingredients();
         let mut doc = bson.as_document().unwrap().to_owned();
-        doc.insert("image", "image".to_string()).unwrap()
+        doc.insert("image", base64::encode(png()));
+        Bson::Document(doc)
     }
 
     fn create_one_recipe_with_ingredients() -> Bson {
@@ -217,12 +779,12 @@ mod tests {
     }
 
     #[actix_rt::test]
-    #[serial]
     async fn test_add_single_recipe() {
-        let dao = before().await;
+        let store = memory_store();
 
         let mut app = test::init_service(App::new()
-            .data(dao.clone())
+            .app_data(store.clone())
+            .app_data(memory_images())
             .route("/addOneRecipe", web::post().to(RecipeRoutes::add_one_recipe))).await;
 
         let req = test::TestRequest::post().uri("/addOneRecipe").to_request();
@@ -255,17 +817,15 @@ mod tests {
         let resp = test::call_service(&mut app, req).await;
         println!("{:#?}", resp);
         assert!(resp.status().is_success(), "{}", resp.status());
-
-        cleanup_after(dao).await;
     }
 
     #[actix_rt::test]
-    #[serial]
     async fn test_delete_single_recipe() {
-        let dao = before().await;
+        let store = memory_store();
 
         let mut app = test::init_service(App::new()
-            .data(dao.clone())
+            .app_data(store.clone())
+            .app_data(memory_images())
             .route("/deleteOneRecipe/{id}", web::delete().to(RecipeRoutes::delete_one_recipe))
             .route("/addOneRecipe", web::post().to(RecipeRoutes::add_one_recipe))).await;
 
@@ -297,17 +857,15 @@ mod tests {
         let req = test::TestRequest::delete().set_json(&payload).uri(&path).to_request();
         let resp = test::call_service(&mut app, req).await;
         assert_eq!(resp.status(), StatusCode::OK);
-
-        cleanup_after(dao).await;
     }
 
     #[actix_rt::test]
-    #[serial]
     async fn test_add_many_recipes() {
-        let dao = before().await;
+        let store = memory_store();
 
         let mut app = test::init_service(App::new()
-            .data(dao.clone())
+            .app_data(store.clone())
+            .app_data(memory_images())
             .route("/addManyRecipes", web::post().to(RecipeRoutes::add_many_recipes))).await;
 
         let req = test::TestRequest::post().uri("/addManyRecipes").to_request();
@@ -326,18 +884,16 @@ mod tests {
             .set_json(&payload).uri("/addManyRecipes").to_request();
         let resp = test::call_service(&mut app, req).await;
         assert!(resp.status().is_success(), "{}", resp.status());
-
-        cleanup_after(dao).await;
     }
 
 
     #[actix_rt::test]
-    #[serial]
     async fn test_get_many_recipes() {
-        let dao = before().await;
+        let store = memory_store();
 
         let mut app = test::init_service(App::new()
-            .data(dao.clone())
+            .app_data(store.clone())
+            .app_data(memory_images())
             .route("/recipes", web::get().to(RecipeRoutes::get_many_recipes))
             .route("/addManyRecipes", web::post().to(RecipeRoutes::add_many_recipes))).await;
 
@@ -348,7 +904,7 @@ mod tests {
 
         let payload = create_many_recipes();
         let payload = payload.as_array().unwrap().clone();
-        let payload: Vec<Bson> = (0..50).into_iter().map(|_| payload.get(0).unwrap().clone()).collect();
+        let payload: Vec<Bson> = (0..50).map(|_| payload.first().unwrap().clone()).collect();
         let payload = Bson::Array(payload);
 
         let req = test::TestRequest::post()
@@ -360,23 +916,42 @@ mod tests {
         let req = test::TestRequest::get().uri("/recipes").to_request();
         let resp = test::call_service(&mut app, req).await;
         assert!(resp.status().is_success(), "{}", resp.status());
+        let recipes: Vec<Recipe> = test::read_body_json(resp).await;
+        assert_eq!(recipes.len(), 50);
+        assert!(recipes.iter().all(|recipe| recipe.image_base64.is_none()));
 
-        cleanup_after(dao).await;
+        let req = test::TestRequest::get().uri("/recipes?page=3&items=20&sorting=-1").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let recipes: Vec<Recipe> = test::read_body_json(resp).await;
+        assert_eq!(recipes.len(), 10);
     }
 
     #[actix_rt::test]
-    #[serial]
     async fn test_get_one_recipe() {
-        let dao = before().await;
+        let store = memory_store();
 
         let mut app = test::init_service(App::new()
-            .data(dao.clone())
+            .app_data(store.clone())
+            .app_data(memory_images())
             .route("/recipes/{id}", web::get().to(RecipeRoutes::get_one_recipe_without_image))
             .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))).await;
 
         let req = test::TestRequest::get().uri("/recipes/hello").to_request();
         let resp = test::call_service(&mut app, req).await;
-        assert!(resp.status().is_client_error(), "{}", resp.status());
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/problem+json");
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["type"], "urn:zellinotes:problem:invalid-id");
+        assert_eq!(body["field"], "id");
+
+        let mut payload = create_one_recipe_no_ingredients().as_document().unwrap().clone();
+        payload.insert("difficulty", "Extreme");
+        let req = test::TestRequest::post()
+            .set_json(&payload).uri("/recipes/new").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "difficulty");
 
         let payload = create_one_recipe_no_ingredients().as_document().unwrap().clone();
 
@@ -396,16 +971,365 @@ mod tests {
 
         assert!(resp.status().is_client_error(), "{}", resp.status());
 
+    }
+
+    #[actix_rt::test]
+    async fn test_scaled_recipe() {
+        let store = memory_store();
 
-        cleanup_after(dao).await;
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes/{id}", web::get().to(RecipeRoutes::get_one_recipe_without_image))
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))).await;
+
+        let req = test::TestRequest::post().set_json(&create_one_recipe_with_ingredients()).uri("/recipes/new").to_request();
+        let body: Bson = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let url = format!("/recipes/{}", body.as_object_id().unwrap());
+        let recipe: Recipe = test::read_body_json(test::call_service(&mut app, test::TestRequest::get().uri(&url).to_request()).await).await;
+
+        let servings = recipe.default_servings * 2;
+        let req = test::TestRequest::get().uri(&format!("{}?servings={}", url, servings)).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let scaled: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(scaled["scaleFactor"], 2.0);
+        assert_eq!(scaled["defaultServings"], servings);
+        assert_eq!(scaled["ingredients"], serde_json::to_value(&recipe.scaled(servings).unwrap().recipe.ingredients).unwrap());
+
+        let req = test::TestRequest::get().uri(&format!("{}?servings={}&units=imperial", url, servings)).to_request();
+        let imperial: Recipe = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(imperial.ingredients, recipe.scaled(servings).unwrap().recipe.in_units(UnitSystem::Imperial, &UnitCatalogue::builtin()).ingredients);
+        assert_ne!(imperial.ingredients, recipe.scaled(servings).unwrap().recipe.ingredients);
+
+        for servings in &["0", "1001", "many"] {
+            let req = test::TestRequest::get().uri(&format!("{}?servings={}", url, servings)).to_request();
+            assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::BAD_REQUEST, "{}", servings);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_recipe_units() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes", web::get().to(RecipeRoutes::get_many_recipes))
+            .route("/recipes/{id}", web::get().to(RecipeRoutes::get_one_recipe_without_image))
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))).await;
+
+        let mut payload = create_one_recipe_with_ingredients().as_document().unwrap().clone();
+        payload.insert("ingredients", bson!([
+            {"id": "0", "amount": 2, "title": "all-purpose flour", "measurementUnit": "Cup"},
+            {"id": "1", "amount": 3, "title": "eggs", "measurementUnit": "Piece"},
+        ]));
+        let req = test::TestRequest::post().set_json(&payload).uri("/recipes/new").to_request();
+        let body: Bson = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let url = format!("/recipes/{}", body.as_object_id().unwrap());
+
+        let req = test::TestRequest::get().uri(&format!("{}?units=metric", url)).to_request();
+        let metric: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(metric["ingredients"][0]["amount"], 250);
+        assert_eq!(metric["ingredients"][0]["measurementUnit"], "Gramm");
+        assert_eq!(metric["ingredients"][1]["measurementUnit"], "Piece");
+
+        let req = test::TestRequest::get().uri(&format!("{}?units=imperial", url)).to_request();
+        let imperial: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(imperial["ingredients"][0]["measurementUnit"], "Cup");
+
+        let req = test::TestRequest::get().uri("/recipes?units=metric").to_request();
+        let listed: Vec<Recipe> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(serde_json::to_value(&listed[0].ingredients).unwrap(), metric["ingredients"]);
+
+        let req = test::TestRequest::get().uri(&format!("{}?units=cubits", url)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        // a converted recipe has its own tag, which changes with the catalogue
+        let converted = format!("{}?units=metric", url);
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&converted).to_request()).await;
+        let etag = resp.headers().get(header::ETAG).unwrap().clone();
+        assert_ne!(etag, "\"1\"");
+        assert!(resp.headers().get(header::LAST_MODIFIED).is_none());
+        let listing = test::call_service(&mut app, test::TestRequest::get().uri("/recipes?units=metric").to_request()).await;
+        let listing_etag = listing.headers().get(header::ETAG).unwrap().clone();
+        let req = test::TestRequest::get().uri(&converted).header(header::IF_NONE_MATCH, etag.clone()).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::NOT_MODIFIED);
+
+        let bucket: Unit = serde_json::from_value(serde_json::json!({
+            "id": "Eimer", "names": {"de": "Eimer"}, "dimension": "volume", "factor": 10000.0})).unwrap();
+        store.put_unit(bucket).await.unwrap();
+        let req = test::TestRequest::get().uri(&converted).header(header::IF_NONE_MATCH, etag).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::get().uri("/recipes?units=metric").header(header::IF_NONE_MATCH, listing_etag).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_conditional_get() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .app_data(CacheConfig { image: "private, max-age=60".to_string(), ..CacheConfig::default() })
+            .route("/recipes", web::get().to(RecipeRoutes::get_many_recipes))
+            .route("/recipes/{id}", web::get().to(RecipeRoutes::get_one_recipe_without_image))
+            .route("/recipes/{id}", web::put().to(RecipeRoutes::update_one_recipe_without_image))
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))
+            .route("/recipes/{id}", web::delete().to(RecipeRoutes::delete_one_recipe))
+            .route("/recipes/{id}/image", web::get().to(RecipeRoutes::get_one_recipe_image))
+            .route("/recipes/{id}/image", web::put().to(RecipeRoutes::update_one_recipe_image))).await;
+
+        let req = test::TestRequest::post().set_json(&create_one_recipe_no_ingredients()).uri("/recipes/new").to_request();
+        let body: Bson = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let url = format!("/recipes/{}", body.as_object_id().unwrap());
+
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&url).to_request()).await;
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"1\"");
+        assert_eq!(resp.headers().get(header::CACHE_CONTROL).unwrap(), "no-cache");
+        let last_modified = resp.headers().get(header::LAST_MODIFIED).unwrap().to_str().unwrap().to_string();
+        let recipe: Recipe = test::read_body_json(resp).await;
+
+        let req = test::TestRequest::get().uri(&url).header(header::IF_NONE_MATCH, "\"1\"").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"1\"");
+        assert!(test::read_body(resp).await.is_empty());
+        let req = test::TestRequest::get().uri(&url).header(header::IF_MODIFIED_SINCE, last_modified.as_str()).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::NOT_MODIFIED);
+
+        let req = test::TestRequest::get().uri("/recipes?page=1&items=10&sorting=1").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get(header::VARY).unwrap(), "accept");
+        let listing_etag = resp.headers().get(header::ETAG).unwrap().clone();
+        let req = test::TestRequest::get().uri("/recipes?page=1&items=10&sorting=1").header(header::IF_NONE_MATCH, listing_etag.clone()).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::NOT_MODIFIED);
+        let req = test::TestRequest::get().uri("/recipes?page=1&items=10&sorting=1").header(header::ACCEPT, PAGE_MEDIA_TYPE)
+            .header(header::IF_NONE_MATCH, listing_etag.clone()).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+
+        let req = test::TestRequest::put().set_json(&recipe).uri(&url).to_request();
+        assert!(test::call_service(&mut app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri(&url).header(header::IF_NONE_MATCH, "\"1\"").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"2\"");
+        let req = test::TestRequest::get().uri("/recipes?page=1&items=10&sorting=1").header(header::IF_NONE_MATCH, listing_etag).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+
+        let image = format!("{}/image", url);
+        let req = test::TestRequest::put().header(header::CONTENT_TYPE, "image/png").set_payload(png()).uri(&image).to_request();
+        assert!(test::call_service(&mut app, req).await.status().is_success());
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&image).to_request()).await;
+        let image_etag = format!("\"{}\"", image_store::hash(&png()));
+        assert_eq!(resp.headers().get(header::ETAG).unwrap().to_str().unwrap(), image_etag);
+        assert_eq!(resp.headers().get(header::CACHE_CONTROL).unwrap(), "private, max-age=60");
+        let req = test::TestRequest::get().uri(&image).header(header::IF_NONE_MATCH, image_etag.as_str()).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers().get(header::CACHE_CONTROL).unwrap(), "private, max-age=60");
+    }
+
+    #[actix_rt::test]
+    async fn test_server_sets_timestamps() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes/{id}", web::get().to(RecipeRoutes::get_one_recipe_without_image))
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))
+            .route("/recipes/{id}/image", web::put().to(RecipeRoutes::update_one_recipe_image))).await;
+
+        let mut payload = create_one_recipe_no_ingredients().as_document().unwrap().clone();
+        payload.remove("lastModified");
+        let req = test::TestRequest::post().set_json(&payload).uri("/recipes/new").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success(), "{}", resp.status());
+        let body: Bson = test::read_body_json(resp).await;
+        let url = format!("/recipes/{}", body.as_object_id().unwrap());
+
+        let req = test::TestRequest::get().uri(&url).to_request();
+        let added: Recipe = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert!(added.created > Utc::now() - Duration::minutes(1));
+        assert_eq!(added.last_modified, added.created);
+
+        let req = test::TestRequest::put().set_payload(base64::encode(png())).uri(&format!("{}/image", url)).to_request();
+        assert!(test::call_service(&mut app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri(&url).to_request();
+        let changed: Recipe = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(changed.created, added.created);
+        assert!(changed.last_modified >= added.last_modified);
+    }
+
+    #[actix_rt::test]
+    async fn test_gallery() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes/{id}", web::get().to(RecipeRoutes::get_one_recipe_without_image))
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))
+            .route("/recipes/{id}/image", web::get().to(RecipeRoutes::get_one_recipe_image))
+            .route("/recipes/{id}/gallery", web::post().to(RecipeRoutes::add_gallery_image))
+            .route("/recipes/{id}/gallery", web::put().to(RecipeRoutes::update_gallery))
+            .route("/recipes/{id}/gallery/{imageId}", web::get().to(RecipeRoutes::get_gallery_image))
+            .route("/recipes/{id}/gallery/{imageId}", web::delete().to(RecipeRoutes::delete_gallery_image))).await;
+
+        let mut payload = create_one_recipe_with_image().as_document().unwrap().clone();
+        payload.insert("instructions", vec!["Kneten", "Backen"]);
+        let req = test::TestRequest::post().set_json(&payload).uri("/recipes/new").to_request();
+        let body: Bson = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let url = format!("/recipes/{}", body.as_object_id().unwrap());
+        let gallery = format!("{}/gallery", url);
+
+        let req = test::TestRequest::post().header(header::CONTENT_TYPE, "image/jpeg").set_payload(jpeg())
+            .uri(&format!("{}?caption=Teig&step=1", gallery)).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let added: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(added["caption"], "Teig");
+        assert_eq!(added["step"], 1);
+        assert_eq!(added["cover"], false);
+
+        let req = test::TestRequest::post().header(header::CONTENT_TYPE, "image/jpeg").set_payload(jpeg())
+            .uri(&format!("{}?step=2", gallery)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let recipe: Recipe = test::read_body_json(test::call_service(&mut app, test::TestRequest::get().uri(&url).to_request()).await).await;
+        assert_eq!(recipe.gallery.len(), 2);
+        assert!(recipe.gallery[0].cover);
+        let (cover, step) = (recipe.gallery[0].id.clone(), recipe.gallery[1].id.clone());
+
+        let req = test::TestRequest::get().uri(&format!("{}/{}?size=thumb", gallery, step)).to_request();
+        let thumb = ::image::load_from_memory(&test::read_body(test::call_service(&mut app, req).await).await).unwrap();
+        assert_eq!(thumb.width(), 200);
+
+        let req = test::TestRequest::put().set_json(&serde_json::json!([{"id": step, "cover": true}, {"id": cover, "caption": "Fertig"}]))
+            .uri(&gallery).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"3\"");
+        let changed: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(changed[0]["id"], step.as_str());
+        assert_eq!(changed[0].get("step"), None);
+        assert_eq!(changed[1]["caption"], "Fertig");
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&format!("{}/image", url)).to_request()).await;
+        assert_eq!(test::read_body(resp).await, jpeg());
+
+        let req = test::TestRequest::put().set_json(&serde_json::json!([{"id": step}, {"id": "other"}])).uri(&gallery).to_request();
+        let body: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(body["violations"].as_array().unwrap().len(), 2);
+
+        let req = test::TestRequest::delete().header(header::IF_MATCH, "\"2\"").uri(&format!("{}/{}", gallery, step)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::PRECONDITION_FAILED);
+        let req = test::TestRequest::delete().uri(&format!("{}/{}", gallery, step)).to_request();
+        assert!(test::call_service(&mut app, req).await.status().is_success());
+        let req = test::TestRequest::delete().uri(&format!("{}/{}", gallery, step)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::NOT_FOUND);
+
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&format!("{}/image", url)).to_request()).await;
+        assert_eq!(test::read_body(resp).await, png());
+    }
+
+    #[actix_rt::test]
+    async fn test_recipe_image() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))
+            .route("/recipes/{id}/image", web::get().to(RecipeRoutes::get_one_recipe_image))
+            .route("/recipes/{id}/image", web::put().to(RecipeRoutes::update_one_recipe_image))
+            .route("/recipes/{id}/image", web::delete().to(RecipeRoutes::delete_one_recipe_image))).await;
+
+        let req = test::TestRequest::post().set_json(&create_one_recipe_no_ingredients()).uri("/recipes/new").to_request();
+        let body: Bson = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let url = format!("/recipes/{}/image", body.as_object_id().unwrap());
+
+        let req = test::TestRequest::put().header(header::CONTENT_TYPE, "image/jpeg").set_payload(jpeg()).uri(&url).to_request();
+        let body: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(body, serde_json::json!({"contentType": "image/jpeg", "size": jpeg().len()}));
+
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&url).to_request()).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "image/jpeg");
+        assert_eq!(resp.headers().get(header::CACHE_CONTROL).unwrap(), "no-cache");
+        assert_eq!(test::read_body(resp).await, jpeg());
+
+        let req = test::TestRequest::get().uri(&format!("{}?encoding=base64", url)).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "text/plain; charset=utf-8");
+        assert_eq!(test::read_body(resp).await, base64::encode(jpeg()));
+
+        let req = test::TestRequest::get().uri(&format!("{}?size=thumb", url)).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "image/jpeg");
+        let thumb = ::image::load_from_memory(&test::read_body(resp).await).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (200, 133));
+        let req = test::TestRequest::get().uri(&format!("{}?size=huge", url)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::put().header(header::CONTENT_TYPE, "image/jpeg").set_payload(png()).uri(&url).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&url).to_request()).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "image/jpeg");
+
+        let req = test::TestRequest::delete().uri(&url).to_request();
+        assert!(test::call_service(&mut app, req).await.status().is_success());
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&url).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["type"], "urn:zellinotes:problem:image-not-found");
+
+        let req = test::TestRequest::post().set_json(&create_one_recipe_with_image()).uri("/recipes/new").to_request();
+        let body: Bson = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let id = body.as_object_id().unwrap().clone();
+        assert_eq!(store.get_one_recipe_image(id.clone()).await.unwrap(), format!("sha256:{}", image_store::hash(&png())));
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&format!("/recipes/{}/image", id)).to_request()).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "image/png");
+        assert_eq!(test::read_body(resp).await, png());
+
+        let mut inline: Recipe = bson::from_bson(create_one_recipe_no_ingredients()).unwrap();
+        inline.image_base64 = Some(base64::encode(jpeg()));
+        let id = store.insert_recipe(inline).await.unwrap();
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&format!("/recipes/{}/image", id.as_object_id().unwrap())).to_request()).await;
+        assert_eq!(test::read_body(resp).await, jpeg());
+
+        let mut payload = create_one_recipe_no_ingredients().as_document().unwrap().clone();
+        payload.insert("image", "no image");
+        let req = test::TestRequest::post().set_json(&payload).uri("/recipes/new").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        payload.insert("image", format!("sha256:{}", image_store::hash(&png())));
+        let req = test::TestRequest::post().set_json(&payload).uri("/recipes/new").to_request();
+        assert!(test::call_service(&mut app, req).await.status().is_success());
+
+        payload.insert("image", format!("sha256:{}", image_store::hash(b"never stored")));
+        let req = test::TestRequest::post().set_json(&payload).uri("/recipes/new").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["type"], "urn:zellinotes:problem:unknown-image");
+        assert_eq!(body["field"], "image");
+
+        let mut dangling: Recipe = bson::from_bson(create_one_recipe_no_ingredients()).unwrap();
+        dangling.image_base64 = Some(format!("sha256:{}", image_store::hash(b"never stored")));
+        let id = store.insert_recipe(dangling).await.unwrap();
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(&format!("/recipes/{}/image", id.as_object_id().unwrap())).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["type"], "urn:zellinotes:problem:image-not-found");
     }
 
     #[actix_rt::test]
     async fn test_update_one_recipe() {
-        let dao = before().await;
+        let store = memory_store();
 
         let mut app = test::init_service(App::new()
-            .data(dao.clone())
+            .app_data(store.clone())
+            .app_data(memory_images())
             .route("/recipes/{id}", web::get().to(RecipeRoutes::get_one_recipe_without_image))
             .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))
             .route("/recipes/{id}", web::put().to(RecipeRoutes::update_one_recipe_without_image))).await;
@@ -418,19 +1342,397 @@ mod tests {
         let resp = test::call_service(&mut app, req).await;
         assert!(resp.status().is_success(), "{}", resp.status());
 
-        //     todo get body from resp and extract id.
+        let body: Bson = test::read_body_json(resp).await;
+        let id = body.as_object_id().unwrap().to_string();
 
         payload.insert("difficulty", "Medium");
-        let id = "5f7333360051027600b01a36".to_string();
         let url = format!("/recipes/{}", id);
 
         let req = test::TestRequest::put().set_json(&payload).uri(&url).to_request();
 
         let resp = test::call_service(&mut app, req).await;
         assert!(resp.status().is_success(), "{}", resp.status());
-        // todo check if recipe was updated
 
+        let req = test::TestRequest::get().uri(&url).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"2\"");
+        let recipe: Recipe = test::read_body_json(resp).await;
+        assert_eq!(recipe.difficulty, Difficulty::Medium);
+        assert_eq!(recipe.version, 2);
+
+        payload.insert("difficulty", "Hard");
+        let req = test::TestRequest::put().set_json(&payload).uri(&url).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"2\"");
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["current"]["difficulty"], "Medium");
+        assert_eq!(body["current"]["version"], 2);
+
+        let req = test::TestRequest::put().set_json(&payload).uri(&url)
+            .header(header::IF_MATCH, "\"2\"").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), "\"3\"");
+
+        let req = test::TestRequest::put().set_json(&payload).uri(&url)
+            .header(header::IF_MATCH, "\"2\"").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::PRECONDITION_FAILED);
+
+        let req = test::TestRequest::put().set_json(&payload).uri(&url)
+            .header(header::IF_MATCH, "W/\"3\"").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["type"], "urn:zellinotes:problem:weak-precondition");
+        assert_eq!(body["field"], "If-Match");
 
-        cleanup_after(dao).await;
+        let req = test::TestRequest::put().set_json(&payload).uri(&url)
+            .header(header::IF_MATCH, "latest").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "If-Match");
+
+        let url = "/recipes/5f7333360051027600b01a36";
+        let req = test::TestRequest::put().set_json(&payload).uri(url).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_import_text() {
+        let mut app = test::init_service(App::new()
+            .app_data(memory_store())
+            .app_data(memory_images())
+            .route("/recipes/import/text", web::post().to(RecipeRoutes::import_text))
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))).await;
+
+        let text = "Tomatensuppe\nFür 2 Personen\nKochzeit: 30 Minuten\n\nZutaten\n1 Dose Tomaten\n½ l Brühe\nFür die Deko:\n\n\
+                    Zubereitung\n1. Alles aufkochen.\n2. Pürieren.";
+        let req = test::TestRequest::post().set_json(&serde_json::json!({"text": text})).uri("/recipes/import/text").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["recipe"]["title"], "Tomatensuppe");
+        assert_eq!(body["recipe"]["defaultServings"], 2);
+        assert_eq!(body["recipe"]["cookingTimeInMinutes"], 30);
+        assert_eq!(body["recipe"]["ingredients"][1], serde_json::json!({"id": "1", "amount": "½", "title": "Brühe", "measurementUnit": "Liter"}));
+        assert_eq!(body["recipe"]["instructions"], serde_json::json!(["Alles aufkochen.", "Pürieren."]));
+        assert_eq!(body["unparsed"], serde_json::json!([{"line": 8, "text": "Für die Deko:"}]));
+
+        let req = test::TestRequest::post().set_json(&body["recipe"]).uri("/recipes/new").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+
+        let mut app = test::init_service(App::new()
+            .app_data(memory_store())
+            .app_data(ValidationConfig { max_ingredients: 1, ..ValidationConfig::default() })
+            .route("/recipes/import/text", web::post().to(RecipeRoutes::import_text))).await;
+        let req = test::TestRequest::post().set_json(&serde_json::json!({"text": text})).uri("/recipes/import/text").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["type"], "urn:zellinotes:problem:too-many-lines");
+        assert_eq!(body["field"], "text");
+
+        let text = "Für die Deko:\n".repeat(2 * (1 + ValidationConfig::default().max_instructions) + 1);
+        let req = test::TestRequest::post().set_json(&serde_json::json!({"text": text})).uri("/recipes/import/text").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_rt::test]
+    async fn test_validate_recipe() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .app_data(ValidationConfig { max_tags: 1, ..Default::default() })
+            .route("/recipes/validate", web::post().to(RecipeRoutes::validate_recipe))
+            .route("/recipes", web::get().to(RecipeRoutes::get_many_recipes))
+            .route("/recipes", web::post().to(RecipeRoutes::add_many_recipes))
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))).await;
+
+        let mut invalid = create_one_recipe_with_ingredients().as_document().unwrap().clone();
+        invalid.insert("title", "");
+        invalid.insert("tags", vec!["quick", "vegan"]);
+        invalid.insert("defaultServings", 0);
+
+        let req = test::TestRequest::post().set_json(&invalid).uri("/recipes/validate").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["valid"], false);
+        assert_eq!(body["violations"].as_array().unwrap().iter().map(|violation| violation["field"].as_str().unwrap()).collect::<Vec<&str>>(),
+                   vec!["title", "defaultServings", "tags"]);
+
+        let req = test::TestRequest::post().set_json(&invalid).uri("/recipes/new").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["violations"].as_array().unwrap().len(), 3);
+
+        let payload = Bson::Array(vec![create_one_recipe_no_ingredients(), Bson::Document(invalid)]);
+        let req = test::TestRequest::post().set_json(&payload).uri("/recipes").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["violations"][0]["field"], "[1].title");
+
+        let req = test::TestRequest::get().uri("/recipes").to_request();
+        let recipes: Vec<Recipe> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert!(recipes.is_empty());
+
+        let req = test::TestRequest::post().set_json(&create_one_recipe_no_ingredients()).uri("/recipes/validate").to_request();
+        let body: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(body, serde_json::json!({"valid": true, "violations": []}));
+    }
+
+    #[actix_rt::test]
+    async fn test_search_recipes() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes/search", web::get().to(RecipeRoutes::search_recipes))
+            .route("/addManyRecipes", web::post().to(RecipeRoutes::add_many_recipes))).await;
+
+        let mut cheese = create_one_recipe_with_ingredients().as_document().unwrap().clone();
+        cheese.insert("title", "Käsebrötchen");
+        cheese.insert("description", "Brötchen mit geschmolzenem Käse");
+        let payload = Bson::Array(vec![create_one_recipe_with_ingredients(), Bson::Document(cheese)]);
+        let req = test::TestRequest::post()
+            .set_json(&payload).uri("/addManyRecipes").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success(), "{}", resp.status());
+
+        let req = test::TestRequest::get().uri("/recipes/search?q=kaese").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success(), "{}", resp.status());
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["results"][0]["recipe"]["title"], "Käsebrötchen");
+        assert_eq!(body["results"][0]["highlights"][0]["snippet"], "<mark>Käsebrötchen</mark>");
+
+        let req = test::TestRequest::get().uri("/recipes/search?q=%22geschmolzenem%20K%C3%A4se%22").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["total"], 1);
+
+        let req = test::TestRequest::get().uri("/recipes/search?q=wheat&limit=1&offset=1").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["results"].as_array().unwrap().len(), 1);
+
+        for uri in &["/recipes/search", "/recipes/search?q=%22%22", "/recipes/search?q=wheat&limit=0"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_filter_recipes() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes", web::get().to(RecipeRoutes::get_many_recipes))
+            .route("/addManyRecipes", web::post().to(RecipeRoutes::add_many_recipes))).await;
+
+        let mut hard = create_one_recipe_with_ingredients().as_document().unwrap().clone();
+        hard.insert("difficulty", "Hard");
+        hard.insert("tags", vec!["vegan"]);
+        let payload = Bson::Array(vec![create_one_recipe_no_ingredients(), Bson::Document(hard)]);
+        let req = test::TestRequest::post()
+            .set_json(&payload).uri("/addManyRecipes").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success(), "{}", resp.status());
+
+        let req = test::TestRequest::get().uri("/recipes?difficulty=Hard&tags=vegan&ingredient=milk").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let recipes: Vec<Recipe> = test::read_body_json(resp).await;
+        assert_eq!(recipes.len(), 1);
+        assert_eq!(recipes[0].difficulty, Difficulty::Hard);
+
+        let req = test::TestRequest::get().uri("/recipes?page=1&items=5&sorting=1&maxCookingTime=11").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let recipes: Vec<Recipe> = test::read_body_json(resp).await;
+        assert!(recipes.is_empty());
+
+        let req = test::TestRequest::get().uri("/recipes?difficulty=Impossible").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "difficulty");
+        assert!(body["detail"].as_str().unwrap().contains("'difficulty'"));
+    }
+
+    #[actix_rt::test]
+    async fn test_get_recipes_by_cursor() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes", web::get().to(RecipeRoutes::get_many_recipes))
+            .route("/addManyRecipes", web::post().to(RecipeRoutes::add_many_recipes))).await;
+
+        let payload: Vec<Bson> = (0..5).map(|i| {
+            let mut recipe = create_one_recipe_no_ingredients().as_document().unwrap().clone();
+            recipe.insert("title", format!("recipe {}", i % 3));
+            Bson::Document(recipe)
+        }).collect();
+        let req = test::TestRequest::post()
+            .set_json(&Bson::Array(payload)).uri("/addManyRecipes").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success(), "{}", resp.status());
+
+        let mut titles: Vec<String> = vec![];
+        let mut uri = "/recipes?sort=-title&items=2&cursor=".to_string();
+        loop {
+            let req = test::TestRequest::get().uri(&uri).to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert!(resp.status().is_success(), "{}", resp.status());
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            let items: Vec<Recipe> = serde_json::from_value(body["items"].clone()).unwrap();
+            assert!(!items.is_empty() && items.len() <= 2);
+            titles.extend(items.into_iter().map(|recipe| recipe.title));
+            match body["nextCursor"].as_str() {
+                Some(cursor) => uri = format!("/recipes?sort=-title&items=2&cursor={}", cursor),
+                None => break
+            }
+        }
+        assert_eq!(titles, vec!["recipe 2", "recipe 1", "recipe 1", "recipe 0", "recipe 0"]);
+
+        let req = test::TestRequest::get().uri("/recipes?sort=title&items=2&cursor=abc").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "cursor");
+
+        let req = test::TestRequest::get().uri("/recipes?sort=title&page=1&items=2").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let recipes: Vec<Recipe> = test::read_body_json(resp).await;
+        assert_eq!(recipes.iter().map(|recipe| recipe.title.as_str()).collect::<Vec<&str>>(), vec!["recipe 0", "recipe 0"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_recipes_with_totals() {
+        let store = memory_store();
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes", web::get().to(RecipeRoutes::get_many_recipes))
+            .route("/addManyRecipes", web::post().to(RecipeRoutes::add_many_recipes))).await;
+
+        let payload = Bson::Array((0..12).map(|_| create_one_recipe_no_ingredients()).collect());
+        let req = test::TestRequest::post()
+            .set_json(&payload).uri("/addManyRecipes").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success(), "{}", resp.status());
+
+        let req = test::TestRequest::get().uri("/recipes?page=2&items=5&sorting=1").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get("X-Total-Count").unwrap(), "12");
+        assert_eq!(resp.headers().get(header::LINK).unwrap(),
+                   "</recipes?items=5&sorting=1&page=1>; rel=\"first\", </recipes?items=5&sorting=1&page=1>; rel=\"prev\", \
+                    </recipes?items=5&sorting=1&page=3>; rel=\"next\", </recipes?items=5&sorting=1&page=3>; rel=\"last\"");
+        let recipes: Vec<Recipe> = test::read_body_json(resp).await;
+        assert_eq!(recipes.len(), 5);
+
+        let req = test::TestRequest::get().uri("/recipes?page=3&items=5&sorting=1")
+            .header(header::ACCEPT, "text/html, application/vnd.zellinotes.recipe-page+json;q=0.9").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/vnd.zellinotes.recipe-page+json");
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["page"], 3);
+        assert_eq!(body["itemsPerPage"], 5);
+        assert_eq!(body["totalItems"], 12);
+        assert_eq!(body["totalPages"], 3);
+        assert_eq!(body["items"].as_array().unwrap().len(), 2);
+
+        let req = test::TestRequest::get().uri("/recipes?envelope=true&difficulty=Hard").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get("X-Total-Count").unwrap(), "0");
+        assert!(resp.headers().get(header::LINK).is_none());
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["totalPages"], 1);
+        assert_eq!(body["items"], serde_json::json!([]));
+    }
+
+    #[actix_rt::test]
+    async fn test_lenient_listing() {
+        let sqlite = Arc::new(SqliteStore::open_in_memory().unwrap());
+        let store = web::Data::from(sqlite.clone() as Arc<dyn RecipeStore>);
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(memory_images())
+            .route("/recipes", web::get().to(RecipeRoutes::get_many_recipes))
+            .route("/scan", web::get().to(RecipeRoutes::scan_recipes))
+            .route("/addManyRecipes", web::post().to(RecipeRoutes::add_many_recipes))).await;
+
+        let req = test::TestRequest::post().set_json(&create_many_recipes()).uri("/addManyRecipes").to_request();
+        let ids: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        // the recipes were created in the same millisecond, ids are no order
+        for (created, id) in ids.as_array().unwrap().iter().enumerate() {
+            sqlite.execute(&format!("UPDATE recipes SET created = {} WHERE id = '{}'", created, id["$oid"].as_str().unwrap())).unwrap();
+        }
+        let id = ids[1]["$oid"].as_str().unwrap();
+        sqlite.execute(&format!("UPDATE recipes SET difficulty = 'Impossible' WHERE id = '{}'", id)).unwrap();
+        let warnings = serde_json::json!([
+            {"id": id, "error": "Difficulty 'Impossible' does not match one predefined value"}]);
+
+        let req = test::TestRequest::get().uri("/recipes?lenient=true").to_request();
+        let body: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(body["items"].as_array().unwrap().len(), 2);
+        assert_eq!(body["warnings"], warnings);
+
+        let req = test::TestRequest::get().uri("/recipes?lenient=true&envelope=true&page=1&items=5&sorting=1").to_request();
+        let body: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(body["totalItems"], 3);
+        assert_eq!(body["items"].as_array().unwrap().len(), 2);
+        assert_eq!(body["warnings"], warnings);
+
+        let req = test::TestRequest::get().uri("/recipes?lenient=true&sort=created&items=2&cursor=").to_request();
+        let body: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(body["items"].as_array().unwrap().len(), 2);
+        assert_eq!(body["nextCursor"], serde_json::Value::Null);
+        assert_eq!(body["warnings"], warnings);
+
+        // without lenient a page with the unreadable recipe is answered with 500, before any recipe is sent
+        for uri in &["/recipes?page=1&items=2&sorting=1", "/recipes?sort=created&items=1&cursor="] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR, "{}", uri);
+            assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/problem+json");
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["status"], 500);
+        }
+
+        // pages without it are still answered
+        let req = test::TestRequest::get().uri("/recipes?page=2&items=2&sorting=1").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let recipes: Vec<Recipe> = test::read_body_json(resp).await;
+        assert_eq!(recipes.len(), 1);
+
+        let req = test::TestRequest::get().uri("/scan").to_request();
+        let body: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(body, serde_json::json!({"scanned": 3, "unreadable": warnings}));
+
+        // a listing without limit only reads its first recipe ahead
+        let req = test::TestRequest::get().uri("/recipes").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+        let first = ids[0]["$oid"].as_str().unwrap();
+        sqlite.execute(&format!("UPDATE recipes SET difficulty = 'Impossible' WHERE id = '{}'", first)).unwrap();
+        let req = test::TestRequest::get().uri("/recipes").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
 }
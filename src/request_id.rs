@@ -0,0 +1,109 @@
+use std::task::{Context, Poll};
+
+use actix_web::{Error, ResponseError};
+use actix_web::body::Body;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{HeaderName, HeaderValue};
+use bson::oid::ObjectId;
+use futures_util::future::{ok, LocalBoxFuture, Ready};
+
+use crate::error::ApiError;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Tags every request with an id that is returned in the `X-Request-Id` header and in
+/// every problem body. A sane id sent by the client, e.g. from a proxy, is kept.
+pub struct RequestId;
+
+impl<S> Transform<S> for RequestId
+    where S: Service<Request=ServiceRequest, Response=ServiceResponse<Body>, Error=Error>,
+          S::Future: 'static {
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Body>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdMiddleware { service })
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service for RequestIdMiddleware<S>
+    where S: Service<Request=ServiceRequest, Response=ServiceResponse<Body>, Error=Error>,
+          S::Future: 'static {
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Body>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let id = req.headers().get(REQUEST_ID_HEADER)
+            .and_then(|id| id.to_str().ok())
+            .filter(|id| is_sane(id))
+            .map(String::from)
+            .unwrap_or_else(|| ObjectId::new().to_hex());
+
+        let response = self.service.call(req);
+        Box::pin(async move {
+            let mut response = response.await?;
+            let problem = response.response().error()
+                .and_then(|err| err.as_error::<ApiError>())
+                .map(|err| err.clone().with_request_id(&id));
+            if let Some(problem) = problem {
+                let rendered = problem.error_response();
+                response = response.into_response(rendered);
+            }
+            if let Ok(value) = HeaderValue::from_str(&id) {
+                response.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// only short ids of safe characters are taken over, anything else could forge log lines
+fn is_sane(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+
+#[cfg(test)]
+mod request_id_tests {
+    use actix_web::{App, HttpResponse, test, web};
+
+    use crate::error::ApiError;
+    use crate::request_id::RequestId;
+
+    #[actix_rt::test]
+    async fn adds_request_id() {
+        let mut app = test::init_service(App::new()
+            .wrap(RequestId)
+            .route("/ok", web::get().to(HttpResponse::Ok))
+            .route("/fail", web::get().to(|| async { Err::<HttpResponse, ApiError>(ApiError::recipe_not_found()) }))).await;
+
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri("/ok").to_request()).await;
+        assert_eq!(resp.headers().get("X-Request-Id").unwrap().len(), 24);
+
+        let req = test::TestRequest::get().uri("/fail").header("X-Request-Id", "trace-42").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get("X-Request-Id").unwrap(), "trace-42");
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["requestId"], "trace-42");
+        assert_eq!(body["status"], 404);
+
+        let req = test::TestRequest::get().uri("/ok").header("X-Request-Id", "a b\"c").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_ne!(resp.headers().get("X-Request-Id").unwrap(), "a b\"c");
+    }
+}
@@ -0,0 +1,404 @@
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use futures_util::{future, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::model::recipe::Recipe;
+use crate::store::{DaoError, RecipeStream};
+
+pub const DEFAULT_LIMIT: usize = 20;
+pub const MAX_LIMIT: usize = 100;
+
+const WEIGHT_TITLE: f64 = 8.0;
+const WEIGHT_TAGS: f64 = 5.0;
+const WEIGHT_INGREDIENTS: f64 = 4.0;
+const WEIGHT_DESCRIPTION: f64 = 2.0;
+const WEIGHT_INSTRUCTIONS: f64 = 1.0;
+
+/// A token only starting with the query term counts less than a whole word.
+const PREFIX_MATCH_QUALITY: f64 = 0.5;
+
+const MAX_HIGHLIGHTS: usize = 5;
+const SNIPPET_CHARS: usize = 160;
+const SNIPPET_CONTEXT_CHARS: usize = 50;
+const MARK_START: &str = "<mark>";
+const MARK_END: &str = "</mark>";
+
+#[derive(Deserialize, Debug)]
+pub struct SearchParams {
+    pub q: String,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchResults {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub results: Vec<SearchHit>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchHit {
+    pub score: f64,
+    pub recipe: Recipe,
+    pub highlights: Vec<Highlight>,
+}
+
+/// Part of a matching field, HTML escaped with the matches wrapped in `<mark>`.
+#[derive(Serialize, Debug, Eq, PartialEq)]
+pub struct Highlight {
+    pub field: &'static str,
+    pub snippet: String,
+}
+
+/// Words are matched on their own, text in double quotes only as a phrase.
+/// Every word and phrase has to be found in a recipe for it to match.
+#[derive(Debug, Eq, PartialEq)]
+pub struct SearchQuery {
+    phrases: Vec<Vec<String>>,
+}
+
+impl SearchQuery {
+    /// None when the query contains no searchable word
+    pub fn parse(query: &str) -> Option<Self> {
+        let mut phrases = vec![];
+        for (i, part) in query.split('"').enumerate() {
+            let terms = tokenize(part).into_iter().map(|token| token.folded);
+            if i % 2 == 0 {
+                phrases.extend(terms.map(|term| vec![term]));
+            } else {
+                let phrase: Vec<String> = terms.collect();
+                if !phrase.is_empty() {
+                    phrases.push(phrase);
+                }
+            }
+        }
+
+        if phrases.is_empty() {
+            return None;
+        }
+        return Some(SearchQuery { phrases });
+    }
+}
+
+struct Token {
+    start: usize,
+    end: usize,
+    folded: String,
+}
+
+struct Match {
+    range: Range<usize>,
+    quality: f64,
+}
+
+/// Lowercase without diacritics, umlauts are written out ("ä" as "ae") so "kaese" finds "Käse".
+/// Only that direction, "ae" in words like "Israel" or "blue" is no umlaut.
+fn fold(word: &str) -> String {
+    let lowercase: String = word.nfc().flat_map(char::to_lowercase).collect();
+    lowercase.replace('ä', "ae")
+        .replace('ö', "oe")
+        .replace('ü', "ue")
+        .replace('ß', "ss")
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect()
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(begin), false) => {
+                tokens.push(Token { start: begin, end: i, folded: fold(&text[begin..i]) });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    return tokens;
+}
+
+/// Whole words have to match, only the last word of a phrase may be a prefix.
+fn find_matches(tokens: &[Token], phrase: &[String]) -> Vec<Match> {
+    if tokens.len() < phrase.len() {
+        return vec![];
+    }
+    let last = phrase.len() - 1;
+    (0..=tokens.len() - phrase.len())
+        .filter_map(|first| {
+            let window = &tokens[first..first + phrase.len()];
+            let whole = window[..last].iter().zip(phrase).all(|(token, term)| &token.folded == term);
+            if !whole || !window[last].folded.starts_with(&phrase[last]) {
+                return None;
+            }
+            let quality = if window[last].folded == phrase[last] { 1.0 } else { PREFIX_MATCH_QUALITY };
+            Some(Match { range: window[0].start..window[last].end, quality })
+        })
+        .collect()
+}
+
+fn searchable_fields(recipe: &Recipe) -> Vec<(&'static str, f64, &str)> {
+    let mut fields = vec![("title", WEIGHT_TITLE, recipe.title.as_str())];
+    fields.extend(recipe.tags.iter().map(|tag| ("tags", WEIGHT_TAGS, tag.as_str())));
+    fields.extend(recipe.ingredients.iter().map(|ingredient| ("ingredients", WEIGHT_INGREDIENTS, ingredient.title.as_str())));
+    fields.push(("description", WEIGHT_DESCRIPTION, recipe.description.as_str()));
+    fields.extend(recipe.instructions.iter().map(|instruction| ("instructions", WEIGHT_INSTRUCTIONS, instruction.as_str())));
+    return fields;
+}
+
+/// None when not every part of the query is found in the recipe.
+pub fn score(recipe: Recipe, query: &SearchQuery) -> Option<SearchHit> {
+    let fields: Vec<_> = searchable_fields(&recipe).into_iter()
+        .map(|(field, weight, text)| (field, weight, text, tokenize(text)))
+        .collect();
+    let mut ranges: Vec<Vec<Range<usize>>> = vec![vec![]; fields.len()];
+    let mut score = 0.0;
+
+    for phrase in &query.phrases {
+        let mut raw = 0.0;
+        for (i, (_, weight, _, tokens)) in fields.iter().enumerate() {
+            for found in find_matches(tokens, phrase) {
+                raw += weight * found.quality * phrase.len() as f64;
+                ranges[i].push(found.range);
+            }
+        }
+        if raw == 0.0 {
+            return None;
+        }
+        score += (1.0 + raw).ln();
+    }
+
+    let highlights = fields.iter().zip(ranges)
+        .filter(|(_, ranges)| !ranges.is_empty())
+        .take(MAX_HIGHLIGHTS)
+        .map(|((field, _, text, _), ranges)| Highlight { field, snippet: snippet(text, ranges) })
+        .collect();
+
+    return Some(SearchHit { score, recipe, highlights });
+}
+
+/// Scans every recipe of the stream, so any store can be searched. Best matches first.
+/// Unreadable recipes are left out like in a lenient listing, other errors end the search.
+pub async fn search(recipes: RecipeStream, query: &SearchQuery) -> Result<Vec<SearchHit>, DaoError> {
+    let mut hits: Vec<SearchHit> = recipes
+        .filter(|recipe| future::ready(match recipe {
+            Err(DaoError::UnreadableRecipe(unreadable)) => {
+                warn!("Leaving unreadable recipe out of search. id={}, err={}", unreadable.id, unreadable.error);
+                false
+            }
+            _ => true
+        }))
+        .try_filter_map(|recipe| async move { Ok(score(recipe, query)) })
+        .try_collect()
+        .await?;
+    hits.sort_by(|a, b| b.score.partial_cmp(&a.score)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a.recipe.title.cmp(&b.recipe.title)));
+    return Ok(hits);
+}
+
+fn snippet(text: &str, mut ranges: Vec<Range<usize>>) -> String {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = vec![];
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    let first = merged[0].clone();
+    let mut from = text[..first.start].char_indices().rev()
+        .nth(SNIPPET_CONTEXT_CHARS - 1)
+        .map_or(0, |(i, _)| i);
+    if from > 0 {
+        from = text[from..first.start].find(char::is_whitespace).map_or(from, |i| from + i + 1);
+    }
+    let mut to = text[from..].char_indices()
+        .nth(SNIPPET_CHARS)
+        .map_or(text.len(), |(i, _)| from + i)
+        .max(first.end);
+    if to < text.len() {
+        to = text[first.end..to].rfind(char::is_whitespace).map_or(to, |i| first.end + i);
+    }
+
+    let mut snippet = String::new();
+    if from > 0 {
+        snippet.push('…');
+    }
+    let mut position = from;
+    for range in merged.into_iter().filter(|range| range.start < to) {
+        let end = range.end.min(to);
+        snippet.push_str(&escape_html(&text[position..range.start]));
+        snippet.push_str(MARK_START);
+        snippet.push_str(&escape_html(&text[range.start..end]));
+        snippet.push_str(MARK_END);
+        position = end;
+    }
+    snippet.push_str(&escape_html(&text[position..to]));
+    if to < text.len() {
+        snippet.push('…');
+    }
+    return snippet;
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+
+#[cfg(test)]
+mod search_tests {
+    use futures_util::stream::{self, StreamExt};
+
+    use crate::dao::dao_tests::create_one_recipe_without_image;
+    use crate::model::ingredients::Ingredient;
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::recipe::Recipe;
+    use crate::search::{fold, Highlight, score, search, SearchQuery, snippet};
+    use crate::store::{DaoError, UnreadableRecipe};
+
+    fn recipe(title: &str, description: &str, tags: &[&str], ingredients: &[&str], instructions: &[&str]) -> Recipe {
+        let mut recipe = create_one_recipe_without_image();
+        recipe.title = title.to_string();
+        recipe.description = description.to_string();
+        recipe.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        recipe.ingredients = ingredients.iter().enumerate()
+            .map(|(i, title)| Ingredient::new(&i.to_string(), 1, title, MeasurementUnit::PIECE))
+            .collect();
+        recipe.instructions = instructions.iter().map(|instruction| instruction.to_string()).collect();
+        recipe
+    }
+
+    #[test]
+    fn folds_diacritics_and_umlauts() {
+        assert_eq!(fold("Käse"), "kaese");
+        assert_eq!(fold("Kaese"), "kaese");
+        assert_eq!(fold("Ka\u{308}se"), "kaese");
+        assert_eq!(fold("BRÖTCHEN"), "broetchen");
+        assert_eq!(fold("Crème"), "creme");
+        assert_eq!(fold("Soße"), "sosse");
+    }
+
+    #[test]
+    fn keeps_words_that_only_look_like_umlauts() {
+        assert_eq!(fold("blue"), "blue");
+        assert_eq!(fold("Poet"), "poet");
+        assert_eq!(fold("Feuer"), "feuer");
+        assert!(score(recipe("Blu Cheese", "", &[], &[], &[]), &SearchQuery::parse("blue").unwrap()).is_none());
+        assert!(score(recipe("Pot Roast", "", &[], &[], &[]), &SearchQuery::parse("poet").unwrap()).is_none());
+        assert!(score(recipe("Flammkuchen", "vom Feuer", &[], &[], &[]), &SearchQuery::parse("fur").unwrap()).is_none());
+    }
+
+    #[test]
+    fn parses_words_and_phrases() {
+        let query = SearchQuery::parse("Käse \"grüne  Soße\" brot").unwrap();
+        assert_eq!(query.phrases, vec![
+            vec!["kaese".to_string()],
+            vec!["gruene".to_string(), "sosse".to_string()],
+            vec!["brot".to_string()],
+        ]);
+        assert_eq!(SearchQuery::parse("  \"\" , "), None);
+        assert_eq!(SearchQuery::parse("\"unclosed phrase").unwrap().phrases.len(), 1);
+    }
+
+    #[test]
+    fn matches_without_diacritics() {
+        assert!(score(recipe("Crème brûlée", "", &[], &[], &[]), &SearchQuery::parse("creme brulee").unwrap()).is_some());
+        let recipe = recipe("Käsebrötchen", "", &[], &["Bergkäse"], &[]);
+        assert!(score(recipe.clone(), &SearchQuery::parse("kaesebroetchen").unwrap()).is_some());
+        assert!(score(recipe.clone(), &SearchQuery::parse("KÄSEBRÖTCHEN").unwrap()).is_some());
+        assert!(score(recipe.clone(), &SearchQuery::parse("bergkaese").unwrap()).is_some());
+        assert!(score(recipe, &SearchQuery::parse("Gouda").unwrap()).is_none());
+    }
+
+    #[test]
+    fn every_part_has_to_match() {
+        let recipe = recipe("Kartoffelsalat", "mit Gurke", &[], &[], &[]);
+        assert!(score(recipe.clone(), &SearchQuery::parse("kartoffelsalat gurke").unwrap()).is_some());
+        assert!(score(recipe, &SearchQuery::parse("kartoffelsalat speck").unwrap()).is_none());
+    }
+
+    #[test]
+    fn phrases_match_consecutive_words() {
+        let recipe = recipe("Pasta", "Mit grüner Soße und Käse", &[], &[], &[]);
+        assert!(score(recipe.clone(), &SearchQuery::parse("\"gruener sosse\"").unwrap()).is_some());
+        assert!(score(recipe.clone(), &SearchQuery::parse("\"grüner so\"").unwrap()).is_some());
+        assert!(score(recipe.clone(), &SearchQuery::parse("\"soße grüner\"").unwrap()).is_none());
+        assert!(score(recipe, &SearchQuery::parse("\"grüner käse\"").unwrap()).is_none());
+    }
+
+    #[test]
+    fn ranks_by_field_and_whole_words() {
+        let query = SearchQuery::parse("käse").unwrap();
+        let title = score(recipe("Käse", "", &[], &[], &[]), &query).unwrap().score;
+        let tag = score(recipe("Brot", "", &["käse"], &[], &[]), &query).unwrap().score;
+        let instruction = score(recipe("Brot", "", &[], &[], &["Käse reiben"]), &query).unwrap().score;
+        let prefix = score(recipe("Käsekuchen", "", &[], &[], &[]), &query).unwrap().score;
+        assert!(title > tag);
+        assert!(tag > instruction);
+        assert!(title > prefix);
+    }
+
+    #[test]
+    fn highlights_matches() {
+        let hit = score(recipe("Käse & Brötchen", "", &["brötchen"], &["Roggenbrötchen"], &["Die Brötchen <b>halbieren</b>"]),
+                        &SearchQuery::parse("broetchen").unwrap()).unwrap();
+        assert_eq!(hit.highlights, vec![
+            Highlight { field: "title", snippet: "Käse &amp; <mark>Brötchen</mark>".to_string() },
+            Highlight { field: "tags", snippet: "<mark>brötchen</mark>".to_string() },
+            Highlight { field: "instructions", snippet: "Die <mark>Brötchen</mark> &lt;b&gt;halbieren&lt;/b&gt;".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn snippets_cut_long_text_around_the_first_match() {
+        let text = format!("{}Käse {}", "vorher ".repeat(30), "nachher ".repeat(30));
+        let start = text.find("Käse").unwrap();
+        let found = start..start + "Käse".len();
+        let snippet = snippet(&text, vec![found]);
+        assert!(snippet.starts_with("…vorher"));
+        assert!(snippet.ends_with("nachher…"));
+        assert!(snippet.contains("vorher <mark>Käse</mark> nachher"));
+        assert!(snippet.chars().count() < 200);
+    }
+
+    #[actix_rt::test]
+    async fn search_orders_by_score() {
+        let recipes = vec![
+            recipe("Brot", "", &[], &[], &["Mit Käse bestreuen"]),
+            recipe("Nudeln", "", &[], &[], &[]),
+            recipe("Käsespätzle", "", &["käse"], &["Käse"], &[]),
+        ];
+        let stream = stream::iter(recipes.into_iter().map(Ok)).boxed();
+        let hits = search(stream, &SearchQuery::parse("käse").unwrap()).await.unwrap();
+        let titles: Vec<&str> = hits.iter().map(|hit| hit.recipe.title.as_str()).collect();
+        assert_eq!(titles, vec!["Käsespätzle", "Brot"]);
+    }
+
+    #[actix_rt::test]
+    async fn search_leaves_out_unreadable_recipes() {
+        let unreadable = UnreadableRecipe { id: "5f7333360051027600b01a36".to_string(), error: "Error getting tag from document".to_string() };
+        let recipes = vec![
+            Err(DaoError::UnreadableRecipe(unreadable)),
+            Ok(recipe("Käsespätzle", "", &[], &[], &[])),
+        ];
+        let hits = search(stream::iter(recipes).boxed(), &SearchQuery::parse("käse").unwrap()).await.unwrap();
+        assert_eq!(hits.len(), 1);
+
+        let failing = stream::iter(vec![Err(DaoError::DatabaseError("gone".to_string()))]).boxed();
+        assert!(search(failing, &SearchQuery::parse("käse").unwrap()).await.is_err());
+    }
+}
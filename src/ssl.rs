@@ -3,20 +3,31 @@ use rustls::internal::pemfile::{certs, pkcs8_private_keys};
 use std::io::BufReader;
 use std::fs::File;
 
+use crate::config::{ConfigError, TlsConfig};
 
-pub fn init() -> ServerConfig {
+
+pub fn init(tls: &TlsConfig) -> Result<ServerConfig, ConfigError> {
 // Create configuration
     let mut config = ServerConfig::new(NoClientAuth::new());
 
 // Load key files
-    let cert_file = &mut BufReader::new(
-        File::open("localhost.crt").unwrap());
-    let key_file = &mut BufReader::new(
-        File::open("localhost.key").unwrap());
+    let cert_file = &mut BufReader::new(File::open(&tls.cert)
+        .map_err(|err| ConfigError::invalid("tls.cert", format!("could not open '{}': {}", tls.cert.display(), err)))?);
+    let key_file = &mut BufReader::new(File::open(&tls.key)
+        .map_err(|err| ConfigError::invalid("tls.key", format!("could not open '{}': {}", tls.key.display(), err)))?);
 
 // Parse the certificate and set it in the configuration
-    let cert_chain = certs(cert_file).unwrap();
-    let mut keys = pkcs8_private_keys(key_file).unwrap();
-    config.set_single_cert(cert_chain, keys.remove(0)).unwrap();
-    return config
+    let cert_chain = certs(cert_file)
+        .map_err(|_| ConfigError::invalid("tls.cert", format!("'{}' contains no PEM certificate", tls.cert.display())))?;
+    let mut keys = pkcs8_private_keys(key_file)
+        .map_err(|_| ConfigError::invalid("tls.key", format!("'{}' contains no PEM key", tls.key.display())))?;
+    if cert_chain.is_empty() {
+        return Err(ConfigError::invalid("tls.cert", format!("'{}' contains no PEM certificate", tls.cert.display())));
+    }
+    if keys.is_empty() {
+        return Err(ConfigError::invalid("tls.key", format!("'{}' contains no PKCS8 private key", tls.key.display())));
+    }
+    config.set_single_cert(cert_chain, keys.remove(0))
+        .map_err(|err| ConfigError::invalid("tls.key", format!("key does not match certificate: {}", err)))?;
+    return Ok(config);
 }
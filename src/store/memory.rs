@@ -0,0 +1,265 @@
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use bson::Bson;
+use bson::oid::ObjectId;
+use futures_util::stream::{self, StreamExt};
+
+use crate::filter::RecipeFilter;
+use crate::image_store::referenced_hash;
+use crate::model::gallery::{self, GalleryImage};
+use crate::model::recipe::Recipe;
+use crate::model::unit::Unit;
+use crate::pagination::Listing;
+use crate::store::{DaoError, ImageBase64String, RecipeStore, RecipeStream, RecipeSummary, now, Resource, revise};
+
+/// Keeps recipes in insertion order, like a collection without index would return them.
+#[derive(Default)]
+pub struct MemoryStore {
+    recipes: RwLock<Vec<Recipe>>,
+    units: RwLock<Vec<Unit>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_recipe<T, F: FnOnce(&mut Recipe) -> T>(&self, id: &ObjectId, f: F) -> Result<T, DaoError> {
+        let mut recipes = self.recipes.write()
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?;
+        match recipes.iter_mut().find(|recipe| &recipe._id == id) {
+            Some(recipe) => Ok(f(recipe)),
+            None => Err(DaoError::DocumentNotFound(Resource::Recipe))
+        }
+    }
+
+    fn read<T, F: FnOnce(&Vec<Recipe>) -> T>(&self, f: F) -> Result<T, DaoError> {
+        self.recipes.read()
+            .map(|recipes| f(&recipes))
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))
+    }
+}
+
+#[async_trait]
+impl RecipeStore for MemoryStore {
+    async fn insert_recipe(&self, mut recipe: Recipe) -> Result<Bson, DaoError> {
+        recipe._id = ObjectId::new();
+        let id = recipe._id.clone();
+        self.recipes.write()
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?
+            .push(recipe);
+        info!("Added recipe in memory. id={:?}", id);
+        Ok(Bson::ObjectId(id))
+    }
+
+    async fn update_recipe_ignore_image(&self, id: ObjectId, recipe: Recipe, expected_version: u32) -> Result<Recipe, DaoError> {
+        self.with_recipe(&id, |stored| {
+            let mut current = stored.clone();
+            current.image_base64 = None;
+            if stored.version != expected_version {
+                return Err(DaoError::VersionConflict(Box::new(current)));
+            }
+            let recipe = Recipe { created: stored.created, gallery: stored.gallery.clone(), ..revise(recipe, id.clone(), expected_version) };
+            *stored = Recipe { image_base64: stored.image_base64.take(), ..recipe.clone() };
+            Ok(recipe)
+        })?
+    }
+
+    async fn add_many_recipes(&self, recipes: Vec<Recipe>) -> Result<Bson, DaoError> {
+        let mut ids = vec![];
+        for recipe in recipes {
+            ids.push(self.insert_recipe(recipe).await?);
+        }
+        Ok(Bson::Array(ids))
+    }
+
+    async fn get_one_recipe_without_image(&self, id: ObjectId) -> Result<Recipe, DaoError> {
+        self.with_recipe(&id, |recipe| {
+            let mut recipe = recipe.clone();
+            recipe.image_base64 = None;
+            recipe
+        })
+    }
+
+    async fn get_one_recipe_image(&self, id: ObjectId) -> Result<ImageBase64String, DaoError> {
+        self.with_recipe(&id, |recipe| recipe.image_base64.clone())?
+            .ok_or(DaoError::DocumentNotFound(Resource::Image))
+    }
+
+    async fn update_gallery(&self, id: ObjectId, gallery: Vec<GalleryImage>, expected_version: u32) -> Result<Recipe, DaoError> {
+        self.with_recipe(&id, |stored| {
+            let mut current = stored.clone();
+            current.image_base64 = None;
+            if stored.version != expected_version {
+                return Err(DaoError::VersionConflict(Box::new(current)));
+            }
+            stored.image_base64 = gallery::cover(&gallery).map(|cover| cover.image.clone());
+            stored.gallery = gallery;
+            stored.version = expected_version.saturating_add(1);
+            stored.last_modified = now();
+            Ok(Recipe { image_base64: None, ..stored.clone() })
+        })?
+    }
+
+    async fn recipes_with_inline_image(&self) -> Result<Vec<ObjectId>, DaoError> {
+        self.read(|recipes| recipes.iter()
+            .filter(|recipe| recipe.image_base64.as_deref().is_some_and(|image| referenced_hash(image).is_none()))
+            .map(|recipe| recipe._id.clone())
+            .collect())
+    }
+
+    async fn replace_inline_image(&self, id: ObjectId, inline: &str, reference: &str) -> Result<bool, DaoError> {
+        self.with_recipe(&id, |recipe| {
+            let unchanged = recipe.image_base64.as_deref() == Some(inline);
+            if unchanged {
+                recipe.image_base64 = Some(reference.to_string());
+            }
+            unchanged
+        })
+    }
+
+    async fn delete_one_recipe(&self, id: ObjectId) -> Result<(), DaoError> {
+        let mut recipes = self.recipes.write()
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?;
+        match recipes.iter().position(|recipe| recipe._id == id) {
+            Some(index) => {
+                recipes.remove(index);
+                Ok(())
+            }
+            None => Err(DaoError::DocumentNotFound(Resource::Recipe))
+        }
+    }
+
+    async fn stream_many_recipes(&self, listing: &Listing, filter: &RecipeFilter) -> Result<RecipeStream, DaoError> {
+        let mut recipes: Vec<Recipe> = self.read(|recipes| recipes.iter()
+            .filter(|recipe| filter.matches(recipe))
+            .cloned()
+            .collect())?;
+
+        if let Some(sort) = &listing.sort {
+            recipes.sort_by(|a, b| sort.compare(a, b));
+            if let Some(after) = &listing.after {
+                recipes.retain(|recipe| sort.is_after(recipe, after));
+            }
+        }
+        let recipes = recipes.into_iter()
+            .skip(listing.skip)
+            .take(listing.limit.unwrap_or(usize::MAX))
+            .map(|mut recipe| {
+                recipe.image_base64 = None;
+                Ok(recipe)
+            });
+        Ok(stream::iter(recipes).boxed())
+    }
+
+    async fn summarize_recipes(&self, filter: &RecipeFilter) -> Result<RecipeSummary, DaoError> {
+        self.read(|recipes| recipes.iter()
+            .filter(|recipe| filter.matches(recipe))
+            .fold(RecipeSummary::default(), |summary, recipe| RecipeSummary {
+                count: summary.count + 1,
+                versions: summary.versions + recipe.version as u64,
+                last_modified: summary.last_modified.max(Some(recipe.last_modified)),
+            }))
+    }
+
+    async fn custom_units(&self) -> Result<Vec<Unit>, DaoError> {
+        self.units.read()
+            .map(|units| units.clone())
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))
+    }
+
+    async fn put_unit(&self, unit: Unit) -> Result<bool, DaoError> {
+        let mut units = self.units.write()
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?;
+        match units.iter_mut().find(|stored| stored.id == unit.id) {
+            Some(stored) => {
+                *stored = unit;
+                Ok(false)
+            }
+            None => {
+                units.push(unit);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn delete_unit(&self, id: &str) -> Result<(), DaoError> {
+        let mut units = self.units.write()
+            .map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))?;
+        match units.iter().position(|unit| unit.id.id() == id) {
+            Some(index) => {
+                units.remove(index);
+                Ok(())
+            }
+            None => Err(DaoError::DocumentNotFound(Resource::Unit))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod memory_store_tests {
+    use crate::store::memory::MemoryStore;
+    use crate::store::store_tests;
+
+    #[actix_rt::test]
+    async fn insert_and_get_recipe() {
+        store_tests::insert_and_get_recipe(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn update_recipe_ignores_image() {
+        store_tests::update_recipe_ignores_image(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn update_checks_version() {
+        store_tests::update_checks_version(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn update_keeps_created() {
+        store_tests::update_keeps_created(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn update_gallery_sets_image() {
+        store_tests::update_gallery_sets_image(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn delete_recipe() {
+        store_tests::delete_recipe(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn add_and_get_many_recipes() {
+        store_tests::add_and_get_many_recipes(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn get_paged_recipes() {
+        store_tests::get_paged_recipes(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn keeps_ingredients_tags_and_instructions_in_order() {
+        store_tests::keeps_recipe_content(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn filter_recipes() {
+        store_tests::filter_recipes(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn sort_and_follow_cursor() {
+        store_tests::sort_and_follow_cursor(&MemoryStore::new()).await;
+    }
+
+    #[actix_rt::test]
+    async fn put_and_delete_units() {
+        store_tests::put_and_delete_units(&MemoryStore::new()).await;
+    }
+}
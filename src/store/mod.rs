@@ -0,0 +1,497 @@
+use async_trait::async_trait;
+use bson::Bson;
+use bson::oid::ObjectId;
+use chrono::{DateTime, TimeZone, Utc};
+use futures_util::stream::{BoxStream, StreamExt};
+use serde::Serialize;
+
+use crate::filter::RecipeFilter;
+use crate::model::gallery::GalleryImage;
+use crate::model::recipe::Recipe;
+use crate::model::unit::{Unit, UnitCatalogue};
+use crate::pagination::Listing;
+
+pub mod memory;
+pub mod sqlite;
+
+pub type ImageBase64String = String;
+
+pub type RecipeStream = BoxStream<'static, Result<Recipe, DaoError>>;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DaoError {
+    DatabaseError(String),
+    DocumentNotFound(Resource),
+    RecipeFormatError(String),
+    /// the image store failed in a way retrying does not help, like a full disk
+    ImageStoreError(String),
+    /// the stored recipe has another version than the update expected, holds the stored recipe
+    VersionConflict(Box<Recipe>),
+    /// a stored document is no valid recipe, a listing can skip it and go on
+    UnreadableRecipe(UnreadableRecipe),
+}
+
+/// What was not found, the API answers with the matching problem.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Resource {
+    Recipe,
+    /// a recipe without image or a blob missing in the image store
+    Image,
+    Unit,
+}
+
+/// A stored recipe that cannot be read, `error` is the message of the `RecipeFormatError`.
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct UnreadableRecipe {
+    pub id: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Debug, Default, Clone, Eq, PartialEq)]
+pub struct ScanReport {
+    pub scanned: usize,
+    pub unreadable: Vec<UnreadableRecipe>,
+}
+
+/// What a listing knows before it reads the recipes. Every change of a matching recipe raises its
+/// version, an added one the number, a deleted one lowers it, so any change shows in the summary.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct RecipeSummary {
+    pub count: usize,
+    pub versions: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Storage operations the routes rely on. Every backend has to behave the same,
+/// `store_tests` contains the checks each implementation runs.
+#[async_trait]
+pub trait RecipeStore: Send + Sync {
+    /// ignores id, returns the id of the new recipe
+    async fn insert_recipe(&self, recipe: Recipe) -> Result<Bson, DaoError>;
+
+    /// Replaces the recipe when the stored version is `expected_version`, in one atomic step.
+    /// Version and last modified are set by `revise`, created and the gallery are kept. Returns the stored recipe without image.
+    async fn update_recipe_ignore_image(&self, id: ObjectId, recipe: Recipe, expected_version: u32) -> Result<Recipe, DaoError>;
+
+    /// ignores ids, returns an array of the new ids
+    async fn add_many_recipes(&self, recipes: Vec<Recipe>) -> Result<Bson, DaoError>;
+
+    async fn get_one_recipe_without_image(&self, id: ObjectId) -> Result<Recipe, DaoError>;
+
+    /// DocumentNotFound of the recipe when it does not exist, of the image when it has none
+    async fn get_one_recipe_image(&self, id: ObjectId) -> Result<ImageBase64String, DaoError>;
+
+    /// Replaces the gallery when the stored version is `expected_version`, in one atomic step.
+    /// The image becomes the one of the cover, version and last modified change like with
+    /// `update_recipe_ignore_image`. Returns the stored recipe without image.
+    async fn update_gallery(&self, id: ObjectId, gallery: Vec<GalleryImage>, expected_version: u32) -> Result<Recipe, DaoError>;
+
+    async fn delete_one_recipe(&self, id: ObjectId) -> Result<(), DaoError>;
+
+    /// recipes without image matching the filter, in the order of the listing.
+    /// Filter, order, cursor, skip and limit are applied by the backend, recipes are produced one by one.
+    async fn stream_many_recipes(&self, listing: &Listing, filter: &RecipeFilter) -> Result<RecipeStream, DaoError>;
+
+    /// number, summed versions and latest last modified of the recipes matching the filter
+    async fn summarize_recipes(&self, filter: &RecipeFilter) -> Result<RecipeSummary, DaoError>;
+
+    /// recipes whose image is still kept inline as base64 instead of a reference into the image store
+    async fn recipes_with_inline_image(&self) -> Result<Vec<ObjectId>, DaoError>;
+
+    /// Sets the image to `reference` only while it is still `inline`, last modified is kept.
+    /// False when the image changed in the meantime.
+    async fn replace_inline_image(&self, id: ObjectId, inline: &str, reference: &str) -> Result<bool, DaoError>;
+
+    /// the units added through `/api/v1/units`, in the order they were first added
+    async fn custom_units(&self) -> Result<Vec<Unit>, DaoError>;
+
+    /// Adds the unit or replaces the one with its id, true when it was added.
+    async fn put_unit(&self, unit: Unit) -> Result<bool, DaoError>;
+
+    /// DocumentNotFound of the unit when there is no custom unit with the id
+    async fn delete_unit(&self, id: &str) -> Result<(), DaoError>;
+
+    /// the builtin units and the custom ones
+    async fn unit_catalogue(&self) -> Result<UnitCatalogue, DaoError> {
+        Ok(UnitCatalogue::with_custom(self.custom_units().await?))
+    }
+
+    /// reads every stored recipe and reports the ones that cannot be read
+    async fn scan_recipes(&self) -> Result<ScanReport, DaoError> {
+        let mut recipes = self.stream_many_recipes(&Listing::default(), &RecipeFilter::default()).await?;
+        let mut report = ScanReport::default();
+        while let Some(recipe) = recipes.next().await {
+            report.scanned += 1;
+            match recipe {
+                Ok(_) => {}
+                Err(DaoError::UnreadableRecipe(unreadable)) => report.unreadable.push(unreadable),
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(report)
+    }
+
+    /// collects the stream, only meant for tests
+    #[cfg(test)]
+    async fn get_many_recipes(&self, listing: &Listing) -> Result<Vec<Recipe>, DaoError> {
+        self.get_filtered_recipes(listing, &RecipeFilter::default()).await
+    }
+
+    #[cfg(test)]
+    async fn get_filtered_recipes(&self, listing: &Listing, filter: &RecipeFilter) -> Result<Vec<Recipe>, DaoError> {
+        use futures_util::TryStreamExt;
+        self.stream_many_recipes(listing, filter).await?.try_collect().await
+    }
+}
+
+/// The recipe an update stores: the next version, modified now and without image.
+pub fn revise(mut recipe: Recipe, id: ObjectId, expected_version: u32) -> Recipe {
+    recipe._id = id;
+    recipe.version = expected_version.saturating_add(1);
+    recipe.last_modified = now();
+    recipe.image_base64 = None;
+    recipe
+}
+
+/// the current time in the millisecond precision every backend keeps
+pub fn now() -> DateTime<Utc> {
+    Utc.timestamp_millis(Utc::now().timestamp_millis())
+}
+
+
+#[cfg(test)]
+pub mod store_tests {
+    use bson::Bson;
+    use bson::oid::ObjectId;
+
+    use std::convert::TryFrom;
+
+    use actix_web::web::Query;
+    use chrono::Duration;
+
+    use crate::dao::dao_tests::{create_many_recipes_without_images, create_one_recipe_with_image, create_one_recipe_without_image};
+    use crate::filter::RecipeFilter;
+    use crate::model::difficulty::Difficulty;
+    use crate::model::gallery::GalleryImage;
+    use crate::model::ingredients::Ingredient;
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::quantity::Quantity;
+    use crate::model::recipe::Recipe;
+    use crate::model::unit::Unit;
+    use crate::pagination::{Listing, Pagination};
+    use crate::store::{DaoError, RecipeStore, RecipeSummary, Resource};
+
+    fn inserted_id(bson: Bson) -> ObjectId {
+        bson.as_object_id().unwrap().to_owned()
+    }
+
+    fn params(query: &str) -> Pagination {
+        Query::<Pagination>::from_query(query).unwrap().into_inner()
+    }
+
+    fn listing(query: &str) -> Listing {
+        params(query).listing().unwrap()
+    }
+
+    pub async fn insert_and_get_recipe(store: &dyn RecipeStore) {
+        let recipe = create_one_recipe_with_image();
+        let id = inserted_id(store.insert_recipe(recipe.clone()).await.unwrap());
+        assert_ne!(id, recipe._id);
+
+        let found = store.get_one_recipe_without_image(id.clone()).await.unwrap();
+        assert_eq!(found._id, id);
+        assert_eq!(found.image_base64, None);
+        assert_eq!(found.title, recipe.title);
+        assert_eq!(found.created, recipe.created);
+
+        let result = store.get_one_recipe_without_image(ObjectId::new()).await;
+        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
+    }
+
+    pub async fn keeps_recipe_content(store: &dyn RecipeStore) {
+        let mut recipe = create_one_recipe_with_image();
+        recipe.title = "Käsespätzle".to_string();
+        recipe.ingredients = vec![
+            Ingredient::new("1", 500, "Spätzle", MeasurementUnit::GRAMM),
+            Ingredient::new("0", 200, "Bergkäse", MeasurementUnit::GRAMM),
+            Ingredient::new("2", 2, "Zwiebeln", MeasurementUnit::PIECE),
+            Ingredient::new("3", Quantity::parse("1½").unwrap(), "Butter", MeasurementUnit::ESSLOEFFEL),
+            Ingredient::new("4", Quantity::parse("0.5").unwrap(), "Milch", MeasurementUnit::LITER),
+            Ingredient::new("5", Quantity::parse("1-2").unwrap(), "Knoblauch", MeasurementUnit::ZEHE),
+            Ingredient::new("6", Quantity::ToTaste, "Muskat", MeasurementUnit::PRISE),
+            Ingredient::new("7", Quantity::Unspecified, "Schnittlauch", MeasurementUnit::BUND),
+        ];
+        recipe.tags = vec!["vegetarisch".to_string(), "allgäu".to_string()];
+        recipe.instructions = vec!["Zwiebeln rösten".to_string(), "Schichten".to_string(), "Backen".to_string()];
+        let id = inserted_id(store.insert_recipe(recipe.clone()).await.unwrap());
+
+        let mut found = store.get_one_recipe_without_image(id.clone()).await.unwrap();
+        found._id = recipe._id.clone();
+        recipe.image_base64 = None;
+        assert_eq!(found, recipe);
+
+        recipe.tags = vec!["käse".to_string()];
+        recipe.ingredients.truncate(1);
+        let updated = store.update_recipe_ignore_image(id.clone(), recipe.clone(), recipe.version).await.unwrap();
+        let found = store.get_one_recipe_without_image(id).await.unwrap();
+        assert_eq!(found, updated);
+        recipe._id = found._id.clone();
+        recipe.version += 1;
+        recipe.last_modified = found.last_modified;
+        assert_eq!(found, recipe);
+    }
+
+    pub async fn update_recipe_ignores_image(store: &dyn RecipeStore) {
+        let mut recipe = create_one_recipe_with_image();
+        let id = inserted_id(store.insert_recipe(recipe.clone()).await.unwrap());
+        recipe.title = "new".to_string();
+        recipe.image_base64 = Some("new_image".to_string());
+
+        assert!(store.update_recipe_ignore_image(id.clone(), recipe.clone(), recipe.version).await.is_ok());
+        assert_eq!(store.get_one_recipe_without_image(id.clone()).await.unwrap().title, "new");
+        assert_eq!(store.get_one_recipe_image(id.clone()).await.unwrap(), "image");
+
+        let result = store.update_recipe_ignore_image(ObjectId::new(), recipe.clone(), recipe.version).await;
+        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
+    }
+
+    pub async fn update_checks_version(store: &dyn RecipeStore) {
+        let mut recipe = create_one_recipe_with_image();
+        recipe.version = 3;
+        recipe.last_modified = recipe.created;
+        let id = inserted_id(store.insert_recipe(recipe.clone()).await.unwrap());
+
+        recipe.title = "first".to_string();
+        let updated = store.update_recipe_ignore_image(id.clone(), recipe.clone(), 3).await.unwrap();
+        assert_eq!(updated.version, 4);
+        assert!(updated.last_modified > recipe.created);
+        assert_eq!(updated.image_base64, None);
+
+        recipe.title = "stale".to_string();
+        match store.update_recipe_ignore_image(id.clone(), recipe.clone(), 3).await {
+            Err(DaoError::VersionConflict(current)) => assert_eq!(*current, updated),
+            other => panic!("expected a version conflict, got {:?}", other)
+        }
+        let stored = store.get_one_recipe_without_image(id.clone()).await.unwrap();
+        assert_eq!(stored.title, "first");
+        assert_eq!(store.get_one_recipe_image(id.clone()).await.unwrap(), "image");
+
+        assert_eq!(store.update_recipe_ignore_image(id, recipe, 4).await.unwrap().version, 5);
+    }
+
+    pub async fn update_keeps_created(store: &dyn RecipeStore) {
+        let mut recipe = create_one_recipe_with_image();
+        let created = recipe.created;
+        recipe.last_modified = created;
+        let id = inserted_id(store.insert_recipe(recipe.clone()).await.unwrap());
+
+        recipe.created = created + Duration::days(3);
+        let updated = store.update_recipe_ignore_image(id.clone(), recipe.clone(), recipe.version).await.unwrap();
+        assert_eq!(updated.created, created);
+        assert_eq!(store.get_one_recipe_without_image(id.clone()).await.unwrap().created, created);
+
+        store.update_gallery(id.clone(), vec![gallery_image("1", true)], updated.version).await.unwrap();
+        let found = store.get_one_recipe_without_image(id).await.unwrap();
+        assert!(found.last_modified >= updated.last_modified);
+        assert!(found.last_modified > created);
+        assert_eq!(found.created, created);
+    }
+
+    fn gallery_image(id: &str, cover: bool) -> GalleryImage {
+        GalleryImage { id: id.to_string(), image: format!("sha256:{}", id), caption: "".to_string(), cover, step: None }
+    }
+
+    pub async fn update_gallery_sets_image(store: &dyn RecipeStore) {
+        let recipe = create_one_recipe_with_image();
+        let id = inserted_id(store.insert_recipe(recipe.clone()).await.unwrap());
+
+        let mut gallery = vec![gallery_image("1", false), gallery_image("2", true)];
+        gallery[0].caption = "Teig".to_string();
+        gallery[0].step = Some(0);
+        let updated = store.update_gallery(id.clone(), gallery.clone(), recipe.version).await.unwrap();
+        assert_eq!(updated.gallery, gallery);
+        assert_eq!(updated.version, recipe.version + 1);
+        assert_eq!(updated.image_base64, None);
+        assert_eq!(store.get_one_recipe_image(id.clone()).await.unwrap(), "sha256:2");
+        assert_eq!(store.get_one_recipe_without_image(id.clone()).await.unwrap(), updated);
+
+        let result = store.update_gallery(id.clone(), vec![], recipe.version).await;
+        assert!(matches!(result, Err(DaoError::VersionConflict(current)) if current.gallery == gallery));
+
+        let kept = store.update_recipe_ignore_image(id.clone(), recipe.clone(), updated.version).await.unwrap();
+        assert_eq!(kept.gallery, gallery);
+        assert_eq!(store.get_one_recipe_image(id.clone()).await.unwrap(), "sha256:2");
+
+        store.update_gallery(id.clone(), vec![], kept.version).await.unwrap();
+        assert_eq!(store.get_one_recipe_image(id.clone()).await.err().unwrap(), DaoError::DocumentNotFound(Resource::Image));
+
+        let result = store.update_gallery(ObjectId::new(), vec![], 1).await;
+        assert_eq!(result.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
+    }
+
+    pub async fn delete_recipe(store: &dyn RecipeStore) {
+        let id = inserted_id(store.insert_recipe(create_one_recipe_with_image()).await.unwrap());
+
+        assert!(store.delete_one_recipe(id.clone()).await.is_ok());
+        assert_eq!(store.get_one_recipe_image(id.clone()).await.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
+        assert_eq!(store.get_one_recipe_without_image(id.clone()).await.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
+        assert_eq!(store.delete_one_recipe(id).await.err().unwrap(), DaoError::DocumentNotFound(Resource::Recipe));
+    }
+
+    pub async fn add_and_get_many_recipes(store: &dyn RecipeStore) {
+        let recipes = create_many_recipes_without_images(50);
+        let ids = store.add_many_recipes(recipes.clone()).await.unwrap();
+        assert_eq!(ids.as_array().unwrap().len(), recipes.len());
+
+        let read = store.get_many_recipes(&Listing::default()).await.unwrap();
+        assert_eq!(read.len(), recipes.len());
+        assert_eq!(read.iter().map(|recipe| recipe.title.clone()).collect::<Vec<String>>(),
+                   recipes.iter().map(|recipe| recipe.title.clone()).collect::<Vec<String>>());
+
+        store.insert_recipe(create_one_recipe_with_image()).await.unwrap();
+        let read = store.get_many_recipes(&Listing::default()).await.unwrap();
+        assert!(read.iter().all(|recipe| recipe.image_base64.is_none()));
+    }
+
+    pub async fn get_paged_recipes(store: &dyn RecipeStore) {
+        let mut recipes = create_many_recipes_without_images(20);
+        for (i, recipe) in recipes.iter_mut().enumerate() {
+            recipe.created = recipe.created - chrono::Duration::minutes(i as i64);
+        }
+        store.add_many_recipes(recipes.clone()).await.unwrap();
+        store.insert_recipe(create_one_recipe_with_image()).await.unwrap();
+
+        let all: Vec<Recipe> = store.get_many_recipes(&listing("page=1&items=100&sorting=1")).await.unwrap();
+        assert_eq!(all.len(), 21);
+        assert!(all.iter().all(|recipe| recipe.image_base64.is_none()));
+        assert!(all.windows(2).all(|pair| pair[0].created <= pair[1].created));
+
+        let page = store.get_many_recipes(&listing("page=2&items=5&sorting=1")).await.unwrap();
+        assert_eq!(page, all[5..10].to_vec());
+
+        let page = store.get_many_recipes(&listing("page=1&items=3&sorting=-1")).await.unwrap();
+        let mut descending = all.clone();
+        descending.reverse();
+        assert_eq!(page, descending[..3].to_vec());
+
+        let page = store.get_many_recipes(&listing("page=8&items=5&sorting=1")).await.unwrap();
+        assert!(page.is_empty());
+    }
+
+    pub async fn filter_recipes(store: &dyn RecipeStore) {
+        let mut quick = create_one_recipe_with_image();
+        quick.title = "quick".to_string();
+        quick.cooking_time_in_minutes = 10;
+        quick.tags = vec!["vegan".to_string(), "schnell".to_string()];
+        quick.ingredients = vec![Ingredient::new("0", 200, "Räuchertofu", MeasurementUnit::GRAMM)];
+
+        let mut slow = create_one_recipe_without_image();
+        slow.title = "slow".to_string();
+        slow.difficulty = Difficulty::Hard;
+        slow.cooking_time_in_minutes = 90;
+        slow.tags = vec!["vegan".to_string()];
+        slow.created = slow.created - Duration::days(2);
+        slow.last_modified = slow.last_modified - Duration::days(2);
+        slow.ingredients = vec![Ingredient::new("0", 1, "Bergkäse (gerieben)", MeasurementUnit::PIECE)];
+
+        let mut plain = create_one_recipe_without_image();
+        plain.title = "plain".to_string();
+        plain.difficulty = Difficulty::Medium;
+        plain.cooking_time_in_minutes = 30;
+
+        store.add_many_recipes(vec![quick, slow.clone(), plain]).await.unwrap();
+
+        let titles = |query: String| async move {
+            let params = params(&query);
+            let filter = RecipeFilter::try_from(&params).unwrap();
+            let titles = store.get_filtered_recipes(&params.listing().unwrap(), &filter).await.unwrap()
+                .into_iter().map(|recipe| recipe.title).collect::<Vec<String>>();
+            if !params.is_fully_set() {
+                assert_eq!(store.summarize_recipes(&filter).await.unwrap().count, titles.len());
+            }
+            titles
+        };
+
+        assert_eq!(titles("".to_string()).await, vec!["quick", "slow", "plain"]);
+        assert_eq!(titles("difficulty=Hard,Medium".to_string()).await, vec!["slow", "plain"]);
+        assert_eq!(titles("tags=vegan,schnell".to_string()).await, vec!["quick"]);
+        assert_eq!(titles("tags=schnell,vegan&tagMatch=any".to_string()).await, vec!["quick", "slow"]);
+        assert_eq!(titles("minCookingTime=10&maxCookingTime=30".to_string()).await, vec!["quick", "plain"]);
+        assert_eq!(titles("minCookingTime=31".to_string()).await, vec!["slow"]);
+        assert_eq!(titles(format!("createdAfter={}", slow.created.to_rfc3339())).await, vec!["quick", "plain"]);
+        assert_eq!(titles(format!("modifiedSince={}", slow.last_modified.to_rfc3339())).await, vec!["quick", "slow", "plain"]);
+        assert_eq!(titles("hasImage=true".to_string()).await, vec!["quick"]);
+        assert_eq!(titles("hasImage=false".to_string()).await, vec!["slow", "plain"]);
+        assert_eq!(titles("ingredient=K%C3%A4se%20(".to_string()).await, vec!["slow"]);
+        assert_eq!(titles("ingredient=tofu&tags=vegan".to_string()).await, vec!["quick"]);
+        assert_eq!(titles("tags=vegan&page=1&items=1&sorting=1".to_string()).await, vec!["slow"]);
+        assert_eq!(titles("tags=vegan&page=2&items=1&sorting=1".to_string()).await, vec!["quick"]);
+
+        let summary = |query: &str| {
+            let filter = RecipeFilter::try_from(&params(query)).unwrap();
+            async move { store.summarize_recipes(&filter).await.unwrap() }
+        };
+        assert_eq!(summary("minCookingTime=31").await, RecipeSummary { count: 1, versions: slow.version as u64, last_modified: Some(slow.last_modified) });
+        assert_eq!(summary("").await.versions, 3 * slow.version as u64);
+        assert!(summary("").await.last_modified > Some(slow.last_modified));
+        assert_eq!(summary("minCookingTime=1000").await, RecipeSummary::default());
+    }
+
+    pub async fn sort_and_follow_cursor(store: &dyn RecipeStore) {
+        let mut recipes = create_many_recipes_without_images(9);
+        for (i, recipe) in recipes.iter_mut().enumerate() {
+            recipe.title = ["b", "a", "c"][i % 3].to_string();
+            recipe.cooking_time_in_minutes = (i % 2) as u32;
+            recipe.last_modified = recipe.last_modified - Duration::minutes((i % 4) as i64);
+        }
+        store.add_many_recipes(recipes).await.unwrap();
+
+        let sort = "title,-lastModified,cookingTimeInMinutes";
+        let all = store.get_many_recipes(&listing(&format!("sort={}", sort))).await.unwrap();
+        assert_eq!(all.len(), 9);
+        let order = listing(&format!("sort={}", sort)).sort.unwrap();
+        assert!(all.windows(2).all(|pair| order.compare(&pair[0], &pair[1]) == std::cmp::Ordering::Less));
+        assert_eq!(store.get_many_recipes(&listing(&format!("sort={}&page=2&items=4", sort))).await.unwrap(), all[4..8].to_vec());
+
+        let mut followed: Vec<Recipe> = vec![];
+        let mut cursor = String::new();
+        loop {
+            let page = store.get_many_recipes(&listing(&format!("sort={}&items=2&cursor={}", sort, cursor))).await.unwrap();
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.len() <= 2);
+            cursor = order.cursor(page.last().unwrap()).encode(&order);
+            followed.extend(page);
+
+            // recipes inserted before the cursor must neither shift nor repeat the following pages
+            let mut early = create_one_recipe_without_image();
+            early.title = "a".to_string();
+            early.last_modified = early.last_modified + Duration::days(1);
+            store.insert_recipe(early).await.unwrap();
+        }
+        assert_eq!(followed, all);
+    }
+
+    pub async fn put_and_delete_units(store: &dyn RecipeStore) {
+        let unit = |id: &str, factor: f64| -> Unit {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "names": {"de": id},
+                "abbreviations": ["x"],
+                "dimension": "volume",
+                "factor": factor,
+            })).unwrap()
+        };
+        assert_eq!(store.custom_units().await.unwrap(), vec![]);
+        assert!(store.put_unit(unit("Eimer", 10000.0)).await.unwrap());
+        assert!(store.put_unit(unit("Schuss", 20.0)).await.unwrap());
+        assert!(!store.put_unit(unit("Eimer", 12000.0)).await.unwrap());
+        assert_eq!(store.custom_units().await.unwrap(), vec![unit("Eimer", 12000.0), unit("Schuss", 20.0)]);
+        assert_eq!(store.unit_catalogue().await.unwrap().get("Schuss"), Some(&unit("Schuss", 20.0)));
+
+        store.delete_unit("Eimer").await.unwrap();
+        assert_eq!(store.delete_unit("Eimer").await, Err(DaoError::DocumentNotFound(Resource::Unit)));
+        assert_eq!(store.custom_units().await.unwrap(), vec![unit("Schuss", 20.0)]);
+    }
+}
@@ -0,0 +1,869 @@
+use std::convert::TryFrom;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use actix_web::error::BlockingError;
+use actix_web::web;
+use async_trait::async_trait;
+use bson::Bson;
+use bson::oid::ObjectId;
+use chrono::{DateTime, TimeZone, Utc};
+use futures_channel::mpsc;
+use futures_util::SinkExt;
+use futures_util::stream::StreamExt;
+use rusqlite::{Connection, OptionalExtension, params, Row, Transaction};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value;
+
+use crate::filter::{contains_ignore_case, RecipeFilter, TagMatch};
+use crate::model::difficulty::Difficulty;
+use crate::model::gallery::{self, GalleryImage};
+use crate::model::ingredients::Ingredient;
+use crate::model::measurement_unit::MeasurementUnit;
+use crate::model::quantity::Quantity;
+use crate::model::recipe::{Recipe, RecipeFormatError};
+use crate::model::unit::Unit;
+use crate::pagination::{Listing, Sort, SortField, SortValue};
+use crate::store::{DaoError, ImageBase64String, RecipeStore, RecipeStream, RecipeSummary, now, Resource, revise, UnreadableRecipe};
+
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+
+    CREATE TABLE IF NOT EXISTS recipes (
+        id TEXT PRIMARY KEY NOT NULL,
+        cooking_time_in_minutes INTEGER NOT NULL,
+        created INTEGER NOT NULL,
+        last_modified INTEGER NOT NULL,
+        version INTEGER NOT NULL,
+        difficulty TEXT NOT NULL,
+        description TEXT NOT NULL,
+        title TEXT NOT NULL,
+        default_servings INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS recipes_created ON recipes (created);
+
+    CREATE TABLE IF NOT EXISTS tags (
+        recipe_id TEXT NOT NULL REFERENCES recipes (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (recipe_id, position)
+    );
+    CREATE INDEX IF NOT EXISTS tags_tag ON tags (tag);
+
+    CREATE TABLE IF NOT EXISTS instructions (
+        recipe_id TEXT NOT NULL REFERENCES recipes (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        instruction TEXT NOT NULL,
+        PRIMARY KEY (recipe_id, position)
+    );
+
+    CREATE TABLE IF NOT EXISTS images (
+        recipe_id TEXT PRIMARY KEY NOT NULL REFERENCES recipes (id) ON DELETE CASCADE,
+        image TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS gallery (
+        recipe_id TEXT NOT NULL REFERENCES recipes (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        id TEXT NOT NULL,
+        image TEXT NOT NULL,
+        caption TEXT NOT NULL,
+        cover INTEGER NOT NULL,
+        step INTEGER,
+        PRIMARY KEY (recipe_id, position)
+    );
+
+    CREATE TABLE IF NOT EXISTS units (
+        id TEXT PRIMARY KEY NOT NULL,
+        definition TEXT NOT NULL
+    );
+";
+
+/// created by `upgrade_ingredients`, which first moves a table of an older version aside
+const INGREDIENTS: &str = "
+    CREATE TABLE IF NOT EXISTS ingredients (
+        recipe_id TEXT NOT NULL REFERENCES recipes (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        id TEXT NOT NULL,
+        quantity TEXT NOT NULL,
+        title TEXT NOT NULL,
+        measurement_unit TEXT NOT NULL,
+        PRIMARY KEY (recipe_id, position)
+    );
+";
+
+/// how many recipes a listing reads ahead of the client
+const STREAM_BUFFER: usize = 8;
+
+const SELECT_RECIPE: &str = "SELECT id, cooking_time_in_minutes, created, last_modified, version, \
+    difficulty, description, title, default_servings FROM recipes";
+
+/// Embedded backend for installs without MongoDB. Ingredients, tags, instructions and the gallery
+/// live in their own tables, images in a separate table so listing never reads them.
+#[derive(Clone)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, DaoError> {
+        let connection = Connection::open(path).map_err(DaoError::from)?;
+        info!("Opened sqlite database at {}", path.display());
+        SqliteStore::init(connection)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, DaoError> {
+        SqliteStore::init(Connection::open_in_memory().map_err(DaoError::from)?)
+    }
+
+    /// runs a statement directly, for tests that need rows the store would never write
+    #[cfg(test)]
+    pub fn execute(&self, sql: &str) -> Result<usize, DaoError> {
+        self.connection.lock().unwrap().execute(sql, params![]).map_err(DaoError::from)
+    }
+
+    fn init(mut connection: Connection) -> Result<Self, DaoError> {
+        connection.execute_batch(SCHEMA).map_err(DaoError::from)?;
+        upgrade_ingredients(&mut connection)?;
+        register_functions(&connection).map_err(DaoError::from)?;
+        Ok(Self { connection: Arc::new(Mutex::new(connection)) })
+    }
+
+    /// runs `f` on the blocking thread pool, sqlite calls must not block the workers
+    async fn run<T, F>(&self, f: F) -> Result<T, DaoError>
+        where F: FnOnce(&mut Connection) -> Result<T, DaoError> + Send + 'static,
+              T: Send + 'static {
+        let connection = self.connection.clone();
+        web::block(move || f(&mut *lock(&connection)?)).await.map_err(blocking_error)
+    }
+}
+
+/// Sends the recipes the query selects with their children, stops when the listing was dropped.
+fn send_rows(connection: &Mutex<Connection>, query: &str, values: &[Value], mut sender: mpsc::Sender<Result<Recipe, DaoError>>) -> Result<(), DaoError> {
+    let connection = lock(connection)?;
+    let mut statement = connection.prepare(query)?;
+    let mut rows = statement.query(values)?;
+    while let Some(row) = rows.next()? {
+        let recipe = match read_recipe_row(row)? {
+            Ok(mut recipe) => read_children(&connection, &mut recipe).map(|_| recipe),
+            Err(unreadable) => Err(DaoError::UnreadableRecipe(unreadable))
+        };
+        if futures_executor::block_on(sender.send(recipe)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn lock(connection: &Mutex<Connection>) -> Result<MutexGuard<'_, Connection>, DaoError> {
+    connection.lock().map_err(|err| DaoError::DatabaseError(format!("{:#?}", err)))
+}
+
+fn blocking_error(err: BlockingError<DaoError>) -> DaoError {
+    match err {
+        BlockingError::Error(err) => err,
+        BlockingError::Canceled => DaoError::DatabaseError("sqlite operation was canceled".to_string())
+    }
+}
+
+#[async_trait]
+impl RecipeStore for SqliteStore {
+    async fn insert_recipe(&self, recipe: Recipe) -> Result<Bson, DaoError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let id = insert_recipe(&transaction, &recipe)?;
+            transaction.commit()?;
+            info!("Added recipe in sqlite. id={:?}", id);
+            Ok(Bson::ObjectId(id))
+        }).await
+    }
+
+    async fn update_recipe_ignore_image(&self, id: ObjectId, recipe: Recipe, expected_version: u32) -> Result<Recipe, DaoError> {
+        let recipe = revise(recipe, id.clone(), expected_version);
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let updated = transaction.execute(
+                "UPDATE recipes SET cooking_time_in_minutes = ?2, last_modified = ?3, version = ?4, \
+                 difficulty = ?5, description = ?6, title = ?7, default_servings = ?8 WHERE id = ?1 AND version = ?9",
+                params![id.to_hex(), recipe.cooking_time_in_minutes, recipe.last_modified.timestamp_millis(),
+                        recipe.version, recipe.difficulty.to_string(), recipe.description, recipe.title,
+                        recipe.default_servings, expected_version])?;
+            if updated == 0 {
+                let current = read_recipe(&transaction, &id)?;
+                info!("Not Updated recipe, version {} expected but found {} id={:#?}", expected_version, current.version, &id);
+                return Err(DaoError::VersionConflict(Box::new(current)));
+            }
+            delete_children(&transaction, &id)?;
+            insert_children(&transaction, &id, &recipe)?;
+            let recipe = read_recipe(&transaction, &id)?;
+            transaction.commit()?;
+            info!("Updated recipe in sqlite with id={:#?}", &id);
+            Ok(recipe)
+        }).await
+    }
+
+    async fn add_many_recipes(&self, recipes: Vec<Recipe>) -> Result<Bson, DaoError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let ids = recipes.iter()
+                .map(|recipe| insert_recipe(&transaction, recipe).map(Bson::ObjectId))
+                .collect::<Result<Vec<Bson>, DaoError>>()?;
+            transaction.commit()?;
+            info!("Added multiple recipes in sqlite. ids={:#?}", ids);
+            Ok(Bson::Array(ids))
+        }).await
+    }
+
+    async fn get_one_recipe_without_image(&self, id: ObjectId) -> Result<Recipe, DaoError> {
+        self.run(move |connection| read_recipe(connection, &id)).await
+    }
+
+    async fn get_one_recipe_image(&self, id: ObjectId) -> Result<ImageBase64String, DaoError> {
+        self.run(move |connection| {
+            let image = connection
+                .query_row("SELECT image FROM images WHERE recipe_id = ?1", params![id.to_hex()], |row| row.get(0))
+                .optional()?;
+            image.ok_or_else(|| {
+                error!("Image not found id={:#?}", id);
+                match exists(connection, &id) {
+                    Ok(true) => DaoError::DocumentNotFound(Resource::Image),
+                    Ok(false) => DaoError::DocumentNotFound(Resource::Recipe),
+                    Err(err) => err
+                }
+            })
+        }).await
+    }
+
+    async fn update_gallery(&self, id: ObjectId, gallery: Vec<GalleryImage>, expected_version: u32) -> Result<Recipe, DaoError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let updated = transaction.execute(
+                "UPDATE recipes SET last_modified = ?2, version = ?3 WHERE id = ?1 AND version = ?4",
+                params![id.to_hex(), now().timestamp_millis(), expected_version.saturating_add(1), expected_version])?;
+            if updated == 0 {
+                let current = read_recipe(&transaction, &id)?;
+                info!("Not Updated gallery, version {} expected but found {} id={:#?}", expected_version, current.version, &id);
+                return Err(DaoError::VersionConflict(Box::new(current)));
+            }
+            transaction.execute("DELETE FROM gallery WHERE recipe_id = ?1", params![id.to_hex()])?;
+            insert_gallery(&transaction, &id, &gallery)?;
+            set_image(&transaction, &id, gallery::cover(&gallery).map(|cover| cover.image.as_str()))?;
+            let recipe = read_recipe(&transaction, &id)?;
+            transaction.commit()?;
+            info!("Updated recipe gallery in sqlite with id={:#?}", &id);
+            Ok(recipe)
+        }).await
+    }
+
+    async fn recipes_with_inline_image(&self) -> Result<Vec<ObjectId>, DaoError> {
+        self.run(|connection| {
+            let mut statement = connection.prepare("SELECT recipe_id FROM images WHERE image NOT LIKE 'sha256:%'")?;
+            let ids = statement
+                .query_map(params![], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+            Ok(ids.iter().filter_map(|id| ObjectId::with_string(id).ok()).collect())
+        }).await
+    }
+
+    async fn replace_inline_image(&self, id: ObjectId, inline: &str, reference: &str) -> Result<bool, DaoError> {
+        let (inline, reference) = (inline.to_string(), reference.to_string());
+        self.run(move |connection| {
+            let changed = connection.execute("UPDATE images SET image = ?3 WHERE recipe_id = ?1 AND image = ?2",
+                                             params![id.to_hex(), inline, reference])?;
+            Ok(changed == 1)
+        }).await
+    }
+
+    async fn delete_one_recipe(&self, id: ObjectId) -> Result<(), DaoError> {
+        self.run(move |connection| {
+            match connection.execute("DELETE FROM recipes WHERE id = ?1", params![id.to_hex()])? {
+                0 => {
+                    error!("Deleted no recipe from sqlite. id={:#?}", &id);
+                    Err(DaoError::DocumentNotFound(Resource::Recipe))
+                }
+                _ => {
+                    info!("Deleted one recipe from sqlite. id={:#?}", &id);
+                    Ok(())
+                }
+            }
+        }).await
+    }
+
+    async fn stream_many_recipes(&self, listing: &Listing, filter: &RecipeFilter) -> Result<RecipeStream, DaoError> {
+        let (condition, values) = where_clause(filter, listing);
+        let order = match &listing.sort {
+            Some(sort) => order_clause(sort),
+            None => "rowid".to_string()
+        };
+        let limit = match listing.limit {
+            Some(limit) => limit as i64,
+            None => -1
+        };
+        let query = format!("{}{} ORDER BY {} LIMIT {} OFFSET {}", SELECT_RECIPE, condition, order, limit, listing.skip);
+
+        // the rows are stepped one at a time while the listing is read, the connection stays locked
+        // until the last one was sent or the listing was dropped
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let mut errors = sender.clone();
+        let connection = self.connection.clone();
+        actix_rt::spawn(async move {
+            if let Err(err) = web::block(move || send_rows(&connection, &query, &values, sender)).await {
+                let _ = errors.send(Err(blocking_error(err))).await;
+            }
+        });
+        Ok(receiver.boxed())
+    }
+
+    async fn summarize_recipes(&self, filter: &RecipeFilter) -> Result<RecipeSummary, DaoError> {
+        let (condition, values) = where_clause(filter, &Listing::default());
+        let query = format!("SELECT COUNT(*), COALESCE(SUM(version), 0), MAX(last_modified) FROM recipes{}", condition);
+        self.run(move |connection| {
+            let (count, versions, last_modified): (i64, i64, Option<i64>) = connection.query_row(&query, &values,
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            Ok(RecipeSummary { count: count as usize, versions: versions as u64, last_modified: last_modified.map(millis_to_datetime) })
+        }).await
+    }
+
+    async fn custom_units(&self) -> Result<Vec<Unit>, DaoError> {
+        self.run(|connection| {
+            let mut statement = connection.prepare("SELECT definition FROM units ORDER BY rowid")?;
+            let definitions = statement
+                .query_map(params![], |row| row.get(0))?
+                .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+            definitions.iter()
+                .map(|definition| serde_json::from_str(definition)
+                    .map_err(|err| DaoError::RecipeFormatError(format!("unit {}, {}", definition, err))))
+                .collect()
+        }).await
+    }
+
+    async fn put_unit(&self, unit: Unit) -> Result<bool, DaoError> {
+        let definition = serde_json::to_string(&unit)
+            .map_err(|err| DaoError::RecipeFormatError(err.to_string()))?;
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let exists = transaction
+                .query_row("SELECT 1 FROM units WHERE id = ?1", params![unit.id.id()], |_| Ok(()))
+                .optional()?
+                .is_some();
+            // an update keeps the rowid and so the position of the unit
+            transaction.execute("INSERT INTO units (id, definition) VALUES (?1, ?2) \
+                                 ON CONFLICT (id) DO UPDATE SET definition = excluded.definition",
+                                params![unit.id.id(), definition])?;
+            transaction.commit()?;
+            info!("Stored unit in sqlite. id={}", unit.id);
+            Ok(!exists)
+        }).await
+    }
+
+    async fn delete_unit(&self, id: &str) -> Result<(), DaoError> {
+        let id = id.to_string();
+        self.run(move |connection| {
+            match connection.execute("DELETE FROM units WHERE id = ?1", params![id])? {
+                0 => Err(DaoError::DocumentNotFound(Resource::Unit)),
+                _ => {
+                    info!("Deleted unit from sqlite. id={}", id);
+                    Ok(())
+                }
+            }
+        }).await
+    }
+}
+
+
+fn column(field: SortField) -> &'static str {
+    match field {
+        SortField::Title => "title",
+        SortField::Created => "created",
+        SortField::LastModified => "last_modified",
+        SortField::CookingTime => "cooking_time_in_minutes",
+        SortField::DefaultServings => "default_servings",
+    }
+}
+
+fn order_clause(sort: &Sort) -> String {
+    let mut order: Vec<String> = sort.keys.iter()
+        .map(|key| format!("{} {}", column(key.field), if key.descending { "DESC" } else { "ASC" }))
+        .collect();
+    order.push("id ASC".to_string());
+    order.join(", ")
+}
+
+fn sort_value(value: &SortValue) -> Value {
+    match value {
+        SortValue::Integer(value) => Value::Integer(*value),
+        SortValue::Text(value) => Value::Text(value.clone()),
+    }
+}
+
+/// the same selection as `RecipeFilter::matches` and `Sort::is_after`, empty without filter and cursor
+fn where_clause(filter: &RecipeFilter, listing: &Listing) -> (String, Vec<Value>) {
+    let mut conditions: Vec<String> = vec![];
+    let mut values: Vec<Value> = vec![];
+    let placeholders = |amount: usize| vec!["?"; amount].join(", ");
+
+    if !filter.difficulties.is_empty() {
+        conditions.push(format!("difficulty IN ({})", placeholders(filter.difficulties.len())));
+        values.extend(filter.difficulties.iter().map(|difficulty| Value::Text(difficulty.to_string())));
+    }
+    if !filter.tags.is_empty() {
+        let has_tag = "EXISTS (SELECT 1 FROM tags WHERE tags.recipe_id = recipes.id AND tag";
+        match filter.tag_match {
+            TagMatch::All => conditions.extend(filter.tags.iter().map(|_| format!("{} = ?)", has_tag))),
+            TagMatch::Any => conditions.push(format!("{} IN ({}))", has_tag, placeholders(filter.tags.len()))),
+        }
+        values.extend(filter.tags.iter().cloned().map(Value::Text));
+    }
+    if let Some(min) = filter.min_cooking_time {
+        conditions.push("cooking_time_in_minutes >= ?".to_string());
+        values.push(Value::Integer(min.into()));
+    }
+    if let Some(max) = filter.max_cooking_time {
+        conditions.push("cooking_time_in_minutes <= ?".to_string());
+        values.push(Value::Integer(max.into()));
+    }
+    if let Some(date) = filter.created_after {
+        conditions.push("created > ?".to_string());
+        values.push(Value::Integer(date.timestamp_millis()));
+    }
+    if let Some(date) = filter.modified_since {
+        conditions.push("last_modified >= ?".to_string());
+        values.push(Value::Integer(date.timestamp_millis()));
+    }
+    if let Some(has_image) = filter.has_image {
+        let negation = if has_image { "" } else { "NOT " };
+        conditions.push(format!("{}EXISTS (SELECT 1 FROM images WHERE images.recipe_id = recipes.id)", negation));
+    }
+    if let Some(ingredient) = &filter.ingredient {
+        conditions.push("EXISTS (SELECT 1 FROM ingredients WHERE ingredients.recipe_id = recipes.id \
+                         AND contains_ignore_case(title, ?))".to_string());
+        values.push(Value::Text(ingredient.clone()));
+    }
+    if let (Some(sort), Some(after)) = (&listing.sort, &listing.after) {
+        // (a > ?) OR (a = ? AND b < ?) OR ... OR (a = ? AND b = ? AND id > ?)
+        let mut alternatives: Vec<String> = vec![];
+        for (i, key) in sort.keys.iter().enumerate() {
+            let mut parts: Vec<String> = sort.keys[..i].iter().map(|key| format!("{} = ?", column(key.field))).collect();
+            parts.push(format!("{} {} ?", column(key.field), if key.descending { "<" } else { ">" }));
+            alternatives.push(format!("({})", parts.join(" AND ")));
+            values.extend(after.values[..=i].iter().map(sort_value));
+        }
+        let mut parts: Vec<String> = sort.keys.iter().map(|key| format!("{} = ?", column(key.field))).collect();
+        parts.push("id > ?".to_string());
+        alternatives.push(format!("({})", parts.join(" AND ")));
+        values.extend(after.values.iter().map(sort_value));
+        values.push(Value::Text(after.id.to_hex()));
+        conditions.push(format!("({})", alternatives.join(" OR ")));
+    }
+
+    if conditions.is_empty() {
+        return (String::new(), values);
+    }
+    return (format!(" WHERE {}", conditions.join(" AND ")), values);
+}
+
+/// Databases from before quantities keep the amount of an ingredient in an INTEGER column, which
+/// later held quantities as JSON text too. The table is recreated with a TEXT column for the JSON,
+/// whole and decimal amounts are valid JSON as they are.
+fn upgrade_ingredients(connection: &mut Connection) -> Result<(), DaoError> {
+    let transaction = connection.transaction()?;
+    let has_amount = transaction
+        .prepare("SELECT 1 FROM pragma_table_info('ingredients') WHERE name = 'amount'")?
+        .exists(params![])?;
+    if has_amount {
+        transaction.execute_batch("ALTER TABLE ingredients RENAME TO ingredients_amount")?;
+    }
+    transaction.execute_batch(INGREDIENTS)?;
+    if has_amount {
+        let moved = transaction.execute(
+            "INSERT INTO ingredients (recipe_id, position, id, quantity, title, measurement_unit) \
+             SELECT recipe_id, position, id, CAST(amount AS TEXT), title, measurement_unit FROM ingredients_amount",
+            params![])?;
+        transaction.execute_batch("DROP TABLE ingredients_amount")?;
+        info!("Moved {} ingredient amounts to the quantity column", moved);
+    }
+    transaction.commit().map_err(DaoError::from)
+}
+
+/// sqlite only folds the case of ASCII letters, ingredient titles are German
+fn register_functions(connection: &Connection) -> rusqlite::Result<()> {
+    connection.create_scalar_function(
+        "contains_ignore_case", 2, FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |context| {
+            let text: String = context.get(0)?;
+            let part: String = context.get(1)?;
+            Ok(contains_ignore_case(&text, &part))
+        })
+}
+
+impl From<rusqlite::Error> for DaoError {
+    fn from(error: rusqlite::Error) -> Self {
+        DaoError::DatabaseError(format!("{:#?}", error))
+    }
+}
+
+/// ignores the id of the recipe, like the mongo store does
+fn insert_recipe(transaction: &Transaction, recipe: &Recipe) -> Result<ObjectId, DaoError> {
+    let id = ObjectId::new();
+    transaction.execute(
+        "INSERT INTO recipes (id, cooking_time_in_minutes, created, last_modified, version, difficulty, \
+         description, title, default_servings) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![id.to_hex(), recipe.cooking_time_in_minutes, recipe.created.timestamp_millis(),
+                recipe.last_modified.timestamp_millis(), recipe.version, recipe.difficulty.to_string(),
+                recipe.description, recipe.title, recipe.default_servings])?;
+    insert_children(transaction, &id, recipe)?;
+    insert_gallery(transaction, &id, &recipe.gallery)?;
+    if let Some(image) = &recipe.image_base64 {
+        transaction.execute("INSERT INTO images (recipe_id, image) VALUES (?1, ?2)", params![id.to_hex(), image])?;
+    }
+    Ok(id)
+}
+
+/// the gallery is no child `update_recipe_ignore_image` replaces
+fn insert_gallery(transaction: &Transaction, id: &ObjectId, gallery: &[GalleryImage]) -> Result<(), DaoError> {
+    for (position, image) in gallery.iter().enumerate() {
+        transaction.execute(
+            "INSERT INTO gallery (recipe_id, position, id, image, caption, cover, step) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![id.to_hex(), position as i64, image.id, image.image, image.caption, image.cover, image.step])?;
+    }
+    Ok(())
+}
+
+/// None removes the image
+fn set_image(transaction: &Transaction, id: &ObjectId, image: Option<&str>) -> Result<(), DaoError> {
+    match image {
+        Some(image) => transaction.execute(
+            "INSERT INTO images (recipe_id, image) VALUES (?1, ?2) \
+             ON CONFLICT (recipe_id) DO UPDATE SET image = excluded.image",
+            params![id.to_hex(), image])?,
+        None => transaction.execute("DELETE FROM images WHERE recipe_id = ?1", params![id.to_hex()])?
+    };
+    Ok(())
+}
+
+fn insert_children(transaction: &Transaction, id: &ObjectId, recipe: &Recipe) -> Result<(), DaoError> {
+    let id = id.to_hex();
+    for (position, ingredient) in recipe.ingredients.iter().enumerate() {
+        transaction.execute(
+            "INSERT INTO ingredients (recipe_id, position, id, quantity, title, measurement_unit) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![id, position as i64, ingredient.id, write_quantity(&ingredient.amount)?, ingredient.title,
+                    ingredient.measurement_unit.to_string()])?;
+    }
+    for (position, tag) in recipe.tags.iter().enumerate() {
+        transaction.execute("INSERT INTO tags (recipe_id, position, tag) VALUES (?1, ?2, ?3)",
+                            params![id, position as i64, tag])?;
+    }
+    for (position, instruction) in recipe.instructions.iter().enumerate() {
+        transaction.execute("INSERT INTO instructions (recipe_id, position, instruction) VALUES (?1, ?2, ?3)",
+                            params![id, position as i64, instruction])?;
+    }
+    Ok(())
+}
+
+fn delete_children(transaction: &Transaction, id: &ObjectId) -> Result<(), DaoError> {
+    for table in &["ingredients", "tags", "instructions"] {
+        transaction.execute(&format!("DELETE FROM {} WHERE recipe_id = ?1", table), params![id.to_hex()])?;
+    }
+    Ok(())
+}
+
+/// one recipe with its children, without image
+fn exists(connection: &Connection, id: &ObjectId) -> Result<bool, DaoError> {
+    let found = connection.query_row("SELECT 1 FROM recipes WHERE id = ?1", params![id.to_hex()], |_| Ok(()))
+        .optional()?;
+    Ok(found.is_some())
+}
+
+fn read_recipe(connection: &Connection, id: &ObjectId) -> Result<Recipe, DaoError> {
+    let recipe = connection
+        .query_row(&format!("{} WHERE id = ?1", SELECT_RECIPE), params![id.to_hex()], read_recipe_row)
+        .optional()?;
+    match recipe {
+        Some(recipe) => {
+            let mut recipe = recipe.map_err(|error| {
+                error!("Got one recipe, but could not format id={:#?}, error={:#?}", id, error);
+                DaoError::RecipeFormatError(id.to_hex())
+            })?;
+            read_children(connection, &mut recipe)?;
+            Ok(recipe)
+        }
+        None => {
+            error!("get recipe without image, recipe Not found: id={:#?}", id);
+            Err(DaoError::DocumentNotFound(Resource::Recipe))
+        }
+    }
+}
+
+/// a row of the recipes table, children are read separately
+struct RecipeRow {
+    id: String,
+    cooking_time_in_minutes: i64,
+    created: i64,
+    last_modified: i64,
+    version: i64,
+    difficulty: String,
+    description: String,
+    title: String,
+    default_servings: i64,
+}
+
+impl TryFrom<RecipeRow> for Recipe {
+    type Error = RecipeFormatError;
+
+    fn try_from(row: RecipeRow) -> Result<Self, Self::Error> {
+        return Ok(Recipe {
+            _id: ObjectId::with_string(&row.id)
+                .map_err(|_| RecipeFormatError::from("Error getting  Object Id from row"))?,
+            cooking_time_in_minutes: if row.cooking_time_in_minutes < 0 { 0 } else { row.cooking_time_in_minutes as u32 },
+            created: millis_to_datetime(row.created),
+            last_modified: millis_to_datetime(row.last_modified),
+            ingredients: vec![],
+            version: row.version as u32,
+            difficulty: Difficulty::try_from(row.difficulty.as_str())?,
+            description: row.description,
+            title: row.title,
+            tags: vec![],
+            image_base64: None,
+            instructions: vec![],
+            default_servings: if row.default_servings < 1 { 1 } else { row.default_servings as u32 },
+            gallery: vec![],
+        });
+    }
+}
+
+/// the outer result fails on sqlite errors, the inner one on values the model does not accept
+fn read_recipe_row(row: &Row) -> rusqlite::Result<Result<Recipe, UnreadableRecipe>> {
+    let id: String = row.get(0)?;
+    Ok(Recipe::try_from(RecipeRow {
+        id: row.get(0)?,
+        cooking_time_in_minutes: row.get(1)?,
+        created: row.get(2)?,
+        last_modified: row.get(3)?,
+        version: row.get(4)?,
+        difficulty: row.get(5)?,
+        description: row.get(6)?,
+        title: row.get(7)?,
+        default_servings: row.get(8)?,
+    }).map_err(|err| UnreadableRecipe { id, error: err.error }))
+}
+
+fn write_quantity(quantity: &Quantity) -> Result<String, DaoError> {
+    serde_json::to_string(quantity).map_err(|err| DaoError::RecipeFormatError(err.to_string()))
+}
+
+fn read_quantity(json: &str) -> Result<Quantity, RecipeFormatError> {
+    serde_json::from_str(json).map_err(|err| RecipeFormatError::from(err.to_string()))
+}
+
+fn read_children(connection: &Connection, recipe: &mut Recipe) -> Result<(), DaoError> {
+    let id = recipe._id.to_hex();
+
+    let mut statement = connection.prepare(
+        "SELECT id, quantity, title, measurement_unit FROM ingredients WHERE recipe_id = ?1 ORDER BY position")?;
+    recipe.ingredients = statement
+        .query_map(params![id], |row| {
+            let unit: String = row.get(3)?;
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, unit))
+        })?
+        .collect::<Result<Vec<(String, String, String, String)>, rusqlite::Error>>()?
+        .into_iter()
+        .map(|(ingredient_id, quantity, title, unit)| read_quantity(&quantity)
+            .and_then(|amount| MeasurementUnit::try_from(unit.as_str())
+                .map(|unit| Ingredient::new(&ingredient_id, amount, &title, unit))))
+        .collect::<Result<Vec<Ingredient>, RecipeFormatError>>()
+        .map_err(|_| DaoError::RecipeFormatError(id.clone()))?;
+
+    let mut statement = connection.prepare("SELECT tag FROM tags WHERE recipe_id = ?1 ORDER BY position")?;
+    recipe.tags = statement
+        .query_map(params![id], |row| row.get(0))?
+        .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+
+    let mut statement = connection.prepare(
+        "SELECT instruction FROM instructions WHERE recipe_id = ?1 ORDER BY position")?;
+    recipe.instructions = statement
+        .query_map(params![id], |row| row.get(0))?
+        .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+
+    let mut statement = connection.prepare(
+        "SELECT id, image, caption, cover, step FROM gallery WHERE recipe_id = ?1 ORDER BY position")?;
+    recipe.gallery = statement
+        .query_map(params![id], |row| Ok(GalleryImage {
+            id: row.get(0)?,
+            image: row.get(1)?,
+            caption: row.get(2)?,
+            cover: row.get(3)?,
+            step: row.get(4)?,
+        }))?
+        .collect::<Result<Vec<GalleryImage>, rusqlite::Error>>()?;
+    Ok(())
+}
+
+fn millis_to_datetime(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis(millis)
+}
+
+
+#[cfg(test)]
+mod sqlite_store_tests {
+    use chrono::Duration;
+    use futures_util::StreamExt;
+
+    use crate::dao::dao_tests::{create_many_recipes_without_images, create_one_recipe_without_image};
+    use crate::filter::RecipeFilter;
+    use crate::model::ingredients::Ingredient;
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::quantity::Quantity;
+    use crate::pagination::{Listing, Sort};
+    use crate::store::{DaoError, RecipeStore, store_tests, UnreadableRecipe};
+    use crate::store::sqlite::{SqliteStore, STREAM_BUFFER, upgrade_ingredients};
+
+    fn store() -> SqliteStore {
+        SqliteStore::open_in_memory().unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn insert_and_get_recipe() {
+        store_tests::insert_and_get_recipe(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn update_recipe_ignores_image() {
+        store_tests::update_recipe_ignores_image(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn update_checks_version() {
+        store_tests::update_checks_version(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn update_keeps_created() {
+        store_tests::update_keeps_created(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn update_gallery_sets_image() {
+        store_tests::update_gallery_sets_image(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn delete_recipe() {
+        store_tests::delete_recipe(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn add_and_get_many_recipes() {
+        store_tests::add_and_get_many_recipes(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn get_paged_recipes() {
+        store_tests::get_paged_recipes(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn keeps_ingredients_tags_and_instructions_in_order() {
+        store_tests::keeps_recipe_content(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn filter_recipes() {
+        store_tests::filter_recipes(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn sort_and_follow_cursor() {
+        store_tests::sort_and_follow_cursor(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn put_and_delete_units() {
+        store_tests::put_and_delete_units(&store()).await;
+    }
+
+    #[actix_rt::test]
+    async fn scan_reports_unreadable_recipes() {
+        let store = store();
+        store.insert_recipe(create_one_recipe_without_image()).await.unwrap();
+        let id = store.insert_recipe(create_one_recipe_without_image()).await.unwrap();
+        let id = id.as_object_id().unwrap().to_hex();
+        store.execute(&format!("UPDATE recipes SET difficulty = 'Impossible' WHERE id = '{}'", id)).unwrap();
+
+        let report = store.scan_recipes().await.unwrap();
+        assert_eq!(report.scanned, 2);
+        assert_eq!(report.unreadable, vec![UnreadableRecipe {
+            id,
+            error: "Difficulty 'Impossible' does not match one predefined value".to_string(),
+        }]);
+        assert!(matches!(store.get_many_recipes(&Listing::default()).await, Err(DaoError::UnreadableRecipe(_))));
+    }
+
+    #[actix_rt::test]
+    async fn streams_rows_one_at_a_time() {
+        let store = store();
+        store.add_many_recipes(create_many_recipes_without_images(3 * STREAM_BUFFER as i32)).await.unwrap();
+
+        let mut recipes = store.stream_many_recipes(&Listing::default(), &RecipeFilter::default()).await.unwrap();
+        assert!(recipes.next().await.unwrap().is_ok());
+        drop(recipes);
+
+        // the dropped listing stops reading and unlocks the connection
+        store.insert_recipe(create_one_recipe_without_image()).await.unwrap();
+        assert_eq!(store.get_many_recipes(&Listing::default()).await.unwrap().len(), 3 * STREAM_BUFFER + 1);
+    }
+
+    #[actix_rt::test]
+    async fn skip_and_limit_are_part_of_the_query() {
+        let store = store();
+        let mut recipes = create_many_recipes_without_images(5);
+        for (i, recipe) in recipes.iter_mut().enumerate() {
+            recipe.created = recipe.created + Duration::minutes(i as i64);
+        }
+        let ids = store.add_many_recipes(recipes).await.unwrap();
+        let ids = ids.as_array().unwrap();
+        for id in &[&ids[0], &ids[4]] {
+            store.execute(&format!("UPDATE recipes SET difficulty = 'Impossible' WHERE id = '{}'", id.as_object_id().unwrap().to_hex())).unwrap();
+        }
+
+        // the recipes before and after the page are never read, so the unreadable ones do not show
+        let listing = Listing { sort: Some(Sort::created(false)), skip: 1, limit: Some(3), after: None };
+        let page = store.get_filtered_recipes(&listing, &RecipeFilter::default()).await.unwrap();
+        assert_eq!(page.iter().map(|recipe| recipe.title.clone()).collect::<Vec<String>>(), vec!["1", "2", "3"]);
+
+        let listing = Listing { skip: 1, limit: Some(4), ..listing };
+        assert!(matches!(store.get_filtered_recipes(&listing, &RecipeFilter::default()).await, Err(DaoError::UnreadableRecipe(_))));
+    }
+
+    #[actix_rt::test]
+    async fn upgrades_amounts_to_quantities() {
+        let store = store();
+        let mut recipe = create_one_recipe_without_image();
+        recipe.ingredients = ["200", "1.5", "1½", "2-3", "to taste"].iter()
+            .map(|quantity| Ingredient::new("Mehl", Quantity::parse(quantity).unwrap(), "Mehl", MeasurementUnit::GRAMM))
+            .collect();
+        let id = store.insert_recipe(recipe.clone()).await.unwrap();
+        let id = id.as_object_id().unwrap().clone();
+
+        // the table as before quantities, its INTEGER column turns "200" into 200 and "1.5" into 1.5
+        store.execute("ALTER TABLE ingredients RENAME TO ingredients_quantity").unwrap();
+        store.execute("CREATE TABLE ingredients (recipe_id TEXT NOT NULL REFERENCES recipes (id) ON DELETE CASCADE, \
+            position INTEGER NOT NULL, id TEXT NOT NULL, amount INTEGER NOT NULL, title TEXT NOT NULL, \
+            measurement_unit TEXT NOT NULL, PRIMARY KEY (recipe_id, position))").unwrap();
+        store.execute("INSERT INTO ingredients SELECT recipe_id, position, id, quantity, title, measurement_unit \
+            FROM ingredients_quantity").unwrap();
+        store.execute("DROP TABLE ingredients_quantity").unwrap();
+
+        upgrade_ingredients(&mut store.connection.lock().unwrap()).unwrap();
+        upgrade_ingredients(&mut store.connection.lock().unwrap()).unwrap();
+        let types: Vec<String> = store.connection.lock().unwrap()
+            .prepare("SELECT DISTINCT typeof(quantity) FROM ingredients").unwrap()
+            .query_map(rusqlite::params![], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(types, vec!["text"]);
+        let stored = store.get_one_recipe_without_image(id).await.unwrap();
+        assert_eq!(stored.ingredients, recipe.ingredients);
+    }
+}
@@ -0,0 +1,362 @@
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::ingredient_parser::{self, BULLETS};
+use crate::model::difficulty::Difficulty;
+use crate::model::recipe::Recipe;
+use crate::model::unit::UnitCatalogue;
+use crate::store;
+
+/// ingredient lines below this confidence are reported instead of guessed, a title alone is enough
+const MIN_CONFIDENCE: f64 = 0.4;
+/// before any heading only lines starting with a number and having a title are ingredients,
+/// `3 Eier` but not `Ein Klassiker aus dem Allgäu`
+const MIN_CONFIDENCE_WITHOUT_HEADING: f64 = 0.9;
+/// headings and servings are short, `Zutaten für 4 Personen` but not a sentence starting with `Zutaten`
+const MAX_HEADING_WORDS: usize = 5;
+const MAX_SERVINGS_WORDS: usize = 4;
+/// steps are numbered up to 999
+const MAX_STEP_DIGITS: usize = 3;
+
+const INGREDIENT_HEADINGS: &[&str] = &["zutaten", "einkaufsliste", "ingredients", "you will need", "you'll need"];
+const INSTRUCTION_HEADINGS: &[&str] = &["zubereitung", "anleitung", "schritte", "so geht", "instructions", "directions", "method", "preparation", "steps"];
+const STEP_WORDS: &[&str] = &["schritt", "step"];
+const SERVING_WORDS: &[&str] = &["personen", "person", "portionen", "portion", "servings", "serving", "serves", "people"];
+const TIME_WORDS: &[&str] = &["zubereitungszeit", "kochzeit", "backzeit", "arbeitszeit", "gesamtzeit", "garzeit", "dauer",
+    "cooking time", "cook time", "prep time", "preparation time", "baking time", "total time"];
+/// a total time replaces the other times instead of adding to them
+const TOTAL_TIME_WORDS: &[&str] = &["gesamtzeit", "total time"];
+const HOUR_WORDS: &[&str] = &["h", "std", "stunde", "stunden", "hour", "hours", "hr", "hrs"];
+const MINUTE_WORDS: &[&str] = &["min", "mins", "minute", "minuten", "minutes"];
+
+/// `POST /recipes/import/text`
+#[derive(Deserialize, Debug)]
+pub struct TextImport {
+    pub text: String,
+}
+
+/// A line of the text that is neither title, description, ingredient, instruction nor servings or time.
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct UnparsedLine {
+    /// counted from 1 like editors do
+    pub line: usize,
+    pub text: String,
+}
+
+/// A recipe worked out of pasted text, for the client to review and add. Nothing is stored.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportedRecipe {
+    pub recipe: Recipe,
+    pub unparsed: Vec<UnparsedLine>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Section {
+    Description,
+    Ingredients,
+    Instructions,
+}
+
+/// The first line is the title, lines up to the first heading the description. Headings like `Zutaten`
+/// or `Instructions` start the ingredients and the instructions, lines like `Für 4 Personen` or
+/// `Backzeit: 45 Minuten` anywhere give servings and cooking time. Numbered steps may span several
+/// lines, without numbers every line is a step. Without headings ingredients and numbered steps are
+/// recognized by themselves. Servings and cooking time not found are 0, without servings the draft does not validate.
+pub fn import_text(text: &str, units: &UnitCatalogue) -> ImportedRecipe {
+    let mut title = None;
+    let mut description = vec![];
+    let mut ingredients = vec![];
+    let mut instructions: Vec<String> = vec![];
+    let mut unparsed = vec![];
+    let mut servings = None;
+    let (mut times, mut total_time) = (vec![], None);
+    let mut section = Section::Description;
+    let mut numbered = false;
+    let mut previous_blank = true;
+
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        let continues = !previous_blank;
+        previous_blank = trimmed.is_empty();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let unparsed_line = UnparsedLine { line: index + 1, text: line.to_string() };
+
+        if let Some(minutes) = cooking_time(trimmed) {
+            if TOTAL_TIME_WORDS.iter().any(|word| trimmed.to_lowercase().contains(word)) {
+                total_time = Some(minutes);
+            } else {
+                times.push(minutes);
+            }
+            continue;
+        }
+        if let Some(heading) = heading(trimmed) {
+            section = heading;
+            servings = servings.or_else(|| servings_of(trimmed));
+            continue;
+        }
+        if trimmed.split_whitespace().count() <= MAX_SERVINGS_WORDS {
+            if let Some(found) = servings_of(trimmed) {
+                servings = servings.or(Some(found));
+                continue;
+            }
+        }
+        if title.is_none() && section == Section::Description {
+            title = Some(trimmed.trim_start_matches('#').trim().to_string());
+            continue;
+        }
+
+        match section {
+            Section::Ingredients => {
+                let parsed = ingredient_parser::parse_line(trimmed, units);
+                if parsed.confidence >= MIN_CONFIDENCE && !trimmed.ends_with(':') {
+                    ingredients.push(parsed.ingredient(&ingredients.len().to_string()));
+                } else {
+                    unparsed.push(unparsed_line);
+                }
+            }
+            Section::Instructions => match step(trimmed) {
+                Some(step) => {
+                    numbered = true;
+                    instructions.push(step.to_string());
+                }
+                None if numbered && continues => {
+                    let last = instructions.last_mut().expect("a numbered step came before");
+                    last.push(' ');
+                    last.push_str(trimmed);
+                }
+                None if numbered => unparsed.push(unparsed_line),
+                None => instructions.push(trimmed.trim_start_matches(BULLETS).trim().to_string()),
+            },
+            Section::Description => {
+                if let Some(step) = step(trimmed) {
+                    section = Section::Instructions;
+                    numbered = true;
+                    instructions.push(step.to_string());
+                    continue;
+                }
+                let parsed = ingredient_parser::parse_line(trimmed, units);
+                let starts_with_number = trimmed.trim_start_matches(BULLETS).trim_start().starts_with(char::is_numeric);
+                if starts_with_number && parsed.confidence >= MIN_CONFIDENCE_WITHOUT_HEADING {
+                    ingredients.push(parsed.ingredient(&ingredients.len().to_string()));
+                } else if ingredients.is_empty() {
+                    description.push(trimmed.to_string());
+                } else {
+                    unparsed.push(unparsed_line);
+                }
+            }
+        }
+    }
+
+    let now = store::now();
+    let recipe = Recipe {
+        _id: ObjectId::new(),
+        cooking_time_in_minutes: total_time.unwrap_or_else(|| times.iter().sum()),
+        created: now,
+        last_modified: now,
+        ingredients,
+        version: 1,
+        difficulty: Difficulty::Medium,
+        description: description.join("\n"),
+        title: title.unwrap_or_default(),
+        tags: vec![],
+        image_base64: None,
+        instructions: instructions.into_iter()
+            .map(|instruction| instruction.trim().to_string())
+            .filter(|instruction| !instruction.is_empty())
+            .collect(),
+        default_servings: servings.unwrap_or(0),
+        gallery: vec![],
+    };
+    ImportedRecipe { recipe, unparsed }
+}
+
+/// the section a heading like `Zutaten für 4 Personen:` or `## Instructions` starts
+fn heading(line: &str) -> Option<Section> {
+    let heading = line.trim_start_matches('#').trim().trim_end_matches(':').trim().to_lowercase();
+    if heading.split_whitespace().count() > MAX_HEADING_WORDS {
+        return None;
+    }
+    if INGREDIENT_HEADINGS.iter().any(|word| heading.starts_with(word)) {
+        Some(Section::Ingredients)
+    } else if INSTRUCTION_HEADINGS.iter().any(|word| heading.starts_with(word)) {
+        Some(Section::Instructions)
+    } else {
+        None
+    }
+}
+
+/// the words of a line in lower case without punctuation, `45min` split into `45` and `min`
+fn words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    for word in line.to_lowercase().split(|c: char| c.is_whitespace() || ":;()/".contains(c)) {
+        let word = word.trim_matches(|c: char| ",.!".contains(c));
+        match word.find(|c: char| c.is_alphabetic()) {
+            Some(index) if index > 0 && word[..index].chars().all(|c| c.is_ascii_digit() || c == ',' || c == '.') => {
+                words.extend([word[..index].to_string(), word[index..].to_string()]);
+            }
+            _ if !word.is_empty() => words.push(word.to_string()),
+            _ => {}
+        }
+    }
+    words
+}
+
+/// the servings of `Für 4 Personen`, `4-6 Portionen` or `Serves 4`, the first number of a range
+fn servings_of(line: &str) -> Option<u32> {
+    let words = words(line);
+    words.iter().enumerate().find_map(|(index, word)| {
+        let digits: String = word.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let servings: u32 = digits.parse().ok()?;
+        let next = words.get(index + 1).map(String::as_str);
+        let previous = index.checked_sub(1).and_then(|index| words.get(index)).map(String::as_str);
+        if [next, previous].iter().flatten().any(|word| SERVING_WORDS.contains(word)) {
+            Some(servings)
+        } else {
+            None
+        }
+    })
+}
+
+/// The minutes of a line like `Backzeit: 1 Std. 20 Min.` or `Total time: 1.5 hours`,
+/// None for lines that name no time.
+fn cooking_time(line: &str) -> Option<u32> {
+    let lower = line.to_lowercase();
+    if !TIME_WORDS.iter().any(|word| lower.contains(word)) {
+        return None;
+    }
+    let words = words(line);
+    let minutes: f64 = words.windows(2)
+        .filter_map(|pair| {
+            let number: f64 = pair[0].replace(',', ".").parse().ok()?;
+            if HOUR_WORDS.contains(&pair[1].as_str()) {
+                Some(number * 60.0)
+            } else if MINUTE_WORDS.contains(&pair[1].as_str()) {
+                Some(number)
+            } else {
+                None
+            }
+        })
+        .sum();
+    if minutes > 0.0 { Some(minutes.round() as u32) } else { None }
+}
+
+/// The text of a numbered step like `1. Mehl sieben`, `2) Rühren` or `Schritt 3: Backen`,
+/// None for lines without number.
+fn step(line: &str) -> Option<&str> {
+    let line = line.trim_start_matches(BULLETS).trim_start();
+    let (rest, named) = STEP_WORDS.iter()
+        .find_map(|word| line.get(..word.len())
+            .filter(|start| start.eq_ignore_ascii_case(word))
+            .map(|_| (line[word.len()..].trim_start(), true)))
+        .unwrap_or((line, false));
+    let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 || digits > MAX_STEP_DIGITS {
+        return None;
+    }
+    let rest = &rest[digits..];
+    let rest = match rest.strip_prefix(['.', ')', ':']) {
+        Some(rest) => rest,
+        None if named => rest,
+        None => return None,
+    };
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod text_import_tests {
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::quantity::Quantity;
+    use crate::model::unit::UnitCatalogue;
+    use crate::text_import::{import_text, step, UnparsedLine};
+
+    const KAESESPAETZLE: &str = "Käsespätzle
+
+Ein Klassiker aus dem Allgäu.
+Schnell gemacht.
+
+Zutaten für 4 Personen:
+- 500 g Spätzle
+- 200 g Bergkäse, gerieben
+- 2 Zwiebeln
+- Salz und Pfeffer
+Für die Deko:
+
+Zubereitungszeit: 20 Minuten
+Backzeit: 1 Std. 10 Min.
+
+Zubereitung
+1. Zwiebeln in Ringe schneiden
+   und goldbraun rösten.
+2. Spätzle und Käse schichten.
+
+3) Im Ofen backen.
+
+Guten Appetit!
+";
+
+    #[test]
+    fn imports_german_recipe() {
+        let imported = import_text(KAESESPAETZLE, &UnitCatalogue::builtin());
+        let recipe = imported.recipe;
+        assert_eq!(recipe.title, "Käsespätzle");
+        assert_eq!(recipe.description, "Ein Klassiker aus dem Allgäu.\nSchnell gemacht.");
+        assert_eq!(recipe.default_servings, 4);
+        assert_eq!(recipe.cooking_time_in_minutes, 90);
+        assert_eq!(recipe.ingredients.len(), 4);
+        assert_eq!(recipe.ingredients[1].title, "Bergkäse (gerieben)");
+        assert_eq!(recipe.ingredients[1].amount, Quantity::from(200));
+        assert_eq!(recipe.ingredients[2].measurement_unit, MeasurementUnit::PIECE);
+        assert_eq!(recipe.ingredients[3].id, "3");
+        assert_eq!(recipe.instructions, vec![
+            "Zwiebeln in Ringe schneiden und goldbraun rösten.",
+            "Spätzle und Käse schichten.",
+            "Im Ofen backen.",
+        ]);
+        assert_eq!(imported.unparsed, vec![
+            UnparsedLine { line: 11, text: "Für die Deko:".to_string() },
+            UnparsedLine { line: 23, text: "Guten Appetit!".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn imports_english_recipe_without_headings() {
+        let text = "# Pancakes\nServes 2\nTotal time: 25 minutes\n\n1 1/2 cups flour\n2 eggs\n1 cup milk\n\n\
+                    1. Whisk everything.\n2. Fry in a pan.\nEnjoy";
+        let imported = import_text(text, &UnitCatalogue::builtin());
+        let recipe = imported.recipe;
+        assert_eq!(recipe.title, "Pancakes");
+        assert_eq!(recipe.default_servings, 2);
+        assert_eq!(recipe.cooking_time_in_minutes, 25);
+        assert_eq!(recipe.ingredients.iter().map(|ingredient| ingredient.title.as_str()).collect::<Vec<_>>(), vec!["flour", "eggs", "milk"]);
+        assert_eq!(recipe.ingredients[0].amount.to_string(), "1½");
+        assert_eq!(recipe.instructions, vec!["Whisk everything.", "Fry in a pan. Enjoy"]);
+        assert_eq!(recipe.description, "");
+        assert!(imported.unparsed.is_empty());
+    }
+
+    #[test]
+    fn steps_without_numbers_are_lines() {
+        let imported = import_text("Tee\nInstructions:\nBoil water.\n* Add tea.\n", &UnitCatalogue::builtin());
+        assert_eq!(imported.recipe.instructions, vec!["Boil water.", "Add tea."]);
+        assert_eq!(imported.recipe.default_servings, 0);
+        assert_eq!(imported.recipe.cooking_time_in_minutes, 0);
+    }
+
+    #[test]
+    fn recognizes_numbered_steps() {
+        assert_eq!(step("1. Mehl sieben"), Some("Mehl sieben"));
+        assert_eq!(step("12) Rühren"), Some("Rühren"));
+        assert_eq!(step("Schritt 3: Backen"), Some("Backen"));
+        assert_eq!(step("Step 4 Serve"), Some("Serve"));
+        assert_eq!(step("2.5 kg Mehl"), None);
+        assert_eq!(step("200 g Mehl"), None);
+        assert_eq!(step("Mehl"), None);
+    }
+}
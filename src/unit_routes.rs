@@ -0,0 +1,185 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::http::StatusCode;
+
+use crate::error::ApiError;
+use crate::model::unit::{Unit, UnitCatalogue};
+use crate::store::RecipeStore;
+use crate::json_body::JsonBody;
+use crate::validation::{self, Validate};
+
+pub struct UnitRoutes {}
+
+impl UnitRoutes {
+    /// the builtin units followed by the custom ones
+    pub async fn get_units(database: web::Data<dyn RecipeStore>) -> Result<HttpResponse, ApiError> {
+        let units = database.unit_catalogue().await?;
+        Ok(HttpResponse::Ok().json(units.units()))
+    }
+
+    pub async fn get_unit(req: HttpRequest, database: web::Data<dyn RecipeStore>) -> Result<HttpResponse, ApiError> {
+        let units = database.unit_catalogue().await?;
+        units.get(unit_id(&req))
+            .map(|unit| HttpResponse::Ok().json(unit))
+            .ok_or_else(ApiError::unit_not_found)
+    }
+
+    /// Adds the custom unit or replaces it, 201 when it was added. The body has the id of the path.
+    /// Id and builtin units are checked before the unit is validated.
+    pub async fn put_unit(req: HttpRequest, database: web::Data<dyn RecipeStore>, unit: JsonBody<Unit>) -> Result<HttpResponse, ApiError> {
+        let id = unit_id(&req);
+        let unit = unit.into_inner();
+        if unit.id.id() != id {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, "id-mismatch", "Id mismatch",
+                                     format!("The body has the id '{}', the path '{}'", unit.id, id))
+                .with_field("id"));
+        }
+        if is_builtin(id) {
+            return Err(builtin_unit(id));
+        }
+        let violations = unit.validate(&validation::limits(&req), &database.unit_catalogue().await?);
+        if !violations.is_empty() {
+            return Err(Unit::invalid(violations));
+        }
+        let status = if database.put_unit(unit.clone()).await? { StatusCode::CREATED } else { StatusCode::OK };
+        Ok(HttpResponse::build(status).json(unit))
+    }
+
+    /// Recipes keep the id of a deleted unit, they cannot be saved with it again.
+    pub async fn delete_unit(req: HttpRequest, database: web::Data<dyn RecipeStore>) -> Result<HttpResponse, ApiError> {
+        let id = unit_id(&req);
+        if is_builtin(id) {
+            return Err(builtin_unit(id));
+        }
+        database.delete_unit(id).await?;
+        Ok(HttpResponse::Ok().finish())
+    }
+}
+
+
+fn unit_id(req: &HttpRequest) -> &str {
+    req.match_info().get("id").unwrap_or_default()
+}
+
+fn is_builtin(id: &str) -> bool {
+    UnitCatalogue::builtin().get(id).is_some()
+}
+
+fn builtin_unit(id: &str) -> ApiError {
+    ApiError::new(StatusCode::CONFLICT, "builtin-unit", "Builtin unit",
+                  format!("'{}' comes with the server and cannot be changed", id))
+        .with_field("id")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, test, web};
+    use actix_web::http::StatusCode;
+    use bson::Bson;
+
+    use crate::image_store::ImageStore;
+    use crate::image_store::memory::MemoryImageStore;
+    use crate::recipe_routes::RecipeRoutes;
+    use crate::store::memory::MemoryStore;
+    use crate::store::RecipeStore;
+    use crate::unit_routes::UnitRoutes;
+
+    fn bucket() -> serde_json::Value {
+        serde_json::json!({
+            "id": "Eimer",
+            "names": {"de": "Eimer", "en": "bucket"},
+            "abbreviations": ["Ei."],
+            "dimension": "volume",
+            "factor": 10000.0,
+        })
+    }
+
+    fn recipe(unit: &str) -> Bson {
+        bson!({
+            "cookingTimeInMinutes": 12,
+            "created": "2020-09-11T12:21:21+00:00",
+            "lastModified": "2020-09-11T12:21:21+00:00",
+            "ingredients": [{"id": "0", "amount": 2, "title": "Wasser", "measurementUnit": unit}],
+            "version": 1,
+            "difficulty": "Easy",
+            "description": "",
+            "title": "Wasser",
+            "tags": [],
+            "image": null,
+            "instructions": [],
+            "defaultServings": 2
+        })
+    }
+
+    #[actix_rt::test]
+    async fn test_manage_units() {
+        let store = web::Data::from(Arc::new(MemoryStore::new()) as Arc<dyn RecipeStore>);
+        let images = web::Data::from(Arc::new(MemoryImageStore::new()) as Arc<dyn ImageStore>);
+
+        let mut app = test::init_service(App::new()
+            .app_data(store.clone())
+            .app_data(images)
+            .route("/units", web::get().to(UnitRoutes::get_units))
+            .route("/units/{id}", web::get().to(UnitRoutes::get_unit))
+            .route("/units/{id}", web::put().to(UnitRoutes::put_unit))
+            .route("/units/{id}", web::delete().to(UnitRoutes::delete_unit))
+            .route("/recipes/{id}", web::post().to(RecipeRoutes::add_one_recipe))).await;
+
+        let req = test::TestRequest::get().uri("/units").to_request();
+        let units: Vec<serde_json::Value> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(units[0]["id"], "Kilogramm");
+        assert!(units.iter().all(|unit| unit["builtin"] == true));
+
+        let req = test::TestRequest::post().set_json(&recipe("Eimer")).uri("/recipes/new").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "ingredients[0].measurementUnit");
+
+        let req = test::TestRequest::put().set_json(&bucket()).uri("/units/Eimer").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::CREATED);
+        let req = test::TestRequest::put().set_json(&bucket()).uri("/units/Eimer").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["builtin"], false);
+
+        let req = test::TestRequest::get().uri("/units/Eimer").to_request();
+        let unit: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(unit["names"]["en"], "bucket");
+        let req = test::TestRequest::post().set_json(&recipe("Eimer")).uri("/recipes/new").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+
+        let req = test::TestRequest::put().set_json(&bucket()).uri("/units/Kuebel").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::BAD_REQUEST);
+        let mut gramm = bucket();
+        gramm["id"] = "Gramm".into();
+        gramm["abbreviations"] = serde_json::json!([]);
+        let req = test::TestRequest::put().set_json(&gramm).uri("/units/Gramm").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::CONFLICT);
+        // the builtin unit is reported even when the body would not be valid
+        gramm["abbreviations"] = serde_json::json!(["g"]);
+        gramm["names"] = serde_json::json!({});
+        let req = test::TestRequest::put().set_json(&gramm).uri("/units/Gramm").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::CONFLICT);
+        let mut invalid = bucket();
+        invalid["abbreviations"] = serde_json::json!(["EL"]);
+        let req = test::TestRequest::put().set_json(&invalid).uri("/units/Eimer").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["type"], "urn:zellinotes:problem:invalid-unit");
+        assert_eq!(body["field"], "abbreviations[0]");
+
+        let req = test::TestRequest::delete().uri("/units/Eimer").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::delete().uri("/units/Eimer").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::NOT_FOUND);
+        let req = test::TestRequest::get().uri("/units/Eimer").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::NOT_FOUND);
+        let req = test::TestRequest::delete().uri("/units/Prise").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::CONFLICT);
+    }
+}
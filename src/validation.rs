@@ -0,0 +1,374 @@
+use std::collections::HashSet;
+
+use actix_web::{dev, FromRequest, HttpRequest, web};
+use futures_util::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config::ValidationConfig;
+use crate::error::ApiError;
+use crate::json_body::JsonBody;
+use crate::model::gallery::GalleryImage;
+use crate::model::recipe::Recipe;
+use crate::model::unit::{Dimension, Unit, UnitCatalogue};
+use crate::store::RecipeStore;
+
+/// ids of custom units are part of `/api/v1/units/{id}`
+const MAX_UNIT_ID_LENGTH: usize = 40;
+
+/// One broken rule, `field` is the path in the request body like `ingredients[2].amount`.
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Violation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Violation { field: Some(field.into()), message: message.into() }
+    }
+}
+
+/// Checks the rules of the domain serde cannot express. Every violation is returned, not only the first.
+pub trait Validate {
+    fn validate(&self, limits: &ValidationConfig, units: &UnitCatalogue) -> Vec<Violation>;
+
+    /// the answer to a body with violations
+    fn invalid(violations: Vec<Violation>) -> ApiError {
+        ApiError::invalid_recipe(violations)
+    }
+}
+
+impl Validate for Recipe {
+    fn validate(&self, limits: &ValidationConfig, units: &UnitCatalogue) -> Vec<Violation> {
+        let mut violations = vec![];
+        let mut check = |valid: bool, field: String, message: String| {
+            if !valid {
+                violations.push(Violation::new(field, message));
+            }
+        };
+
+        check(!self.title.trim().is_empty(), "title".to_string(), "must not be empty".to_string());
+        check(chars(&self.title) <= limits.max_title_length, "title".to_string(),
+              format!("must not be longer than {} characters", limits.max_title_length));
+        check(chars(&self.description) <= limits.max_description_length, "description".to_string(),
+              format!("must not be longer than {} characters", limits.max_description_length));
+        check(self.cooking_time_in_minutes <= limits.max_cooking_time, "cookingTimeInMinutes".to_string(),
+              format!("must not be more than {} minutes", limits.max_cooking_time));
+        check(self.default_servings >= 1 && self.default_servings <= limits.max_servings, "defaultServings".to_string(),
+              format!("must be between 1 and {}", limits.max_servings));
+
+        check(self.tags.len() <= limits.max_tags, "tags".to_string(),
+              format!("must not contain more than {} tags", limits.max_tags));
+        let mut tags = HashSet::new();
+        for (index, tag) in self.tags.iter().enumerate() {
+            let field = format!("tags[{}]", index);
+            check(!tag.trim().is_empty(), field.clone(), "must not be empty".to_string());
+            check(chars(tag) <= limits.max_tag_length, field.clone(),
+                  format!("must not be longer than {} characters", limits.max_tag_length));
+            check(tags.insert(tag.trim().to_lowercase()), field, format!("'{}' is listed twice", tag));
+        }
+
+        check(self.ingredients.len() <= limits.max_ingredients, "ingredients".to_string(),
+              format!("must not contain more than {} ingredients", limits.max_ingredients));
+        let mut ids = HashSet::new();
+        for (index, ingredient) in self.ingredients.iter().enumerate() {
+            check(!ingredient.id.is_empty(), format!("ingredients[{}].id", index), "must not be empty".to_string());
+            check(ids.insert(ingredient.id.as_str()), format!("ingredients[{}].id", index),
+                  format!("'{}' is already the id of another ingredient", ingredient.id));
+            check(!ingredient.amount.is_negative(), format!("ingredients[{}].amount", index), "must not be negative".to_string());
+            check(ingredient.amount.is_ordered(), format!("ingredients[{}].amount", index), "min must not be greater than max".to_string());
+            check(!ingredient.title.trim().is_empty(), format!("ingredients[{}].title", index), "must not be empty".to_string());
+            check(units.get(ingredient.measurement_unit.id()).is_some(), format!("ingredients[{}].measurementUnit", index),
+                  format!("'{}' is no unit of the catalogue", ingredient.measurement_unit));
+        }
+
+        check(self.instructions.len() <= limits.max_instructions, "instructions".to_string(),
+              format!("must not contain more than {} steps", limits.max_instructions));
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let field = format!("instructions[{}]", index);
+            check(!instruction.trim().is_empty(), field.clone(), "must not be empty".to_string());
+            check(chars(instruction) <= limits.max_instruction_length, field,
+                  format!("must not be longer than {} characters", limits.max_instruction_length));
+        }
+
+        violations
+    }
+}
+
+impl Validate for Vec<Recipe> {
+    fn validate(&self, limits: &ValidationConfig, units: &UnitCatalogue) -> Vec<Violation> {
+        let mut violations = vec![];
+        if self.len() > limits.max_batch_size {
+            violations.push(Violation {
+                field: None,
+                message: format!("must not contain more than {} recipes", limits.max_batch_size),
+            });
+        }
+        for (index, recipe) in self.iter().enumerate() {
+            violations.extend(recipe.validate(limits, units).into_iter().map(|violation| Violation {
+                field: violation.field.map(|field| format!("[{}].{}", index, field)),
+                message: violation.message,
+            }));
+        }
+        violations
+    }
+}
+
+/// A custom unit. Its abbreviations must not stand for another unit already,
+/// only masses and volumes have a factor and a system.
+impl Validate for Unit {
+    fn validate(&self, _limits: &ValidationConfig, units: &UnitCatalogue) -> Vec<Violation> {
+        let mut violations = vec![];
+        let mut check = |valid: bool, field: String, message: String| {
+            if !valid {
+                violations.push(Violation::new(field, message));
+            }
+        };
+
+        let id = self.id.id();
+        check(!id.is_empty() && id.len() <= MAX_UNIT_ID_LENGTH && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+              "id".to_string(), format!("has to be 1 to {} letters, digits, '-' or '_'", MAX_UNIT_ID_LENGTH));
+
+        check(!self.names.is_empty(), "names".to_string(), "must contain the name in at least one language".to_string());
+        for (language, name) in &self.names {
+            let field = format!("names.{}", language);
+            check(!language.is_empty() && language.chars().all(|c| c.is_ascii_alphabetic() || c == '-'), field.clone(),
+                  format!("'{}' is no language tag like 'de'", language));
+            check(!name.trim().is_empty(), field, "must not be empty".to_string());
+        }
+
+        for (index, abbreviation) in self.abbreviations.iter().enumerate() {
+            let field = format!("abbreviations[{}]", index);
+            check(!abbreviation.trim().is_empty(), field.clone(), "must not be empty".to_string());
+            if let Some(other) = units.units().iter()
+                .filter(|unit| unit.id != self.id)
+                .find(|unit| unit.id.id() == abbreviation || unit.abbreviations.contains(abbreviation)) {
+                check(false, field, format!("'{}' already stands for {}", abbreviation, other.id));
+            }
+        }
+
+        match self.dimension {
+            Dimension::Mass | Dimension::Volume => check(
+                self.factor.is_some_and(|factor| factor.is_finite() && factor > 0.0), "factor".to_string(),
+                "a mass or volume needs the grams or milliliters of one unit".to_string()),
+            Dimension::Count | Dimension::Other => {
+                check(self.factor.is_none(), "factor".to_string(), "only masses and volumes have a factor".to_string());
+                check(self.system.is_none(), "system".to_string(), "only masses and volumes belong to a unit system".to_string());
+            }
+        }
+        violations
+    }
+
+    fn invalid(violations: Vec<Violation>) -> ApiError {
+        ApiError::invalid_unit(violations)
+    }
+}
+
+/// The rules of a gallery, which `step` refers to one of `instructions` of the recipe.
+pub fn gallery_violations(gallery: &[GalleryImage], instructions: usize, limits: &ValidationConfig) -> Vec<Violation> {
+    let mut violations = vec![];
+    let mut check = |valid: bool, field: String, message: String| {
+        if !valid {
+            violations.push(Violation::new(field, message));
+        }
+    };
+
+    check(gallery.len() <= limits.max_gallery_images, "gallery".to_string(),
+          format!("must not contain more than {} images", limits.max_gallery_images));
+    check(gallery.iter().filter(|image| image.cover).count() <= 1, "gallery".to_string(),
+          "must not have more than one cover".to_string());
+    for (index, image) in gallery.iter().enumerate() {
+        check(chars(&image.caption) <= limits.max_caption_length, format!("gallery[{}].caption", index),
+              format!("must not be longer than {} characters", limits.max_caption_length));
+        if let Some(step) = image.step {
+            check((step as usize) < instructions, format!("gallery[{}].step", index),
+                  format!("the recipe has {} steps, counted from 0", instructions));
+        }
+    }
+    violations
+}
+
+fn chars(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// The limits the app was configured with, the defaults when none were registered with `app_data`.
+pub fn limits(req: &HttpRequest) -> ValidationConfig {
+    req.app_data::<ValidationConfig>().cloned().unwrap_or_default()
+}
+
+/// The units recipes may use, only the builtin ones when the app has no store.
+pub async fn units(req: &HttpRequest) -> Result<UnitCatalogue, ApiError> {
+    match req.app_data::<web::Data<dyn RecipeStore>>() {
+        Some(store) => Ok(store.unit_catalogue().await?),
+        None => Ok(UnitCatalogue::builtin())
+    }
+}
+
+/// A JSON body that keeps every rule of `Validate`, otherwise the request is answered with 422.
+pub struct Validated<T>(pub T);
+
+impl<T> Validated<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Validate + DeserializeOwned + 'static> FromRequest for Validated<T> {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, ApiError>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let req = req.clone();
+        let body = JsonBody::<T>::from_request(&req, payload);
+        Box::pin(async move {
+            let value = body.await?.into_inner();
+            let violations = value.validate(&limits(&req), &units(&req).await?);
+            if violations.is_empty() {
+                Ok(Validated(value))
+            } else {
+                Err(T::invalid(violations))
+            }
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod validation_tests {
+    use bson::oid::ObjectId;
+    use chrono::Utc;
+
+    use crate::config::ValidationConfig;
+    use crate::model::difficulty::Difficulty;
+    use crate::model::gallery::GalleryImage;
+    use crate::model::ingredients::Ingredient;
+    use crate::model::measurement_unit::MeasurementUnit;
+    use crate::model::quantity::Quantity;
+    use crate::model::recipe::Recipe;
+    use crate::model::unit::{Unit, UnitCatalogue};
+    use crate::validation::{gallery_violations, Validate, Violation};
+
+    fn recipe() -> Recipe {
+        Recipe {
+            _id: ObjectId::new(),
+            cooking_time_in_minutes: 20,
+            created: Utc::now(),
+            last_modified: Utc::now(),
+            ingredients: vec![Ingredient::new("0", 200, "Spaghetti", MeasurementUnit::GRAMM)],
+            version: 1,
+            difficulty: Difficulty::Easy,
+            description: "".to_string(),
+            title: "Spaghetti".to_string(),
+            tags: vec!["pasta".to_string()],
+            image_base64: None,
+            instructions: vec!["Boil water".to_string()],
+            default_servings: 2,
+            gallery: vec![],
+        }
+    }
+
+    fn fields(violations: Vec<Violation>) -> Vec<String> {
+        violations.into_iter().map(|violation| violation.field.unwrap_or_default()).collect()
+    }
+
+    #[test]
+    fn valid_recipe_passes() {
+        assert_eq!(recipe().validate(&ValidationConfig::default(), &UnitCatalogue::builtin()), vec![]);
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let mut recipe = recipe();
+        recipe.title = "  ".to_string();
+        recipe.default_servings = 0;
+        recipe.tags = vec!["Pasta".to_string(), "pasta ".to_string()];
+        recipe.ingredients.push(Ingredient::new("0", -1, "Salt", MeasurementUnit::new("Bucket")));
+        recipe.instructions.push("".to_string());
+
+        assert_eq!(fields(recipe.validate(&ValidationConfig::default(), &UnitCatalogue::builtin())), vec![
+            "title", "defaultServings", "tags[1]", "ingredients[1].id",
+            "ingredients[1].amount", "ingredients[1].measurementUnit", "instructions[1]",
+        ]);
+    }
+
+    #[test]
+    fn checks_quantities() {
+        let mut recipe = recipe();
+        recipe.ingredients = vec![
+            Ingredient::new("0", Quantity::parse("3-2").unwrap(), "Eier", MeasurementUnit::PIECE),
+            Ingredient::new("1", Quantity::parse("-½").unwrap(), "Zitrone", MeasurementUnit::PIECE),
+            Ingredient::new("2", Quantity::parse("2–3").unwrap(), "Zwiebeln", MeasurementUnit::PIECE),
+            Ingredient::new("3", Quantity::ToTaste, "Salz", MeasurementUnit::PRISE),
+        ];
+        let violations = recipe.validate(&ValidationConfig::default(), &UnitCatalogue::builtin());
+        assert_eq!(violations[0].message, "min must not be greater than max");
+        assert_eq!(fields(violations), vec!["ingredients[0].amount", "ingredients[1].amount"]);
+    }
+
+    #[test]
+    fn respects_limits() {
+        let limits = ValidationConfig { max_tags: 1, max_title_length: 5, max_cooking_time: 10, ..Default::default() };
+        let mut recipe = recipe();
+        recipe.tags.push("quick".to_string());
+        assert_eq!(fields(recipe.validate(&limits, &UnitCatalogue::builtin())), vec!["title", "cookingTimeInMinutes", "tags"]);
+
+        let mut umlauts = self::recipe();
+        umlauts.title = "Käse".to_string();
+        umlauts.cooking_time_in_minutes = 10;
+        assert_eq!(umlauts.validate(&limits, &UnitCatalogue::builtin()), vec![]);
+    }
+
+    #[test]
+    fn checks_gallery() {
+        let image = |cover: bool, step: Option<u32>| GalleryImage {
+            id: "1".to_string(),
+            image: "sha256:ab".to_string(),
+            caption: "Teig".to_string(),
+            cover,
+            step,
+        };
+        let limits = ValidationConfig { max_caption_length: 3, ..Default::default() };
+        assert_eq!(gallery_violations(&[image(true, Some(0))], 1, &ValidationConfig::default()), vec![]);
+        assert_eq!(fields(gallery_violations(&[image(true, Some(1)), image(true, None)], 1, &limits)), vec![
+            "gallery", "gallery[0].caption", "gallery[0].step", "gallery[1].caption",
+        ]);
+    }
+
+    #[test]
+    fn prefixes_batch_violations() {
+        let mut invalid = recipe();
+        invalid.title = "".to_string();
+        let limits = ValidationConfig { max_batch_size: 1, ..Default::default() };
+        let violations = vec![recipe(), invalid].validate(&limits, &UnitCatalogue::builtin());
+        assert_eq!(violations[0].field, None);
+        assert_eq!(fields(violations)[1..], ["[1].title".to_string()]);
+    }
+
+    #[test]
+    fn checks_units() {
+        let unit = |json: serde_json::Value| -> Unit { serde_json::from_value(json).unwrap() };
+        let units = UnitCatalogue::builtin();
+        let limits = ValidationConfig::default();
+        let bucket = unit(serde_json::json!({
+            "id": "Eimer", "names": {"de": "Eimer"}, "abbreviations": ["Ei."], "dimension": "volume", "factor": 10000.0,
+        }));
+        assert_eq!(bucket.validate(&limits, &units), vec![]);
+        assert_eq!(bucket.validate(&limits, &UnitCatalogue::with_custom(vec![bucket.clone()])), vec![]);
+
+        let invalid = unit(serde_json::json!({
+            "id": "Großer Eimer", "names": {"": "Eimer", "de": " "}, "abbreviations": ["", "EL"],
+            "dimension": "volume", "factor": 0.0,
+        }));
+        assert_eq!(fields(invalid.validate(&limits, &units)), vec![
+            "id", "names.", "names.de", "abbreviations[0]", "abbreviations[1]", "factor",
+        ]);
+        let pinch = unit(serde_json::json!({
+            "id": "Hauch", "names": {}, "dimension": "other", "factor": 1.0, "system": "metric",
+        }));
+        assert_eq!(fields(pinch.validate(&limits, &units)), vec!["names", "factor", "system"]);
+    }
+}